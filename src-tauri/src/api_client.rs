@@ -1,6 +1,20 @@
-use crate::{MathSeekError, MathSeekResult, AppConfig, FormulaResult, AnalysisResult, InputType, ResultContent, DocumentContent};
-use reqwest::{Client, ClientBuilder};
-use serde::{Deserialize, Serialize};
+use crate::{
+    MathSeekError, MathSeekResult, AppConfig, ProviderConfig, FormulaResult, AnalysisResult, FormulaVariable, InputType,
+    ResultContent, DocumentContent, CancellationToken, MarkdownFormulaFormat, PromptTemplates, ImageProcessor, PayloadBudget,
+    PayloadFormat, NetworkConfig
+};
+use crate::recognition_backend::{self, PartialCallback, RecognitionBackend};
+use crate::circuit_breaker;
+use crate::cassette::{Cassette, CassetteConfig, CassetteMode};
+use crate::rate_limiter::{self, RateLimiter, RateLimits, RatePermit};
+use crate::usage::{self, PriceTable, TokenUsage, UsageLedger};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::{sleep, timeout};
 use base64::prelude::*;
@@ -8,21 +22,89 @@ use base64::prelude::*;
 /// Configuration for API client
 #[derive(Debug, Clone)]
 pub struct ApiConfig {
+    pub backend: String,
     pub endpoint: String,
     pub api_key: String,
+    pub app_id: String,
+    pub model: String,
     pub timeout_seconds: u64,
     pub max_retries: u32,
+    /// Base delay of the exponential backoff
     pub retry_delay_ms: u64,
+    /// Upper bound for a single backoff delay, including `Retry-After`
+    pub max_retry_delay_ms: u64,
+    /// Consecutive transient failures that open an endpoint's circuit breaker
+    pub circuit_failure_threshold: u32,
+    /// How long an open circuit breaker fails fast before allowing a trial request
+    pub circuit_cooldown_secs: u64,
+    /// Requests per minute sent to the endpoint, shared by all clients; 0 for no limit
+    pub requests_per_minute: u32,
+    /// Requests in flight to the endpoint at once, shared by all clients; 0 for no limit
+    pub max_concurrent_requests: u32,
+    /// Prices used to estimate the cost of each call
+    pub prices: PriceTable,
+    /// Ledger file that successful calls are added to
+    pub usage_ledger: Option<PathBuf>,
+    /// Record traffic to, or replay it from, a cassette file
+    pub cassette: Option<CassetteConfig>,
+    /// Sampling temperature for chat model recognition; 0 for deterministic output
+    pub temperature: f32,
+    /// System prompts for chat model recognition
+    pub prompt_templates: PromptTemplates,
+    /// Formula delimiters requested in streamed Markdown
+    pub markdown_formula_format: MarkdownFormulaFormat,
+    /// Upload limit for images in bytes of base64; 0 for the backend's own limit
+    pub max_payload_bytes: u64,
+    /// Proxy, extra root certificates and client certificate
+    pub network: NetworkConfig,
+}
+
+impl ApiConfig {
+    /// Configuration of a fallback provider, sharing the primary's limits and prices
+    pub fn for_fallback(app_config: &AppConfig, provider: &ProviderConfig) -> Self {
+        Self {
+            backend: provider.backend.clone(),
+            endpoint: provider.api_endpoint.clone(),
+            api_key: provider.api_key.clone(),
+            app_id: provider.app_id.clone(),
+            model: provider.model.clone(),
+            max_payload_bytes: provider.max_payload_bytes,
+            ..Self::from(app_config)
+        }
+    }
+
+    fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            requests_per_minute: self.requests_per_minute,
+            max_concurrent_requests: self.max_concurrent_requests,
+        }
+    }
 }
 
 impl Default for ApiConfig {
     fn default() -> Self {
         Self {
+            backend: recognition_backend::DEFAULT_BACKEND.to_string(),
             endpoint: String::new(),
             api_key: String::new(),
+            app_id: String::new(),
+            model: String::new(),
             timeout_seconds: 30,
             max_retries: 3,
             retry_delay_ms: 1000,
+            max_retry_delay_ms: 30_000,
+            circuit_failure_threshold: 5,
+            circuit_cooldown_secs: 30,
+            requests_per_minute: 0,
+            max_concurrent_requests: 0,
+            prices: usage::default_price_table(),
+            usage_ledger: None,
+            cassette: None,
+            temperature: 0.0,
+            prompt_templates: PromptTemplates::default(),
+            markdown_formula_format: MarkdownFormulaFormat::default(),
+            max_payload_bytes: 0,
+            network: NetworkConfig::default(),
         }
     }
 }
@@ -30,11 +112,27 @@ impl Default for ApiConfig {
 impl From<&AppConfig> for ApiConfig {
     fn from(app_config: &AppConfig) -> Self {
         Self {
+            backend: app_config.backend.clone(),
             endpoint: app_config.api_endpoint.clone(),
             api_key: app_config.api_key.clone(),
+            app_id: app_config.app_id.clone(),
+            model: app_config.model.clone(),
             timeout_seconds: 30,
             max_retries: 3,
             retry_delay_ms: 1000,
+            max_retry_delay_ms: 30_000,
+            circuit_failure_threshold: 5,
+            circuit_cooldown_secs: 30,
+            requests_per_minute: app_config.requests_per_minute,
+            max_concurrent_requests: app_config.max_concurrent_requests,
+            prices: app_config.model_prices.clone(),
+            usage_ledger: UsageLedger::default_path().ok(),
+            cassette: CassetteConfig::from_env(),
+            temperature: 0.0,
+            prompt_templates: app_config.prompt_templates.clone(),
+            markdown_formula_format: app_config.markdown_formula_format.clone(),
+            max_payload_bytes: app_config.max_payload_bytes,
+            network: app_config.network.clone(),
         }
     }
 }
@@ -43,6 +141,8 @@ impl From<&AppConfig> for ApiConfig {
 #[derive(Debug, Serialize)]
 struct RecognitionRequest {
     image_data: String,
+    /// MIME type of `image_data`, which may have been re-encoded to fit the payload budget
+    mime_type: String,
     input_type: String,
     options: RecognitionOptions,
 }
@@ -54,13 +154,24 @@ struct RecognitionOptions {
 }
 
 /// Response from formula recognition API
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
 struct RecognitionResponse {
     success: bool,
     latex: Option<String>,
     confidence: Option<f32>,
     content: Option<serde_json::Value>,
     error: Option<String>,
+    #[serde(default)]
+    token_usage: Option<NativeUsage>,
+}
+
+/// Token counts optionally reported by MathSeek-protocol servers
+#[derive(Debug, Default, Deserialize)]
+struct NativeUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
 }
 
 /// Request payload for formula analysis
@@ -71,32 +182,84 @@ struct AnalysisRequest {
 }
 
 /// Response from formula analysis API
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
 struct AnalysisResponse {
     success: bool,
     formula_type: Option<String>,
     description: Option<String>,
     usage: Option<String>,
     examples: Option<Vec<String>>,
+    variables: Option<Vec<FormulaVariable>>,
+    domain: Option<String>,
+    related_formulas: Option<Vec<String>>,
     error: Option<String>,
+    #[serde(default)]
+    token_usage: Option<NativeUsage>,
 }
 
 /// HTTP client for interacting with large language model APIs
+///
+/// The wire protocol is delegated to a [`RecognitionBackend`] selected by `ApiConfig::backend`.
+#[derive(Clone)]
 pub struct ApiClient {
     client: Client,
     config: ApiConfig,
+    backend: Arc<dyn RecognitionBackend>,
+    cancellation: Option<CancellationToken>,
+    cassette: Option<Arc<Cassette>>,
+    rate_limiter: Arc<RateLimiter>,
 }
 
 impl ApiClient {
     /// Create a new API client with the given configuration
     pub fn new(config: ApiConfig) -> MathSeekResult<Self> {
-        let client = ClientBuilder::new()
-            .timeout(Duration::from_secs(config.timeout_seconds))
-            .user_agent("MathSeek/1.0")
+        let client = Self::build_http_client(&config)?;
+        let backend = recognition_backend::create_backend(&config.backend)?;
+
+        let cassette = match &config.cassette {
+            Some(cassette_config) => Some(Arc::new(Cassette::open(cassette_config.clone())?)),
+            None => None,
+        };
+
+        let rate_limiter = rate_limiter::for_endpoint(&config.endpoint, config.rate_limits());
+
+        Ok(Self { client, config, backend, cancellation: None, cassette, rate_limiter })
+    }
+
+    /// Build the underlying HTTP client
+    ///
+    /// Streamed responses may take longer than `timeout_seconds` in total, so
+    /// the client only bounds connecting and stalled reads; `send_once` bounds
+    /// the wait for the response headers, and non-streaming calls bound
+    /// reading the body.
+    fn build_http_client(config: &ApiConfig) -> MathSeekResult<Client> {
+        let timeout = Duration::from_secs(config.timeout_seconds);
+
+        let builder = ClientBuilder::new()
+            .connect_timeout(timeout)
+            .read_timeout(timeout)
+            .user_agent("MathSeek/1.0");
+
+        config.network.apply(builder)?
             .build()
-            .map_err(|e| MathSeekError::NetworkError(format!("Failed to create HTTP client: {}", e)))?;
+            .map_err(|e| MathSeekError::NetworkError(format!("Failed to create HTTP client: {}", e)))
+    }
+
+    /// Abort in-flight requests and retry sleeps once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// System prompt sent to chat models when recognizing `input_type`
+    pub fn recognition_prompt(&self, input_type: &InputType, stream: bool) -> MathSeekResult<String> {
+        self.config.prompt_templates.render(input_type, &self.config.markdown_formula_format, stream)
+    }
 
-        Ok(Self { client, config })
+    /// Sample chat model recognitions at `temperature` instead of the configured one
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.config.temperature = temperature;
+        self
     }
 
     /// Create API client from app configuration
@@ -107,115 +270,462 @@ impl ApiClient {
 
     /// Test API connection and authentication
     pub async fn test_connection(&self) -> MathSeekResult<bool> {
-        if self.config.endpoint.is_empty() || self.config.api_key.is_empty() {
+        if self.config.endpoint.is_empty() || (self.config.api_key.is_empty() && self.backend.requires_api_key()) {
             return Err(MathSeekError::ConfigError("API endpoint or key not configured".to_string()));
         }
 
-        let test_url = format!("{}/health", self.config.endpoint);
-        
-        let response = self.client
-            .get(&test_url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .send()
-            .await?;
-
-        Ok(response.status().is_success())
+        self.backend.health(self).await
     }
 
     /// Recognize mathematical formulas from image data
+    ///
+    /// The image is re-encoded first if it does not fit the provider's payload budget.
+    /// Bytes that are not a decodable image are rejected with `ImageError`
+    /// before anything is sent, rather than being uploaded for the provider to refuse.
     pub async fn recognize_image(&self, image_data: &[u8], input_type: InputType) -> MathSeekResult<FormulaResult> {
-        let base64_image = base64::prelude::BASE64_STANDARD.encode(image_data);
-        
-        let request = RecognitionRequest {
-            image_data: base64_image,
-            input_type: input_type.clone().into(),
-            options: RecognitionOptions {
-                output_format: "latex".to_string(),
-                confidence_threshold: 0.5,
-            },
-        };
+        let (image_data, payload) = ImageProcessor::fit_payload(image_data, &self.payload_budget())?;
+        let mut result = self.backend.recognize(self, &image_data, payload.format, input_type).await?;
+        result.payload = Some(payload);
+        Ok(result)
+    }
 
-        let response = self.make_request_with_retry("/recognize", &request).await?;
-        self.parse_recognition_response(response, input_type).await
+    /// Recognize image data, reporting the accumulated output while it streams in
+    pub async fn recognize_image_streaming(
+        &self,
+        image_data: &[u8],
+        input_type: InputType,
+        on_partial: PartialCallback<'_>,
+    ) -> MathSeekResult<FormulaResult> {
+        let (image_data, payload) = ImageProcessor::fit_payload(image_data, &self.payload_budget())?;
+        let mut result = self.backend.recognize_streaming(self, &image_data, payload.format, input_type, on_partial).await?;
+        result.payload = Some(payload);
+        Ok(result)
+    }
+
+    /// Upload limit of the provider, with the configured override applied
+    pub fn payload_budget(&self) -> PayloadBudget {
+        match self.config.max_payload_bytes {
+            0 => self.backend.payload_budget(),
+            max_encoded_bytes => PayloadBudget { max_encoded_bytes, ..self.backend.payload_budget() },
+        }
     }
 
     /// Analyze a mathematical formula to get type and description
     pub async fn analyze_formula(&self, formula: &str) -> MathSeekResult<AnalysisResult> {
-        let request = AnalysisRequest {
-            formula: formula.to_string(),
-            analysis_type: "comprehensive".to_string(),
+        self.backend.analyze(self, formula).await
+    }
+
+    /// List the models available on the provider, if the backend supports it
+    pub async fn list_models(&self) -> MathSeekResult<Vec<String>> {
+        self.backend.list_models(self).await
+    }
+
+    /// Name of the backend this client speaks to
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Whether result confidences come from the provider, see `RecognitionBackend::reports_confidence`
+    pub fn reports_confidence(&self) -> bool {
+        self.backend.reports_confidence()
+    }
+
+    /// Send an authenticated GET request for `{endpoint}{path}`
+    ///
+    /// GET responses are never streamed, so the timeout covers reading the body too.
+    pub(crate) async fn send_get(&self, path: &str) -> MathSeekResult<Response> {
+        let request = self.authorize(self.client.get(format!("{}{}", self.config.endpoint, path)))
+            .timeout(Duration::from_secs(self.config.timeout_seconds));
+        self.through_cassette("GET", path, &serde_json::Value::Null, async { Ok(request.send().await?) }).await
+    }
+
+    /// Send a request, or serve it from the cassette in replay mode
+    ///
+    /// In record mode the live response is saved before it is returned.
+    async fn through_cassette<T: Serialize>(
+        &self,
+        method: &str,
+        path: &str,
+        payload: &T,
+        send: impl Future<Output = MathSeekResult<Response>>,
+    ) -> MathSeekResult<Response> {
+        let Some(cassette) = &self.cassette else {
+            return send.await;
+        };
+
+        let request = serde_json::to_value(payload)?;
+        match cassette.mode() {
+            CassetteMode::Replay => cassette.replay(method, path, &request),
+            CassetteMode::Record => cassette.record(method, path, &request, send.await?).await,
+        }
+    }
+
+    /// Attach the backend's authentication headers to a request
+    fn authorize(&self, mut request: RequestBuilder) -> RequestBuilder {
+        for (name, value) in self.backend.auth_headers(&self.config) {
+            request = request.header(name, value);
+        }
+        request
+    }
+
+    /// Current API configuration
+    pub(crate) fn api_config(&self) -> &ApiConfig {
+        &self.config
+    }
+
+    /// Usage of one call to `model`, priced from the configured price table
+    pub(crate) fn token_usage(&self, model: &str, input_tokens: u64, output_tokens: u64) -> TokenUsage {
+        TokenUsage::estimate(&self.config.prices, self.backend.name(), model, input_tokens, output_tokens)
+    }
+
+    /// Add a provider call to the usage ledger and hand its usage back
+    ///
+    /// Backends call this as soon as the provider has answered, so that calls
+    /// whose answer then fails to parse are still accounted. Replayed calls
+    /// cost nothing and are skipped. A ledger that cannot be written must not
+    /// fail a recognition; the failure is returned in `ledger_error` instead.
+    pub(crate) fn record_usage(&self, mut token_usage: TokenUsage) -> TokenUsage {
+        let replaying = self.cassette.as_ref().is_some_and(|cassette| cassette.mode() == CassetteMode::Replay);
+        if let (Some(path), false) = (&self.config.usage_ledger, replaying) {
+            if let Err(e) = UsageLedger::new(path).record(&token_usage, usage::unix_now()) {
+                token_usage.ledger_error = Some(format!("Failed to record usage in {}: {}", path.display(), e));
+            }
+        }
+        token_usage
+    }
+
+    /// POST a JSON payload to `{endpoint}{path}` with retry logic
+    ///
+    /// The response body is decoded into the endpoint's own response type `R`.
+    pub(crate) async fn make_request_with_retry<T: Serialize, R: DeserializeOwned>(&self, path: &str, payload: &T) -> MathSeekResult<R> {
+        // The permit keeps the concurrency slot until the body has been read
+        let (response, _permit) = self.send_with_retry(path, payload).await?;
+
+        // The client only bounds each read, so a server trickling bytes needs an overall limit
+        let seconds = self.config.timeout_seconds;
+        let body = async {
+            timeout(Duration::from_secs(seconds), response.json())
+                .await
+                .map_err(|_| MathSeekError::Timeout(format!("Response body not complete within {}s", seconds)))
+        };
+        self.cancellable(body)
+            .await?
+            .map_err(|e| {
+                // The provider answered, so the call is billed even without token counts
+                let token_usage = self.record_usage(self.token_usage(&self.config.model, 0, 0));
+                let message = format!("Failed to parse response: {}", e);
+                MathSeekError::SerializationError(match token_usage.ledger_error {
+                    Some(ledger_error) => format!("{}; {}", message, ledger_error),
+                    None => message,
+                })
+            })
+    }
+
+    /// POST a JSON payload and hand every line of the streamed response to `on_line`
+    ///
+    /// Serves both SSE (`data: ...` lines) and newline-delimited JSON. Only
+    /// opening the stream is retried; once data has arrived, errors are final.
+    pub(crate) async fn stream_lines<T: Serialize>(
+        &self,
+        path: &str,
+        payload: &T,
+        mut on_line: impl FnMut(&str) -> MathSeekResult<()> + Send,
+    ) -> MathSeekResult<()> {
+        let (mut response, _permit) = self.send_with_retry(path, payload).await?;
+        let mut buffer = Vec::new();
+
+        while let Some(chunk) = self.cancellable(async { Ok(response.chunk().await?) }).await? {
+            buffer.extend_from_slice(&chunk);
+            for line in Self::drain_lines(&mut buffer) {
+                on_line(&line)?;
+            }
+        }
+
+        // The last line may come without a trailing newline
+        buffer.push(b'\n');
+        for line in Self::drain_lines(&mut buffer) {
+            on_line(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Remove all complete, non-empty lines from the front of `buffer`
+    ///
+    /// Works on bytes so that a UTF-8 character split across chunks stays intact.
+    fn drain_lines(buffer: &mut Vec<u8>) -> Vec<String> {
+        let Some(last_newline) = buffer.iter().rposition(|&byte| byte == b'\n') else {
+            return Vec::new();
         };
 
-        let response = self.make_request_with_retry("/analyze", &request).await?;
-        self.parse_analysis_response(response).await
+        let complete: Vec<u8> = buffer.drain(..=last_newline).collect();
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
     }
 
-    /// Make HTTP request with retry logic
-    async fn make_request_with_retry<T: Serialize>(&self, endpoint: &str, payload: &T) -> MathSeekResult<RecognitionResponse> {
-        let url = format!("{}{}", self.config.endpoint, endpoint);
-        let mut last_error = None;
+    /// POST a JSON payload until a success response arrives
+    ///
+    /// Transient failures are retried with capped exponential backoff and full
+    /// jitter, and every attempt goes through the endpoint's circuit breaker.
+    /// Each attempt first waits for the endpoint's rate limiter; the returned
+    /// permit holds the concurrency slot until the caller is done with the body.
+    async fn send_with_retry<T: Serialize>(&self, path: &str, payload: &T) -> MathSeekResult<(Response, RatePermit)> {
+        let url = format!("{}{}", self.config.endpoint, path);
+        let mut timed_out = false;
 
         for attempt in 0..=self.config.max_retries {
-            if attempt > 0 {
-                sleep(Duration::from_millis(self.config.retry_delay_ms * attempt as u64)).await;
+            // Queue first so a breaker that opened while waiting is still honoured
+            let permit = self.cancellable(async { Ok(self.rate_limiter.acquire().await) }).await?;
+            circuit_breaker::check(&url, self.circuit_cooldown())?;
+
+            let error = match self.cancellable(self.send_once(&url, path, payload)).await {
+                Ok(response) => {
+                    circuit_breaker::record_success(&url);
+                    return Ok((response, permit));
+                }
+                Err(MathSeekError::Cancelled) => return Err(MathSeekError::Cancelled),
+                Err(e) => e,
+            };
+            // Backoff sleeps must not hold a concurrency slot
+            drop(permit);
+
+            // Only transient failures (network, timeouts, 429, 5xx) count against
+            // the endpoint; a 4xx answer still proves the server is up
+            if error.is_retryable() {
+                circuit_breaker::record_failure(&url, self.config.circuit_failure_threshold);
+            } else {
+                circuit_breaker::record_success(&url);
+                return Err(error);
             }
 
-            match self.make_single_request(&url, payload).await {
-                Ok(response) => return Ok(response),
-                Err(e) => {
-                    last_error = Some(e);
-                    
-                    // Don't retry on authentication or client errors
-                    if let Some(MathSeekError::ApiError(ref msg)) = last_error {
-                        if msg.contains("401") || msg.contains("403") || msg.contains("400") {
-                            break;
-                        }
-                    }
+            // A provider that timed out twice in a row is unlikely to recover
+            // within this call, so a second timeout is not retried
+            if matches!(error, MathSeekError::Timeout(_)) {
+                if timed_out {
+                    return Err(error);
                 }
+                timed_out = true;
             }
+
+            if attempt == self.config.max_retries {
+                return Err(error);
+            }
+
+            match Self::backoff_delay(&self.config, attempt, &error, fastrand::f64()) {
+                Some(delay) => self.cancellable(async { sleep(delay).await; Ok(()) }).await?,
+                None => return Err(error),
+            }
+        }
+
+        Err(MathSeekError::NetworkError("Max retries exceeded".to_string()))
+    }
+
+    /// Run `future` unless the client's job is cancelled first
+    ///
+    /// Dropping the losing future aborts an in-flight reqwest request.
+    async fn cancellable<T>(&self, future: impl Future<Output = MathSeekResult<T>>) -> MathSeekResult<T> {
+        match &self.cancellation {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(MathSeekError::Cancelled),
+                result = future => result,
+            },
+            None => future.await,
+        }
+    }
+
+    /// Delay before retry number `attempt + 1`, or `None` to give up
+    ///
+    /// 429 and 503 responses carrying `Retry-After` wait exactly as long as the
+    /// server asked, unless that exceeds `max_retry_delay_ms`. Everything else
+    /// uses full jitter: a uniform delay in `[0, min(cap, base * 2^attempt)]`,
+    /// with `random` in `[0, 1)`.
+    fn backoff_delay(config: &ApiConfig, attempt: u32, error: &MathSeekError, random: f64) -> Option<Duration> {
+        if let MathSeekError::HttpError { status: 429 | 503, retry_after_secs: Some(secs), .. } = error {
+            let delay = Duration::from_secs(*secs);
+            return (delay <= Duration::from_millis(config.max_retry_delay_ms)).then_some(delay);
         }
 
-        Err(last_error.unwrap_or_else(|| MathSeekError::NetworkError("Max retries exceeded".to_string())))
+        let ceiling = config.retry_delay_ms
+            .saturating_mul(1u64 << attempt.min(32))
+            .min(config.max_retry_delay_ms);
+        Some(Duration::from_millis((ceiling as f64 * random) as u64))
+    }
+
+    fn circuit_cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.circuit_cooldown_secs)
     }
 
-    /// Make a single HTTP request
-    async fn make_single_request<T: Serialize>(&self, url: &str, payload: &T) -> MathSeekResult<RecognitionResponse> {
-        let request_future = self.client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
+    /// Make a single HTTP request, turning non-success statuses into errors
+    async fn send_once<T: Serialize>(&self, url: &str, path: &str, payload: &T) -> MathSeekResult<Response> {
+        let request_future = self
+            .authorize(self.client.post(url))
             .header("Content-Type", "application/json")
             .json(payload)
             .send();
 
-        let response = timeout(Duration::from_secs(self.config.timeout_seconds), request_future)
-            .await
-            .map_err(|_| MathSeekError::NetworkError("Request timeout".to_string()))?
-            .map_err(|e| MathSeekError::NetworkError(format!("Request failed: {}", e)))?;
+        let send = async {
+            timeout(Duration::from_secs(self.config.timeout_seconds), request_future)
+                .await
+                .map_err(|_| MathSeekError::Timeout(format!("No response within {}s", self.config.timeout_seconds)))?
+                .map_err(|e| match MathSeekError::from(e) {
+                    MathSeekError::NetworkError(msg) => MathSeekError::NetworkError(format!("Request failed: {}", msg)),
+                    other => other,
+                })
+        };
+        let response = self.through_cassette("POST", path, payload, send).await?;
 
         if !response.status().is_success() {
-            return Err(MathSeekError::ApiError(format!(
-                "API request failed with status: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            )));
+            return Err(self.error_from_response(response).await);
         }
 
-        let api_response: RecognitionResponse = response
-            .json()
-            .await
-            .map_err(|e| MathSeekError::SerializationError(format!("Failed to parse response: {}", e)))?;
+        Ok(response)
+    }
 
-        if !api_response.success {
-            return Err(MathSeekError::ApiError(
-                api_response.error.unwrap_or_else(|| "Unknown API error".to_string())
-            ));
+    /// Convert a non-success response into a typed error via the backend
+    pub(crate) async fn error_from_response(&self, response: Response) -> MathSeekError {
+        let status = response.status();
+        let retry_after_secs = Self::retry_after_secs(response.headers());
+        let body = response.text().await.unwrap_or_default();
+
+        self.backend.error_from_response(status, retry_after_secs, &body)
+    }
+
+    /// Read the `Retry-After` header in its delay-seconds form
+    ///
+    /// The HTTP-date form is rare for API gateways and is ignored.
+    fn retry_after_secs(headers: &HeaderMap) -> Option<u64> {
+        headers
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+    }
+
+    /// Queue depth and in-flight requests of this client's endpoint
+    pub fn rate_limit_status(&self) -> rate_limiter::RateLimitStatus {
+        self.rate_limiter.status(&self.config.endpoint)
+    }
+
+    /// Update API configuration
+    pub fn update_config(&mut self, config: ApiConfig) -> MathSeekResult<()> {
+        // Validate new configuration
+        if config.endpoint.is_empty() {
+            return Err(MathSeekError::ConfigError("API endpoint cannot be empty".to_string()));
+        }
+        
+        let backend = if config.backend != self.config.backend {
+            recognition_backend::create_backend(&config.backend)?
+        } else {
+            self.backend.clone()
+        };
+
+        if config.api_key.is_empty() && backend.requires_api_key() {
+            return Err(MathSeekError::ConfigError("API key cannot be empty".to_string()));
         }
 
-        Ok(api_response)
+        // Create new client with updated timeout
+        self.client = Self::build_http_client(&config)?;
+
+        self.rate_limiter = rate_limiter::for_endpoint(&config.endpoint, config.rate_limits());
+        self.backend = backend;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Get current API configuration (without sensitive data)
+    pub fn get_config_info(&self) -> serde_json::Value {
+        serde_json::json!({
+            "backend": self.backend.name(),
+            "endpoint": self.config.endpoint,
+            "model": self.config.model,
+            "timeout_seconds": self.config.timeout_seconds,
+            "max_retries": self.config.max_retries,
+            "retry_delay_ms": self.config.retry_delay_ms,
+            "max_retry_delay_ms": self.config.max_retry_delay_ms,
+            "has_api_key": !self.config.api_key.is_empty(),
+            "cassette": self.config.cassette.as_ref().map(|cassette| serde_json::json!({
+                "mode": cassette.mode,
+                "path": cassette.path,
+            })),
+            "circuit_breakers": circuit_breaker::statuses(&self.config.endpoint, self.circuit_cooldown()),
+            "rate_limit": self.rate_limit_status()
+        })
+    }
+}
+
+/// Backend for the MathSeek `/recognize` + `/analyze` JSON protocol
+pub struct NativeBackend;
+
+#[async_trait]
+impl RecognitionBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        recognition_backend::DEFAULT_BACKEND
+    }
+
+    async fn recognize(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        format: PayloadFormat,
+        input_type: InputType,
+    ) -> MathSeekResult<FormulaResult> {
+        let base64_image = base64::prelude::BASE64_STANDARD.encode(image_data);
+        
+        let request = RecognitionRequest {
+            image_data: base64_image,
+            mime_type: format.mime_type().to_string(),
+            input_type: input_type.clone().into(),
+            options: RecognitionOptions {
+                output_format: "latex".to_string(),
+                confidence_threshold: 0.5,
+            },
+        };
+
+        let response: RecognitionResponse = client.make_request_with_retry("/recognize", &request).await?;
+        let token_usage = client.record_usage(Self::token_usage(client, response.token_usage.as_ref()));
+        let mut result = Self::parse_recognition_response(response, input_type)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn analyze(&self, client: &ApiClient, formula: &str) -> MathSeekResult<AnalysisResult> {
+        let request = AnalysisRequest {
+            formula: formula.to_string(),
+            analysis_type: "comprehensive".to_string(),
+        };
+
+        let response: AnalysisResponse = client.make_request_with_retry("/analyze", &request).await?;
+        let token_usage = client.record_usage(Self::token_usage(client, response.token_usage.as_ref()));
+        let mut result = Self::parse_analysis_response(response)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn health(&self, client: &ApiClient) -> MathSeekResult<bool> {
+        let response = client.send_get("/health").await?;
+
+        Ok(response.status().is_success())
+    }
+}
+
+impl NativeBackend {
+    /// Usage of a call; servers that report no token counts still count as a request
+    fn token_usage(client: &ApiClient, usage: Option<&NativeUsage>) -> TokenUsage {
+        let (input_tokens, output_tokens) = usage.map_or((0, 0), |usage| (usage.input_tokens, usage.output_tokens));
+        client.token_usage(&client.api_config().model, input_tokens, output_tokens)
     }
 
     /// Parse recognition API response into FormulaResult
-    async fn parse_recognition_response(&self, response: RecognitionResponse, input_type: InputType) -> MathSeekResult<FormulaResult> {
+    fn parse_recognition_response(response: RecognitionResponse, input_type: InputType) -> MathSeekResult<FormulaResult> {
+        if !response.success {
+            return Err(MathSeekError::ApiError(
+                response.error.unwrap_or_else(|| "Unknown API error".to_string())
+            ));
+        }
+
         let latex = response.latex.ok_or_else(|| {
             MathSeekError::ApiError("No LaTeX content in response".to_string())
         })?;
@@ -254,6 +764,10 @@ impl ApiClient {
                 .as_secs(),
             input_type,
             content,
+            token_usage: None,
+            provenance: None,
+            alternatives: Vec::new(),
+            payload: None,
         };
 
         result.validate()?;
@@ -261,12 +775,7 @@ impl ApiClient {
     }
 
     /// Parse analysis API response into AnalysisResult
-    async fn parse_analysis_response(&self, response: RecognitionResponse) -> MathSeekResult<AnalysisResult> {
-        // For analysis, we need to parse the response differently
-        // This is a simplified implementation - in practice, you'd have a separate AnalysisResponse type
-        let analysis_response: AnalysisResponse = serde_json::from_str(&serde_json::to_string(&response)?)
-            .map_err(|e| MathSeekError::SerializationError(format!("Failed to parse analysis response: {}", e)))?;
-
+    fn parse_analysis_response(analysis_response: AnalysisResponse) -> MathSeekResult<AnalysisResult> {
         if !analysis_response.success {
             return Err(MathSeekError::ApiError(
                 analysis_response.error.unwrap_or_else(|| "Analysis failed".to_string())
@@ -278,39 +787,10 @@ impl ApiClient {
             description: analysis_response.description.unwrap_or_else(|| "No description available".to_string()),
             usage: analysis_response.usage.unwrap_or_else(|| "No usage information available".to_string()),
             examples: analysis_response.examples.unwrap_or_default(),
-        })
-    }
-
-    /// Update API configuration
-    pub fn update_config(&mut self, config: ApiConfig) -> MathSeekResult<()> {
-        // Validate new configuration
-        if config.endpoint.is_empty() {
-            return Err(MathSeekError::ConfigError("API endpoint cannot be empty".to_string()));
-        }
-        
-        if config.api_key.is_empty() {
-            return Err(MathSeekError::ConfigError("API key cannot be empty".to_string()));
-        }
-
-        // Create new client with updated timeout
-        self.client = ClientBuilder::new()
-            .timeout(Duration::from_secs(config.timeout_seconds))
-            .user_agent("MathSeek/1.0")
-            .build()
-            .map_err(|e| MathSeekError::NetworkError(format!("Failed to create HTTP client: {}", e)))?;
-
-        self.config = config;
-        Ok(())
-    }
-
-    /// Get current API configuration (without sensitive data)
-    pub fn get_config_info(&self) -> serde_json::Value {
-        serde_json::json!({
-            "endpoint": self.config.endpoint,
-            "timeout_seconds": self.config.timeout_seconds,
-            "max_retries": self.config.max_retries,
-            "retry_delay_ms": self.config.retry_delay_ms,
-            "has_api_key": !self.config.api_key.is_empty()
+            variables: analysis_response.variables.unwrap_or_default(),
+            domain: analysis_response.domain,
+            related_formulas: analysis_response.related_formulas.unwrap_or_default(),
+            token_usage: None,
         })
     }
 }
@@ -318,6 +798,18 @@ impl ApiClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock_server::{MockResponse, MockScript, MockServer};
+
+    const TEST_IMAGE: &[u8] = include_bytes!("../tests/cassettes/plus_sign.png");
+
+    #[test]
+    fn test_payload_budget_override() {
+        let client = ApiClient::new(ApiConfig { backend: "openai".to_string(), ..Default::default() }).unwrap();
+        assert_eq!(client.payload_budget().max_encoded_bytes, 20 * 1024 * 1024);
+
+        let client = ApiClient::new(ApiConfig { max_payload_bytes: 1024, ..Default::default() }).unwrap();
+        assert_eq!(client.payload_budget(), PayloadBudget { max_encoded_bytes: 1024, ..PayloadBudget::default() });
+    }
 
     #[test]
     fn test_api_config_creation() {
@@ -332,10 +824,14 @@ mod tests {
         let mut app_config = AppConfig::default();
         app_config.api_endpoint = "https://api.example.com".to_string();
         app_config.api_key = "test-key".to_string();
+        app_config.requests_per_minute = 30;
+        app_config.max_concurrent_requests = 2;
 
         let api_config = ApiConfig::from(&app_config);
         assert_eq!(api_config.endpoint, "https://api.example.com");
         assert_eq!(api_config.api_key, "test-key");
+        assert_eq!(api_config.requests_per_minute, 30);
+        assert_eq!(api_config.max_concurrent_requests, 2);
     }
 
     #[tokio::test]
@@ -354,6 +850,7 @@ mod tests {
     fn test_recognition_request_serialization() {
         let request = RecognitionRequest {
             image_data: "base64data".to_string(),
+            mime_type: "image/png".to_string(),
             input_type: "SingleFormula".to_string(),
             options: RecognitionOptions {
                 output_format: "latex".to_string(),
@@ -375,4 +872,471 @@ mod tests {
         let json = serde_json::to_string(&request);
         assert!(json.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_analysis_response_keeps_fields() {
+        let response: AnalysisResponse = serde_json::from_str(r#"{
+            "success": true,
+            "formula_type": "Energy Equation",
+            "description": "Mass-energy equivalence",
+            "usage": "Relativity",
+            "examples": ["Nuclear reactions"],
+            "variables": [{"symbol": "E", "description": "Energy", "unit": "J"}],
+            "domain": "Physics",
+            "related_formulas": ["E^2 = (pc)^2 + (mc^2)^2"]
+        }"#).unwrap();
+
+        let analysis = NativeBackend::parse_analysis_response(response).unwrap();
+        assert_eq!(analysis.formula_type, "Energy Equation");
+        assert_eq!(analysis.description, "Mass-energy equivalence");
+        assert_eq!(analysis.examples, vec!["Nuclear reactions".to_string()]);
+        assert_eq!(analysis.variables[0].symbol, "E");
+        assert_eq!(analysis.variables[0].unit.as_deref(), Some("J"));
+        assert_eq!(analysis.domain.as_deref(), Some("Physics"));
+        assert_eq!(analysis.related_formulas.len(), 1);
+    }
+
+    #[test]
+    fn test_unsuccessful_recognition_response() {
+        let response: RecognitionResponse = serde_json::from_str(
+            r#"{"success": false, "error": "Image unreadable"}"#
+        ).unwrap();
+
+        let result = NativeBackend::parse_recognition_response(response, InputType::SingleFormula);
+        assert!(matches!(result, Err(MathSeekError::ApiError(ref msg)) if msg == "Image unreadable"));
+    }
+
+    #[test]
+    fn test_retry_after_parsing() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(ApiClient::retry_after_secs(&headers), None);
+
+        headers.insert(RETRY_AFTER, "12".parse().unwrap());
+        assert_eq!(ApiClient::retry_after_secs(&headers), Some(12));
+
+        headers.insert(RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(ApiClient::retry_after_secs(&headers), None);
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let config = ApiConfig {
+            retry_delay_ms: 1000,
+            max_retry_delay_ms: 5000,
+            ..Default::default()
+        };
+        let network = MathSeekError::NetworkError("reset".to_string());
+
+        // Full jitter spans [0, base * 2^attempt]
+        assert_eq!(ApiClient::backoff_delay(&config, 0, &network, 0.0), Some(Duration::ZERO));
+        assert_eq!(ApiClient::backoff_delay(&config, 0, &network, 0.5), Some(Duration::from_millis(500)));
+        assert_eq!(ApiClient::backoff_delay(&config, 2, &network, 0.5), Some(Duration::from_millis(2000)));
+        // ... capped by max_retry_delay_ms
+        assert_eq!(ApiClient::backoff_delay(&config, 10, &network, 0.5), Some(Duration::from_millis(2500)));
+        assert_eq!(ApiClient::backoff_delay(&config, 40, &network, 0.5), Some(Duration::from_millis(2500)));
+
+        // Retry-After is honored exactly for 429/503
+        let limited = MathSeekError::http(429, None, "slow down".to_string(), Some(3));
+        assert_eq!(ApiClient::backoff_delay(&config, 0, &limited, 0.0), Some(Duration::from_secs(3)));
+        let unavailable = MathSeekError::http(503, None, "maintenance".to_string(), Some(60));
+        assert_eq!(ApiClient::backoff_delay(&config, 0, &unavailable, 0.0), None);
+
+        // ... but ignored for other statuses
+        let bad_gateway = MathSeekError::http(502, None, "bad gateway".to_string(), Some(60));
+        assert_eq!(ApiClient::backoff_delay(&config, 0, &bad_gateway, 1.0), Some(Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn test_drain_lines() {
+        let mut buffer = b"data: {\"a\": 1}\n\ndata: caf\xc3".to_vec();
+        assert_eq!(ApiClient::drain_lines(&mut buffer), vec!["data: {\"a\": 1}".to_string()]);
+        assert_eq!(buffer, b"data: caf\xc3".to_vec());
+
+        // The second half of the split character completes the line
+        buffer.extend_from_slice(b"\xa9\r\n");
+        assert_eq!(ApiClient::drain_lines(&mut buffer), vec!["data: café".to_string()]);
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_in_flight_request() {
+        // A server that accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.push(socket);
+            }
+        });
+
+        let jobs = crate::RecognitionJobs::default();
+        let job = jobs.start(Some("stuck".to_string())).unwrap();
+        let config = ApiConfig {
+            endpoint: format!("http://{}", address),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        };
+        let client = ApiClient::new(config).unwrap().with_cancellation(job.token());
+
+        let cancel = async {
+            sleep(Duration::from_millis(50)).await;
+            jobs.cancel("stuck");
+        };
+        let payload = serde_json::json!({});
+        let request = client.make_request_with_retry::<_, serde_json::Value>("/recognize", &payload);
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async { tokio::join!(request, cancel) })
+            .await
+            .expect("cancellation should abort the request");
+
+        assert!(matches!(result, Err(MathSeekError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_trickled_body_times_out() {
+        // A server that sends headers, then one byte of the body every 200ms
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0u8; 4096];
+                    let _ = socket.read(&mut request).await;
+                    let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 1000\r\n\r\n";
+                    if socket.write_all(head.as_bytes()).await.is_err() {
+                        return;
+                    }
+                    loop {
+                        sleep(Duration::from_millis(200)).await;
+                        if socket.write_all(b" ").await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        let config = ApiConfig {
+            endpoint: format!("http://{}", address),
+            api_key: "test-key".to_string(),
+            timeout_seconds: 1,
+            max_retries: 0,
+            ..Default::default()
+        };
+        let client = ApiClient::new(config).unwrap();
+
+        let payload = serde_json::json!({});
+        let request = client.make_request_with_retry::<_, serde_json::Value>("/recognize", &payload);
+        let result = tokio::time::timeout(Duration::from_secs(5), request)
+            .await
+            .expect("a trickling server should not hold the request past its timeout");
+        assert!(matches!(result, Err(MathSeekError::Timeout(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_formula_from_cassette() {
+        let config = ApiConfig {
+            endpoint: "http://cassette.invalid".to_string(),
+            api_key: "test-key".to_string(),
+            cassette: Some(CassetteConfig::replay(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes/analyze_formula.json"))),
+            ..Default::default()
+        };
+        let client = ApiClient::new(config).unwrap();
+
+        let analysis = client.analyze_formula("E = mc^2").await.unwrap();
+        assert_eq!(analysis.formula_type, "Energy Equation");
+        assert_eq!(analysis.variables.len(), 3);
+        assert_eq!(analysis.domain.as_deref(), Some("Physics"));
+
+        // Requests that were never recorded are not sent anywhere
+        assert!(client.analyze_formula("F = ma").await.is_err());
+    }
+
+    fn mock_config(server: &MockServer) -> ApiConfig {
+        ApiConfig {
+            endpoint: server.url(),
+            api_key: "test-key".to_string(),
+            retry_delay_ms: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_against_mock_server() {
+        let server = MockServer::start(MockScript::default().api_key("test-key")).await.unwrap();
+
+        let client = ApiClient::new(mock_config(&server)).unwrap();
+        assert!(client.test_connection().await.unwrap());
+
+        let rejected = ApiClient::new(ApiConfig { api_key: "wrong-key".to_string(), ..mock_config(&server) }).unwrap();
+        assert!(!rejected.test_connection().await.unwrap());
+        assert_eq!(server.request_count("/health"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_upload_carries_payload_format() {
+        let server = MockServer::start(MockScript::default()).await.unwrap();
+        let client = ApiClient::new(mock_config(&server)).unwrap();
+        let encode = |format| {
+            let mut data = std::io::Cursor::new(Vec::new());
+            image::RgbImage::from_pixel(64, 64, image::Rgb([255, 255, 255])).write_to(&mut data, format).unwrap();
+            data.into_inner()
+        };
+
+        // JPEG is accepted as it is, WebP is re-encoded to a format the backend takes
+        let jpeg = client.recognize_image(&encode(image::ImageFormat::Jpeg), InputType::SingleFormula).await.unwrap();
+        assert_eq!(jpeg.payload.unwrap().format, PayloadFormat::Jpeg);
+        let webp = client.recognize_image(&encode(image::ImageFormat::WebP), InputType::SingleFormula).await.unwrap();
+        assert_eq!(webp.payload.unwrap().format, PayloadFormat::Png);
+
+        let mime_types: Vec<_> = server.requests().iter().map(|request| request.body["mime_type"].clone()).collect();
+        assert_eq!(mime_types, vec!["image/jpeg", "image/png"]);
+    }
+
+    #[tokio::test]
+    async fn test_non_image_bytes_are_rejected_before_upload() {
+        let server = MockServer::start(MockScript::default()).await.unwrap();
+        let client = ApiClient::new(mock_config(&server)).unwrap();
+
+        let error = client.recognize_image(b"image", InputType::SingleFormula).await.unwrap_err();
+        assert!(matches!(error, MathSeekError::ImageError(_)));
+        assert_eq!(server.request_count("/recognize"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_retry_against_mock_server() {
+        let script = MockScript::default()
+            .route("/recognize", vec![
+                MockResponse::status(503, serde_json::json!({"error": "busy"})).with_retry_after(0),
+                MockResponse::dropped(),
+                MockResponse::ok(serde_json::json!({"success": true, "latex": "a + b", "confidence": 0.9})),
+            ])
+            .route("/analyze", vec![
+                MockResponse::status(400, serde_json::json!({"error": {"code": "bad_formula", "message": "Unparseable"}})),
+            ]);
+        let server = MockServer::start(script).await.unwrap();
+        let client = ApiClient::new(mock_config(&server)).unwrap();
+
+        // 503 and a dropped connection are retried until the success
+        let result = client.recognize_image(TEST_IMAGE, InputType::SingleFormula).await.unwrap();
+        assert_eq!(result.latex, "a + b");
+        assert_eq!(server.request_count("/recognize"), 3);
+        // The small PNG fits the budget and is sent as it is
+        assert_eq!(result.payload.map(|payload| payload.bytes), Some(TEST_IMAGE.len() as u64));
+
+        // Client errors are returned immediately
+        let error = client.analyze_formula("x +").await.unwrap_err();
+        assert!(matches!(error, MathSeekError::HttpError { status: 400, ref code, .. } if code.as_deref() == Some("bad_formula")));
+        assert_eq!(server.request_count("/analyze"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_timeouts_are_not_retried() {
+        let script = MockScript::default().route("/recognize", vec![
+            MockResponse::ok(serde_json::json!({"success": true, "latex": "x"})).with_delay(3000),
+        ]);
+        let server = MockServer::start(script).await.unwrap();
+        let client = ApiClient::new(ApiConfig { timeout_seconds: 1, ..mock_config(&server) }).unwrap();
+
+        let error = client.recognize_image(TEST_IMAGE, InputType::SingleFormula).await.unwrap_err();
+        assert!(matches!(error, MathSeekError::Timeout(_)));
+        assert_eq!(server.request_count("/recognize"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_against_failing_server() {
+        let server = MockServer::start(MockScript::default().failure_rate(1.0)).await.unwrap();
+        let client = ApiClient::new(ApiConfig { circuit_failure_threshold: 2, ..mock_config(&server) }).unwrap();
+
+        let error = client.analyze_formula("x").await.unwrap_err();
+        assert!(matches!(error, MathSeekError::CircuitOpen { .. }));
+        assert_eq!(server.request_count("/analyze"), 2);
+        assert_eq!(client.get_config_info()["circuit_breakers"][0]["state"], "open");
+    }
+
+    #[tokio::test]
+    async fn test_reused_mock_address_starts_fresh() {
+        let server = MockServer::start(MockScript::default().failure_rate(1.0)).await.unwrap();
+        let config = ApiConfig { circuit_failure_threshold: 1, max_retries: 0, ..mock_config(&server) };
+        let client = ApiClient::new(config.clone()).unwrap();
+        assert!(client.analyze_formula("x").await.is_err());
+        assert_eq!(client.get_config_info()["circuit_breakers"][0]["state"], "open");
+
+        // A later server on the same port must not see the earlier one's breaker
+        let address = server.url().trim_start_matches("http://").to_string();
+        drop(server);
+        let mut rebound = MockServer::bind(&address, MockScript::default()).await;
+        for _ in 0..50 {
+            if rebound.is_ok() {
+                break;
+            }
+            // The aborted listener task releases the port on its next poll
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            rebound = MockServer::bind(&address, MockScript::default()).await;
+        }
+        let server = rebound.unwrap();
+        let client = ApiClient::new(ApiConfig { endpoint: server.url(), ..config }).unwrap();
+        assert_eq!(client.get_config_info()["circuit_breakers"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_cap_is_shared_between_clients() {
+        let script = MockScript::default().route("/analyze", vec![
+            MockResponse::ok(serde_json::json!({"success": true, "formula_type": "Sum"})).with_delay(200),
+        ]);
+        let server = MockServer::start(script).await.unwrap();
+        let config = ApiConfig { max_concurrent_requests: 1, ..mock_config(&server) };
+        let first = ApiClient::new(config.clone()).unwrap();
+        let second = ApiClient::new(config).unwrap();
+
+        let started = std::time::Instant::now();
+        let observe = async {
+            sleep(Duration::from_millis(100)).await;
+            first.rate_limit_status()
+        };
+        let (a, b, status) = tokio::join!(first.analyze_formula("a + b"), second.analyze_formula("a + b"), observe);
+
+        // The second request waits for the first instead of failing
+        assert!(a.is_ok() && b.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert_eq!((status.queued, status.in_flight), (1, 1));
+        assert_eq!(first.get_config_info()["rate_limit"]["in_flight"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_priced_and_recorded() {
+        let script = MockScript::default().route("/analyze", vec![MockResponse::ok(serde_json::json!({
+            "success": true,
+            "formula_type": "Sum",
+            "token_usage": {"input_tokens": 1200, "output_tokens": 300},
+        }))]);
+        let server = MockServer::start(script).await.unwrap();
+        let ledger_path = std::env::temp_dir().join(format!("mathseek-usage-client-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&ledger_path);
+
+        let mut prices = PriceTable::new();
+        prices.insert("mathseek/formula-v2".to_string(), usage::ModelPrice {
+            input_per_million: 1.0,
+            output_per_million: 4.0,
+            per_request: 0.0,
+        });
+        let client = ApiClient::new(ApiConfig {
+            model: "formula-v2".to_string(),
+            prices,
+            usage_ledger: Some(ledger_path.clone()),
+            ..mock_config(&server)
+        }).unwrap();
+
+        let analysis = client.analyze_formula("a + b").await.unwrap();
+        let token_usage = analysis.token_usage.unwrap();
+        assert_eq!((token_usage.provider.as_str(), token_usage.model.as_str()), ("mathseek", "formula-v2"));
+        assert_eq!((token_usage.input_tokens, token_usage.output_tokens), (1200, 300));
+        assert_eq!(token_usage.estimated_cost, Some(0.0024));
+
+        // Servers that report no token counts still count as a request
+        client.recognize_image(TEST_IMAGE, InputType::SingleFormula).await.unwrap();
+
+        let spend = UsageLedger::new(&ledger_path).monthly_spend(&usage::current_month()).unwrap();
+        assert_eq!(spend.len(), 1);
+        assert_eq!((spend[0].requests, spend[0].input_tokens, spend[0].unpriced_requests), (2, 1200, 0));
+        std::fs::remove_file(&ledger_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_answers_that_fail_to_parse_are_recorded() {
+        let script = MockScript::default().route("/recognize", vec![
+            // Billed with token counts, but without a formula
+            MockResponse::ok(serde_json::json!({
+                "success": true,
+                "token_usage": {"input_tokens": 800, "output_tokens": 5},
+            })),
+            // Billed, but not a recognition response at all
+            MockResponse::ok(serde_json::json!("overloaded")),
+        ]);
+        let server = MockServer::start(script).await.unwrap();
+        let ledger_path = std::env::temp_dir().join(format!("mathseek-usage-failed-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&ledger_path);
+        let client = ApiClient::new(ApiConfig { usage_ledger: Some(ledger_path.clone()), ..mock_config(&server) }).unwrap();
+
+        assert!(client.recognize_image(TEST_IMAGE, InputType::SingleFormula).await.is_err());
+        assert!(client.recognize_image(TEST_IMAGE, InputType::SingleFormula).await.is_err());
+
+        let spend = UsageLedger::new(&ledger_path).monthly_spend(&usage::current_month()).unwrap();
+        assert_eq!((spend[0].provider.as_str(), spend[0].requests, spend[0].input_tokens), ("mathseek", 2, 800));
+        assert_eq!(spend[0].unpriced_requests, 0);
+        std::fs::remove_file(&ledger_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ledger_failures_are_returned() {
+        let server = MockServer::start(MockScript::default()).await.unwrap();
+        // A file where the ledger's directory should be
+        let blocker = std::env::temp_dir().join(format!("mathseek-usage-blocked-{}", std::process::id()));
+        std::fs::write(&blocker, "").unwrap();
+        let client = ApiClient::new(ApiConfig { usage_ledger: Some(blocker.join("usage.json")), ..mock_config(&server) }).unwrap();
+
+        let result = client.recognize_image(TEST_IMAGE, InputType::SingleFormula).await.unwrap();
+        let ledger_error = result.token_usage.unwrap().ledger_error.unwrap();
+        assert!(ledger_error.starts_with("Failed to record usage in"), "{}", ledger_error);
+        std::fs::remove_file(&blocker).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_document_fallbacks_against_mock_server() {
+        let script = MockScript::default().route("/recognize", vec![
+            // Structured content is used as-is
+            MockResponse::ok(serde_json::json!({
+                "success": true,
+                "latex": "E = mc^2",
+                "confidence": 0.9,
+                "content": {"title": "Physics", "sections": [{"heading": null, "text": "Energy", "formulas": []}]}
+            })),
+            // Content in an unknown shape falls back to a single section
+            MockResponse::ok(serde_json::json!({
+                "success": true,
+                "latex": "Energy is E = mc^2",
+                "confidence": 0.8,
+                "content": {"blocks": ["unexpected"]}
+            })),
+            // Missing content falls back as well
+            MockResponse::ok(serde_json::json!({"success": true, "latex": "F = ma", "confidence": 0.7})),
+        ]);
+        let server = MockServer::start(script).await.unwrap();
+        let client = ApiClient::new(mock_config(&server)).unwrap();
+
+        let mut documents = Vec::new();
+        for _ in 0..3 {
+            match client.recognize_image(TEST_IMAGE, InputType::Document).await.unwrap().content {
+                ResultContent::Document(doc) => documents.push(doc),
+                _ => panic!("Expected Document content"),
+            }
+        }
+
+        assert_eq!(documents[0].title.as_deref(), Some("Physics"));
+        assert_eq!(documents[1].sections.len(), 1);
+        assert_eq!(documents[1].sections[0].text, "Energy is E = mc^2");
+        assert_eq!(documents[2].sections[0].text, "F = ma");
+        assert_eq!(server.requests()[0].body["input_type"], "Document");
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_fails_fast() {
+        let config = ApiConfig {
+            endpoint: "http://open-circuit.invalid".to_string(),
+            api_key: "test-key".to_string(),
+            circuit_failure_threshold: 1,
+            ..Default::default()
+        };
+        let client = ApiClient::new(config).unwrap();
+        circuit_breaker::record_failure("http://open-circuit.invalid/recognize", 1);
+
+        let result: MathSeekResult<serde_json::Value> = client
+            .make_request_with_retry("/recognize", &serde_json::json!({}))
+            .await;
+        assert!(matches!(result, Err(MathSeekError::CircuitOpen { .. })));
+
+        let info = client.get_config_info();
+        assert_eq!(info["circuit_breakers"][0]["state"], "open");
+    }
+}
@@ -0,0 +1,48 @@
+//! Standalone mock recognition server for local development
+//!
+//! Usage: mathseek-mock-server [--port N] [--script FILE] [--latency-ms N] [--failure-rate P] [--api-key KEY]
+
+use mathseek_lib::mock_server::{self, MockScript, MockServer};
+
+fn usage() -> ! {
+    eprintln!("Usage: mathseek-mock-server [--port N] [--script FILE] [--latency-ms N] [--failure-rate P] [--api-key KEY]");
+    std::process::exit(2);
+}
+
+#[tokio::main]
+async fn main() {
+    let mut port = 8787u16;
+    let mut script = MockScript::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match flag.as_str() {
+            "--port" => port = value.parse().unwrap_or_else(|_| usage()),
+            "--script" => {
+                script = mock_server::load_script(&value).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                })
+            }
+            "--latency-ms" => script.latency_ms = value.parse().unwrap_or_else(|_| usage()),
+            "--failure-rate" => script.failure_rate = value.parse().unwrap_or_else(|_| usage()),
+            "--api-key" => script.api_key = Some(value),
+            _ => usage(),
+        }
+    }
+
+    let server = match MockServer::bind(&format!("127.0.0.1:{}", port), script).await {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to start mock server: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("MathSeek mock server listening on {}", server.url());
+    tokio::select! {
+        _ = server.wait() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{MathSeekError, MathSeekResult};
+use base64::prelude::*;
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Environment variable enabling a cassette, as `record:<path>` or `replay:<path>`
+pub const CASSETTE_ENV: &str = "MATHSEEK_CASSETTE";
+
+/// Shortest string treated as a base64 image payload when hashing requests
+const MIN_IMAGE_PAYLOAD_LEN: usize = 64;
+
+/// Whether an `ApiClient` records its traffic or serves it from disk
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Cassette settings for `ApiConfig`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub path: PathBuf,
+}
+
+impl CassetteConfig {
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self { mode: CassetteMode::Record, path: path.into() }
+    }
+
+    pub fn replay(path: impl Into<PathBuf>) -> Self {
+        Self { mode: CassetteMode::Replay, path: path.into() }
+    }
+
+    /// Read the cassette settings from `MATHSEEK_CASSETTE`, if set
+    pub fn from_env() -> Option<Self> {
+        Self::parse(&std::env::var(CASSETTE_ENV).ok()?)
+    }
+
+    fn parse(spec: &str) -> Option<Self> {
+        let (mode, path) = spec.split_once(':')?;
+        if path.is_empty() {
+            return None;
+        }
+
+        match mode {
+            "record" => Some(Self::record(path)),
+            "replay" => Some(Self::replay(path)),
+            _ => None,
+        }
+    }
+}
+
+/// One recorded request and the response it received
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    /// Path relative to the endpoint, so cassettes work with any endpoint
+    pub path: String,
+    /// Request body with image payloads replaced by their SHA-256
+    #[serde(default)]
+    pub request: serde_json::Value,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<String>,
+    pub body: RecordedBody,
+}
+
+/// Response body, kept as JSON when possible so cassettes stay readable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedBody {
+    Json(serde_json::Value),
+    Text(String),
+}
+
+/// On-disk cassette format
+#[derive(Debug, Serialize, Deserialize)]
+struct CassetteFile {
+    version: u32,
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Default)]
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    /// Interactions already served in replay mode
+    served: Vec<bool>,
+}
+
+/// Recorded API traffic backing an `ApiClient`
+///
+/// In record mode every response is appended to the file as it arrives. In
+/// replay mode matching interactions are served in recorded order, so a
+/// failure followed by a success replays the same way; once all matches have
+/// been served, the last one is repeated.
+#[derive(Debug)]
+pub struct Cassette {
+    config: CassetteConfig,
+    state: Mutex<CassetteState>,
+}
+
+impl Cassette {
+    /// Open a cassette; replay mode requires the file to exist
+    pub fn open(config: CassetteConfig) -> MathSeekResult<Self> {
+        let interactions = if config.path.exists() {
+            Self::load(&config.path)?
+        } else if config.mode == CassetteMode::Replay {
+            return Err(MathSeekError::ConfigError(format!(
+                "Cassette not found: {}", config.path.display()
+            )));
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            config,
+            state: Mutex::new(CassetteState {
+                served: vec![false; interactions.len()],
+                interactions,
+            }),
+        })
+    }
+
+    fn load(path: &Path) -> MathSeekResult<Vec<Interaction>> {
+        let content = std::fs::read_to_string(path)?;
+        let file: CassetteFile = serde_json::from_str(&content)
+            .map_err(|e| MathSeekError::SerializationError(format!("Invalid cassette {}: {}", path.display(), e)))?;
+        Ok(file.interactions)
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.config.mode
+    }
+
+    /// Serve the recorded response for a request
+    pub fn replay(&self, method: &str, path: &str, request: &serde_json::Value) -> MathSeekResult<Response> {
+        let request = hash_image_payloads(request);
+        let mut state = self.lock();
+
+        let matches: Vec<usize> = state.interactions.iter()
+            .enumerate()
+            .filter(|(_, interaction)| {
+                interaction.method == method && interaction.path == path && interaction.request == request
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        let index = matches.iter()
+            .copied()
+            .find(|&index| !state.served[index])
+            .or_else(|| matches.last().copied())
+            .ok_or_else(|| MathSeekError::ApiError(format!(
+                "No recorded response for {} {} in cassette {}",
+                method, path, self.config.path.display()
+            )))?;
+
+        state.served[index] = true;
+        Self::build_response(&state.interactions[index])
+    }
+
+    /// Save a live response to the cassette and hand back an equivalent one
+    pub async fn record(&self, method: &str, path: &str, request: &serde_json::Value, response: Response) -> MathSeekResult<Response> {
+        let status = response.status().as_u16();
+        let retry_after = response.headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let text = response.text().await?;
+        let body = match serde_json::from_str(&text) {
+            Ok(json) => RecordedBody::Json(json),
+            Err(_) => RecordedBody::Text(text),
+        };
+
+        let interaction = Interaction {
+            method: method.to_string(),
+            path: path.to_string(),
+            request: hash_image_payloads(request),
+            status,
+            retry_after,
+            body,
+        };
+        let response = Self::build_response(&interaction)?;
+
+        let mut state = self.lock();
+        state.interactions.push(interaction);
+        state.served.push(true);
+        self.save(&state.interactions)?;
+
+        Ok(response)
+    }
+
+    fn save(&self, interactions: &[Interaction]) -> MathSeekResult<()> {
+        if let Some(parent) = self.config.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = CassetteFile { version: 1, interactions: interactions.to_vec() };
+        std::fs::write(&self.config.path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    fn build_response(interaction: &Interaction) -> MathSeekResult<Response> {
+        let (content_type, body) = match &interaction.body {
+            RecordedBody::Json(json) => ("application/json", serde_json::to_string(json)?),
+            RecordedBody::Text(text) => ("text/plain", text.clone()),
+        };
+
+        let mut builder = http::Response::builder()
+            .status(interaction.status)
+            .header(CONTENT_TYPE, content_type);
+        if let Some(retry_after) = &interaction.retry_after {
+            builder = builder.header(RETRY_AFTER, retry_after);
+        }
+
+        builder.body(body)
+            .map(Response::from)
+            .map_err(|e| MathSeekError::SerializationError(format!("Invalid recorded response: {}", e)))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CassetteState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Replace base64 image payloads and `data:` URIs with `sha256:<hex>` of the image
+///
+/// Keeps images out of cassette files while still letting replay tell
+/// requests for different images apart.
+pub fn hash_image_payloads(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => match image_payload(text) {
+            Some(image) => serde_json::Value::String(format!("sha256:{:x}", Sha256::digest(image))),
+            None => value.clone(),
+        },
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(hash_image_payloads).collect())
+        }
+        serde_json::Value::Object(fields) => serde_json::Value::Object(
+            fields.iter()
+                .map(|(key, field)| (key.clone(), hash_image_payloads(field)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Decoded bytes of a base64 `data:` URI or bare base64 string
+fn image_payload(text: &str) -> Option<Vec<u8>> {
+    let encoded = match text.strip_prefix("data:") {
+        Some(uri) => uri.split_once(";base64,")?.1,
+        None if text.len() >= MIN_IMAGE_PAYLOAD_LEN => text,
+        None => return None,
+    };
+    BASE64_STANDARD.decode(encoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cassette_spec() {
+        assert_eq!(CassetteConfig::parse("record:/tmp/a.json"), Some(CassetteConfig::record("/tmp/a.json")));
+        assert_eq!(CassetteConfig::parse("replay:b.json"), Some(CassetteConfig::replay("b.json")));
+        assert_eq!(CassetteConfig::parse("replay:"), None);
+        assert_eq!(CassetteConfig::parse("stream:b.json"), None);
+    }
+
+    #[test]
+    fn test_hash_image_payloads() {
+        let image = BASE64_STANDARD.encode([7u8; 96]);
+        let request = serde_json::json!({
+            "image_data": image,
+            "input_type": "SingleFormula",
+            "messages": [{"content": [{"image_url": {"url": format!("data:image/png;base64,{}", image)}}]}]
+        });
+
+        let hashed = hash_image_payloads(&request);
+        let digest = format!("sha256:{:x}", Sha256::digest([7u8; 96]));
+        assert_eq!(hashed["image_data"], digest.as_str());
+        assert_eq!(hashed["messages"][0]["content"][0]["image_url"]["url"], digest.as_str());
+        assert_eq!(hashed["input_type"], "SingleFormula");
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("mathseek-cassette-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let request = serde_json::json!({"formula": "E = mc^2"});
+
+        let recorder = Cassette::open(CassetteConfig::record(&path)).unwrap();
+        for (status, body) in [(503, "busy"), (200, r#"{"success": true}"#)] {
+            let live = http::Response::builder().status(status).body(body).unwrap();
+            let response = recorder.record("POST", "/analyze", &request, Response::from(live)).await.unwrap();
+            assert_eq!(response.status().as_u16(), status);
+        }
+
+        // Replay serves the recorded sequence, then repeats the last match
+        let player = Cassette::open(CassetteConfig::replay(&path)).unwrap();
+        assert_eq!(player.replay("POST", "/analyze", &request).unwrap().status().as_u16(), 503);
+        let response = player.replay("POST", "/analyze", &request).unwrap();
+        assert_eq!(response.json::<serde_json::Value>().await.unwrap()["success"], true);
+        assert_eq!(player.replay("POST", "/analyze", &request).unwrap().status().as_u16(), 200);
+
+        let other = serde_json::json!({"formula": "F = ma"});
+        assert!(player.replay("POST", "/analyze", &other).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{MathSeekError, MathSeekResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Circuit breaker state as reported to the frontend
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests fail fast until the cooldown has elapsed
+    Open,
+    /// A single trial request is allowed to probe the endpoint
+    HalfOpen,
+}
+
+/// Snapshot of one endpoint's breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitStatus {
+    pub endpoint: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// Seconds until an open breaker lets a trial request through
+    pub retry_in_secs: Option<u64>,
+}
+
+/// Failure counter for a single endpoint
+///
+/// Opens after `threshold` consecutive transient failures, then lets one
+/// trial request through once `cooldown` has elapsed.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+    /// When the breaker opened or the current trial started
+    changed_at: Instant,
+}
+
+impl CircuitBreaker {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            changed_at: now,
+        }
+    }
+
+    /// Ask to send a request; returns the remaining wait if the breaker refuses
+    pub fn try_acquire(&mut self, now: Instant, cooldown: Duration) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.changed_at);
+
+        match self.state {
+            CircuitState::Closed => Ok(()),
+            // A trial that never reported back (e.g. cancelled) must not block forever
+            CircuitState::Open | CircuitState::HalfOpen if elapsed >= cooldown => {
+                self.state = CircuitState::HalfOpen;
+                self.changed_at = now;
+                Ok(())
+            }
+            CircuitState::Open | CircuitState::HalfOpen => Err(cooldown - elapsed),
+        }
+    }
+
+    pub fn record_success(&mut self, now: Instant) {
+        self.state = CircuitState::Closed;
+        self.consecutive_failures = 0;
+        self.changed_at = now;
+    }
+
+    pub fn record_failure(&mut self, now: Instant, threshold: u32) {
+        self.consecutive_failures += 1;
+
+        if self.state == CircuitState::HalfOpen || self.consecutive_failures >= threshold {
+            self.state = CircuitState::Open;
+            self.changed_at = now;
+        }
+    }
+
+    pub fn status(&self, endpoint: &str, now: Instant, cooldown: Duration) -> CircuitStatus {
+        let retry_in_secs = match self.state {
+            CircuitState::Open => Some(cooldown.saturating_sub(now.saturating_duration_since(self.changed_at)).as_secs()),
+            _ => None,
+        };
+
+        CircuitStatus {
+            endpoint: endpoint.to_string(),
+            state: self.state,
+            consecutive_failures: self.consecutive_failures,
+            retry_in_secs,
+        }
+    }
+}
+
+/// Breakers are shared process-wide because Tauri commands create a fresh
+/// `ApiClient` for every call
+fn registry() -> &'static Mutex<HashMap<String, CircuitBreaker>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, CircuitBreaker>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn with_breaker<T>(endpoint: &str, f: impl FnOnce(&mut CircuitBreaker, Instant) -> T) -> T {
+    let now = Instant::now();
+    let mut breakers = registry().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let breaker = breakers
+        .entry(endpoint.to_string())
+        .or_insert_with(|| CircuitBreaker::new(now));
+    f(breaker, now)
+}
+
+/// Fail fast with `CircuitOpen` if the endpoint's breaker is open
+pub fn check(endpoint: &str, cooldown: Duration) -> MathSeekResult<()> {
+    with_breaker(endpoint, |breaker, now| breaker.try_acquire(now, cooldown))
+        .map_err(|remaining| MathSeekError::CircuitOpen {
+            endpoint: endpoint.to_string(),
+            retry_after_secs: remaining.as_secs().max(1),
+        })
+}
+
+pub fn record_success(endpoint: &str) {
+    with_breaker(endpoint, |breaker, now| breaker.record_success(now));
+}
+
+pub fn record_failure(endpoint: &str, threshold: u32) {
+    with_breaker(endpoint, |breaker, now| breaker.record_failure(now, threshold));
+}
+
+/// Drop the breakers of `endpoint` and its paths
+///
+/// Mock servers call this so that a test binding a port another test used
+/// does not inherit its open circuit.
+#[cfg(any(test, feature = "mock-server"))]
+pub fn forget(endpoint: &str) {
+    let path_prefix = format!("{}/", endpoint);
+    registry()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .retain(|key, _| key != endpoint && !key.starts_with(&path_prefix));
+}
+
+/// Status of the breakers of `prefix` and its paths
+pub fn statuses(prefix: &str, cooldown: Duration) -> Vec<CircuitStatus> {
+    let now = Instant::now();
+    let path_prefix = format!("{}/", prefix);
+    let breakers = registry().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut statuses: Vec<CircuitStatus> = breakers
+        .iter()
+        .filter(|(endpoint, _)| *endpoint == prefix || endpoint.starts_with(&path_prefix))
+        .map(|(endpoint, breaker)| breaker.status(endpoint, now, cooldown))
+        .collect();
+    statuses.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new(start);
+
+        breaker.record_failure(start, 3);
+        breaker.record_failure(start, 3);
+        assert!(breaker.try_acquire(start, COOLDOWN).is_ok());
+
+        breaker.record_failure(start, 3);
+        let remaining = breaker.try_acquire(start + Duration::from_secs(10), COOLDOWN).unwrap_err();
+        assert_eq!(remaining, Duration::from_secs(20));
+        assert_eq!(breaker.status("x", start, COOLDOWN).state, CircuitState::Open);
+    }
+
+    #[test]
+    fn test_half_open_trial() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new(start);
+        breaker.record_failure(start, 1);
+
+        // After the cooldown a single trial goes through
+        let later = start + COOLDOWN;
+        assert!(breaker.try_acquire(later, COOLDOWN).is_ok());
+        assert!(breaker.try_acquire(later, COOLDOWN).is_err());
+
+        // A failed trial reopens immediately
+        breaker.record_failure(later, 5);
+        assert!(breaker.try_acquire(later, COOLDOWN).is_err());
+
+        // A successful trial closes the breaker
+        let much_later = later + COOLDOWN;
+        assert!(breaker.try_acquire(much_later, COOLDOWN).is_ok());
+        breaker.record_success(much_later);
+        assert_eq!(breaker.status("x", much_later, COOLDOWN).state, CircuitState::Closed);
+        assert_eq!(breaker.status("x", much_later, COOLDOWN).consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_registry_fails_fast() {
+        let endpoint = "http://circuit-breaker.test/recognize";
+        for _ in 0..2 {
+            record_failure(endpoint, 2);
+        }
+
+        let error = check(endpoint, COOLDOWN).unwrap_err();
+        assert!(matches!(error, MathSeekError::CircuitOpen { .. }));
+        assert!(!error.is_retryable());
+        assert_eq!(statuses("http://circuit-breaker.test", COOLDOWN)[0].state, CircuitState::Open);
+    }
+
+    #[test]
+    fn test_statuses_stop_at_path_boundaries() {
+        record_failure("http://statuses.test:80/v1/recognize", 5);
+        record_failure("http://statuses.test:8080/v1/recognize", 5);
+        record_failure("http://statuses.test:80/v10/recognize", 5);
+
+        let endpoints = |prefix| statuses(prefix, COOLDOWN).into_iter().map(|status| status.endpoint).collect::<Vec<_>>();
+        assert_eq!(endpoints("http://statuses.test:80/v1"), vec!["http://statuses.test:80/v1/recognize"]);
+        assert_eq!(endpoints("http://statuses.test:80"), vec![
+            "http://statuses.test:80/v1/recognize",
+            "http://statuses.test:80/v10/recognize",
+        ]);
+        assert_eq!(endpoints("http://statuses.test:80/v1/recognize"), vec!["http://statuses.test:80/v1/recognize"]);
+    }
+}
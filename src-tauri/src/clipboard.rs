@@ -0,0 +1,185 @@
+use crate::{MathSeekError, MathSeekResult};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// Image formats accepted from copied files
+const FILE_FORMATS: &[ImageFormat] = &[ImageFormat::Png, ImageFormat::Bmp, ImageFormat::Tiff];
+
+/// Read an image from the clipboard as PNG
+///
+/// Copied bitmaps come first; the platform decodes its native flavor, which
+/// is PNG on Linux, a DIB on Windows and TIFF on macOS. Otherwise the first
+/// copied PNG, BMP or TIFF file is used, whether the file manager offered it
+/// as a file list or as `text/uri-list` text. This blocks while the
+/// clipboard owner answers.
+///
+/// Writing goes through the clipboard manager plugin, whose connection stays
+/// open to serve what the app copies; reading opens a short-lived one, as the
+/// plugin cannot list copied files.
+pub fn read_image() -> MathSeekResult<Option<Vec<u8>>> {
+    let mut clipboard = arboard::Clipboard::new().map_err(read_error)?;
+
+    match clipboard.get_image() {
+        Ok(image) => {
+            let (width, height) = (image.width as u32, image.height as u32);
+            return png_from_rgba(width, height, image.bytes.into_owned()).map(Some);
+        }
+        Err(arboard::Error::ContentNotAvailable) => {}
+        Err(e) => return Err(read_error(e)),
+    }
+
+    let mut paths = match clipboard.get().file_list() {
+        Ok(paths) => paths,
+        Err(arboard::Error::ContentNotAvailable) => Vec::new(),
+        Err(e) => return Err(read_error(e)),
+    };
+    if paths.is_empty() {
+        paths = match clipboard.get_text() {
+            Ok(text) => image_paths_from_uri_list(&text),
+            Err(arboard::Error::ContentNotAvailable) => Vec::new(),
+            Err(e) => return Err(read_error(e)),
+        };
+    }
+
+    Ok(paths.iter().find_map(|path| load_image_file(path).ok()))
+}
+
+fn read_error(error: arboard::Error) -> MathSeekError {
+    MathSeekError::ImageError(format!("Failed to read the clipboard: {}", error))
+}
+
+/// Encode clipboard pixels, which are RGBA rows without padding, as PNG
+pub fn png_from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> MathSeekResult<Vec<u8>> {
+    let image = RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| MathSeekError::ImageError(format!("Clipboard image data does not match its size {}x{}", width, height)))?;
+
+    let mut buffer = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+        .map_err(|e| MathSeekError::ImageError(format!("Failed to encode clipboard image: {}", e)))?;
+    Ok(buffer)
+}
+
+/// Read a PNG, BMP or TIFF file and return it as PNG
+pub fn load_image_file(path: &Path) -> MathSeekResult<Vec<u8>> {
+    let data = std::fs::read(path)
+        .map_err(|e| MathSeekError::ImageError(format!("Cannot read {}: {}", path.display(), e)))?;
+
+    let format = image::guess_format(&data)
+        .ok()
+        .filter(|format| FILE_FORMATS.contains(format))
+        .ok_or_else(|| MathSeekError::ImageError(format!("{} is not a PNG, BMP or TIFF image", path.display())))?;
+    if format == ImageFormat::Png {
+        return Ok(data);
+    }
+
+    let image = image::load_from_memory_with_format(&data, format)
+        .map_err(|e| MathSeekError::ImageError(format!("Failed to decode {}: {}", path.display(), e)))?;
+    let mut buffer = Vec::new();
+    image.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+        .map_err(|e| MathSeekError::ImageError(format!("Failed to encode {}: {}", path.display(), e)))?;
+    Ok(buffer)
+}
+
+/// Local paths in `text/uri-list` text
+///
+/// Comment lines and URIs with other schemes are skipped; plain absolute
+/// paths, as some terminals copy them, are accepted too.
+pub fn image_paths_from_uri_list(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match line.strip_prefix("file://") {
+            Some(uri) => {
+                // `file:///path` or `file://localhost/path`
+                let path = &uri[uri.find('/')?..];
+                let path = percent_decode(path)?;
+                // `file:///C:/...` on Windows
+                let path = match path.as_bytes() {
+                    [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+                    _ => path,
+                };
+                Some(PathBuf::from(path))
+            }
+            None => Some(PathBuf::from(line)).filter(|path| path.is_absolute()),
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_list_paths() {
+        let text = "# copied by the file manager\r\n\
+                    file:///home/ada/My%20Formulas/euler.png\r\n\
+                    file://localhost/tmp/a.tiff\r\n\
+                    https://example.com/remote.png\r\n\
+                    file:///C:/Users/ada/scan.bmp\r\n\
+                    /tmp/pasted.png\r\n\
+                    relative.png\r\n";
+
+        assert_eq!(image_paths_from_uri_list(text), vec![
+            PathBuf::from("/home/ada/My Formulas/euler.png"),
+            PathBuf::from("/tmp/a.tiff"),
+            PathBuf::from("C:/Users/ada/scan.bmp"),
+            PathBuf::from("/tmp/pasted.png"),
+        ]);
+        assert!(image_paths_from_uri_list("file:///broken%2").is_empty());
+    }
+
+    #[test]
+    fn test_rgba_to_png() {
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 128].repeat(2);
+        let png = png_from_rgba(2, 2, pixels).unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.get_pixel(1, 0).0, [0, 0, 255, 128]);
+
+        assert!(png_from_rgba(2, 2, vec![0; 15]).is_err());
+    }
+
+    #[test]
+    fn test_load_image_files_as_png() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(3, 2, image::Rgb([10, 20, 30])));
+        let directory = std::env::temp_dir();
+
+        for (extension, format) in [("bmp", ImageFormat::Bmp), ("tiff", ImageFormat::Tiff), ("png", ImageFormat::Png)] {
+            let path = directory.join(format!("mathseek-clipboard-{}.{}", std::process::id(), extension));
+            image.save_with_format(&path, format).unwrap();
+
+            let png = load_image_file(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+            assert_eq!(image::load_from_memory(&png).unwrap().to_rgb8(), image.to_rgb8(), "{}", extension);
+        }
+
+        let path = directory.join(format!("mathseek-clipboard-{}.jpg", std::process::id()));
+        image.save_with_format(&path, ImageFormat::Jpeg).unwrap();
+        let error = load_image_file(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(error, MathSeekError::ImageError(message) if message.contains("not a PNG, BMP or TIFF")));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::mathml::{self, Token};
+use crate::recognition_backend;
+use serde::{Deserialize, Serialize};
+
+/// How many answers an ensemble recognition collects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnsembleConfig {
+    /// Requests sent to every configured provider
+    pub samples_per_provider: u32,
+    /// Sampling temperature for chat models when taking several samples,
+    /// so that the samples can actually differ
+    pub sample_temperature: f32,
+}
+
+impl Default for EnsembleConfig {
+    fn default() -> Self {
+        Self {
+            samples_per_provider: 1,
+            sample_temperature: 0.7,
+        }
+    }
+}
+
+/// An answer that lost the vote, kept for the user to choose from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecognitionAlternative {
+    pub latex: String,
+    pub votes: u32,
+    /// Share of all answers that agreed with this one
+    pub agreement: f32,
+    /// Providers that gave this answer, as `backend` or `backend/model`
+    pub providers: Vec<String>,
+}
+
+/// One answer taking part in the vote
+#[derive(Debug, Clone)]
+pub struct Ballot<'a> {
+    pub latex: &'a str,
+    pub confidence: f32,
+    pub provider: &'a str,
+}
+
+/// Outcome of a vote
+#[derive(Debug, Clone)]
+pub struct Tally {
+    /// Index of the ballot representing the majority answer
+    pub winner: usize,
+    /// Share of ballots in the winning cluster
+    pub agreement: f32,
+    /// Other clusters, most votes first
+    pub alternatives: Vec<RecognitionAlternative>,
+}
+
+/// Cluster equivalent answers and pick the majority
+///
+/// Clusters rank by votes, then by summed provider confidence, then by which
+/// answer came first. Each cluster is represented by its most confident ballot.
+pub fn tally(ballots: &[Ballot<'_>]) -> Option<Tally> {
+    let mut clusters: Vec<(String, Vec<usize>)> = Vec::new();
+    for (index, ballot) in ballots.iter().enumerate() {
+        let key = normalize_latex(ballot.latex);
+        match clusters.iter_mut().find(|(cluster_key, _)| *cluster_key == key) {
+            Some((_, members)) => members.push(index),
+            None => clusters.push((key, vec![index])),
+        }
+    }
+
+    let total_confidence = |members: &[usize]| members.iter().map(|&index| ballots[index].confidence).sum::<f32>();
+    // Stable sort keeps the order of first appearance among equal clusters
+    clusters.sort_by(|(_, a), (_, b)| {
+        b.len().cmp(&a.len()).then(total_confidence(b).total_cmp(&total_confidence(a)))
+    });
+
+    let representative = |members: &[usize]| {
+        members.iter()
+            .copied()
+            .reduce(|best, index| if ballots[index].confidence > ballots[best].confidence { index } else { best })
+            .unwrap_or_default()
+    };
+    let agreement = |members: &[usize]| members.len() as f32 / ballots.len() as f32;
+
+    let mut clusters = clusters.into_iter().map(|(_, members)| members);
+    let winning = clusters.next()?;
+
+    let alternatives = clusters
+        .map(|members| {
+            let mut providers: Vec<String> = Vec::new();
+            for &index in &members {
+                if !providers.iter().any(|provider| provider == ballots[index].provider) {
+                    providers.push(ballots[index].provider.to_string());
+                }
+            }
+
+            RecognitionAlternative {
+                latex: ballots[representative(&members)].latex.to_string(),
+                votes: members.len() as u32,
+                agreement: agreement(&members),
+                providers,
+            }
+        })
+        .collect();
+
+    Some(Tally {
+        winner: representative(&winning),
+        agreement: agreement(&winning),
+        alternatives,
+    })
+}
+
+/// Canonical form of a LaTeX formula for comparing answers
+///
+/// Drops math delimiters, whitespace, spacing commands, `\left`/`\right`
+/// and braces around single-token scripts, and maps common synonyms such
+/// as `\dfrac` and `\le` to one spelling. Tokens are joined by spaces so
+/// that `\alpha x` and `\alphax` stay different.
+pub fn normalize_latex(latex: &str) -> String {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut raw = mathml::tokenize(recognition_backend::strip_math_delimiters(latex))
+        .into_iter()
+        .flat_map(|token| match token {
+            Token::Command(command) => vec![command],
+            Token::Char(ch) => vec![ch.to_string()],
+            // Text compares by its words, not by the spacing around them
+            Token::Text(text) => vec!["{".to_string(), text.split_whitespace().collect::<Vec<_>>().join(" "), "}".to_string()],
+        })
+        .peekable();
+
+    while let Some(token) = raw.next() {
+        let token = match token.as_str() {
+            "\\," | "\\;" | "\\:" | "\\!" | "\\ " | "~" | "\\quad" | "\\qquad"
+            | "\\displaystyle" | "\\textstyle" => continue,
+            "\\left" | "\\right" | "\\bigl" | "\\bigr" | "\\Bigl" | "\\Bigr" => {
+                // `\left.` is an invisible delimiter
+                if raw.peek().map(String::as_str) == Some(".") {
+                    raw.next();
+                }
+                continue;
+            }
+            "\\dfrac" | "\\tfrac" => "\\frac".to_string(),
+            "\\le" => "\\leq".to_string(),
+            "\\ge" => "\\geq".to_string(),
+            "\\ne" => "\\neq".to_string(),
+            "\\to" => "\\rightarrow".to_string(),
+            "\\gets" => "\\leftarrow".to_string(),
+            "\\lbrace" => "\\{".to_string(),
+            "\\rbrace" => "\\}".to_string(),
+            "\\lvert" | "\\rvert" | "\\vert" => "|".to_string(),
+            "\\ast" => "*".to_string(),
+            _ => token,
+        };
+        tokens.push(token);
+    }
+
+    // `x^{2}` and `x^2` are the same
+    let mut index = 0;
+    while index + 3 < tokens.len() {
+        let is_script = tokens[index] == "^" || tokens[index] == "_";
+        if is_script && tokens[index + 1] == "{" && tokens[index + 3] == "}" {
+            tokens.remove(index + 3);
+            tokens.remove(index + 1);
+        }
+        index += 1;
+    }
+
+    // Sentence punctuation after a formula is not part of it
+    while tokens.last().is_some_and(|token| token == "." || token == ",") {
+        tokens.pop();
+    }
+
+    tokens.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_equivalent_spellings() {
+        let canonical = normalize_latex("\\frac{a}{b} \\leq x^2");
+        for variant in [
+            "$\\dfrac{a}{b}\\le x^{2}$",
+            "\\frac{a}{b}\\,\\leq\\; x^{2}.",
+            "\\[ \\frac {a} {b} \\le x ^ 2 \\]",
+        ] {
+            assert_eq!(normalize_latex(variant), canonical, "{}", variant);
+        }
+
+        assert_eq!(normalize_latex("\\left( x \\right)"), normalize_latex("(x)"));
+        assert_eq!(normalize_latex("x \\text{ if  }y"), normalize_latex("x\\text{if} y"));
+        assert_ne!(normalize_latex("\\text{if x}"), normalize_latex("\\text{ifx}"));
+        assert_eq!(normalize_latex("\\left. f \\right|_{0}"), normalize_latex("f|_0"));
+        assert_ne!(normalize_latex("\\alpha x"), normalize_latex("\\alphax"));
+        assert_ne!(normalize_latex("x^{10}"), normalize_latex("x^10"));
+    }
+
+    #[test]
+    fn test_tally_majority_and_alternatives() {
+        let ballots = [
+            Ballot { latex: "x^{2}", confidence: 0.8, provider: "openai/gpt-4o" },
+            Ballot { latex: "x^2", confidence: 0.95, provider: "mathpix" },
+            Ballot { latex: "x_2", confidence: 0.99, provider: "ollama/llava" },
+            Ballot { latex: "$x^2$", confidence: 0.7, provider: "openai/gpt-4o" },
+        ];
+
+        let tally = tally(&ballots).unwrap();
+        assert_eq!(tally.winner, 1);
+        assert_eq!(tally.agreement, 0.75);
+        assert_eq!(tally.alternatives, vec![RecognitionAlternative {
+            latex: "x_2".to_string(),
+            votes: 1,
+            agreement: 0.25,
+            providers: vec!["ollama/llava".to_string()],
+        }]);
+    }
+
+    #[test]
+    fn test_tally_tie_breaks_on_confidence() {
+        let ballots = [
+            Ballot { latex: "a", confidence: 0.6, provider: "one" },
+            Ballot { latex: "b", confidence: 0.9, provider: "two" },
+        ];
+        let tally = tally(&ballots).unwrap();
+        assert_eq!(tally.winner, 1);
+        assert_eq!(tally.alternatives[0].latex, "a");
+
+        assert!(super::tally(&[]).is_none());
+    }
+}
@@ -1,12 +1,25 @@
-use serde::{Deserialize, Serialize};
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use thiserror::Error;
 
 /// Custom error types for MathSeek application
-#[derive(Debug, Error, Serialize, Deserialize)]
+#[derive(Debug, Error)]
 pub enum MathSeekError {
     #[error("API调用失败: {0}")]
     ApiError(String),
     
+    #[error("HTTP错误 {status}: {message}")]
+    HttpError {
+        /// HTTP status code of the response
+        status: u16,
+        /// Provider-specific error code, if the body carried one
+        code: Option<String>,
+        message: String,
+        /// Delay requested by the `Retry-After` header
+        retry_after_secs: Option<u64>,
+        /// Whether repeating the same request may succeed
+        retryable: bool,
+    },
+    
     #[error("图像处理错误: {0}")]
     ImageError(String),
     
@@ -19,6 +32,22 @@ pub enum MathSeekError {
     #[error("网络错误: {0}")]
     NetworkError(String),
     
+    #[error("请求超时: {0}")]
+    Timeout(String),
+    
+    #[error("服务暂不可用: {endpoint} 连续失败，{retry_after_secs}秒后重试")]
+    CircuitOpen {
+        endpoint: String,
+        /// Seconds until the circuit breaker lets a trial request through
+        retry_after_secs: u64,
+    },
+    
+    #[error("识别已取消")]
+    Cancelled,
+    
+    #[error("识别任务已在运行: {0}")]
+    DuplicateJob(String),
+    
     #[error("IO错误: {0}")]
     IoError(String),
     
@@ -29,8 +58,83 @@ pub enum MathSeekError {
     Unknown(String),
 }
 
+impl MathSeekError {
+    /// Build an `HttpError`, classifying retryability from the status code
+    pub fn http(status: u16, code: Option<String>, message: String, retry_after_secs: Option<u64>) -> Self {
+        MathSeekError::HttpError {
+            status,
+            code,
+            message,
+            retry_after_secs,
+            retryable: Self::is_retryable_status(status),
+        }
+    }
+
+    /// Statuses that signal a transient condition on the server side
+    pub fn is_retryable_status(status: u16) -> bool {
+        matches!(status, 408 | 425 | 429 | 500 | 502 | 503 | 504)
+    }
+
+    /// Whether the failed operation is worth retrying
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            MathSeekError::HttpError { retryable, .. } => *retryable,
+            MathSeekError::NetworkError(_) | MathSeekError::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Variant name, used as the `type` tag when sent to the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MathSeekError::ApiError(_) => "ApiError",
+            MathSeekError::HttpError { .. } => "HttpError",
+            MathSeekError::ImageError(_) => "ImageError",
+            MathSeekError::ConfigError(_) => "ConfigError",
+            MathSeekError::ExportError(_) => "ExportError",
+            MathSeekError::NetworkError(_) => "NetworkError",
+            MathSeekError::Timeout(_) => "Timeout",
+            MathSeekError::CircuitOpen { .. } => "CircuitOpen",
+            MathSeekError::Cancelled => "Cancelled",
+            MathSeekError::DuplicateJob(_) => "DuplicateJob",
+            MathSeekError::IoError(_) => "IoError",
+            MathSeekError::SerializationError(_) => "SerializationError",
+            MathSeekError::Unknown(_) => "Unknown",
+        }
+    }
+}
+
+/// Serialized as `{ "type", "message", "retryable", ... }` so that Tauri
+/// commands hand the frontend structured errors instead of plain strings
+impl Serialize for MathSeekError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.serialize_entry("retryable", &self.is_retryable())?;
+
+        match self {
+            MathSeekError::HttpError { status, code, retry_after_secs, .. } => {
+                map.serialize_entry("status", status)?;
+                map.serialize_entry("code", code)?;
+                map.serialize_entry("retry_after_secs", retry_after_secs)?;
+            }
+            MathSeekError::CircuitOpen { endpoint, retry_after_secs } => {
+                map.serialize_entry("endpoint", endpoint)?;
+                map.serialize_entry("retry_after_secs", retry_after_secs)?;
+            }
+            _ => {}
+        }
+
+        map.end()
+    }
+}
+
 impl From<reqwest::Error> for MathSeekError {
     fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return MathSeekError::Timeout(err.to_string());
+        }
         MathSeekError::NetworkError(err.to_string())
     }
 }
@@ -53,5 +157,48 @@ impl From<image::ImageError> for MathSeekError {
     }
 }
 
+impl From<tokio::task::JoinError> for MathSeekError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        MathSeekError::Unknown(format!("Background task failed: {}", err))
+    }
+}
+
 /// Result type alias for MathSeek operations
-pub type MathSeekResult<T> = Result<T, MathSeekError>;
\ No newline at end of file
+pub type MathSeekResult<T> = Result<T, MathSeekError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_error_classification() {
+        assert!(MathSeekError::http(429, None, "Too many requests".to_string(), Some(3)).is_retryable());
+        assert!(MathSeekError::http(503, None, "Unavailable".to_string(), None).is_retryable());
+        assert!(!MathSeekError::http(401, None, "Unauthorized".to_string(), None).is_retryable());
+        assert!(!MathSeekError::http(400, None, "Bad request".to_string(), None).is_retryable());
+        assert!(MathSeekError::NetworkError("connection reset".to_string()).is_retryable());
+        assert!(MathSeekError::Timeout("30s".to_string()).is_retryable());
+        assert!(!MathSeekError::ConfigError("missing key".to_string()).is_retryable());
+        assert!(!MathSeekError::Cancelled.is_retryable());
+    }
+
+    #[test]
+    fn test_structured_serialization() {
+        let error = MathSeekError::http(
+            429,
+            Some("rate_limit_exceeded".to_string()),
+            "Slow down".to_string(),
+            Some(7),
+        );
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["type"], "HttpError");
+        assert_eq!(json["status"], 429);
+        assert_eq!(json["code"], "rate_limit_exceeded");
+        assert_eq!(json["retry_after_secs"], 7);
+        assert_eq!(json["retryable"], true);
+
+        let json = serde_json::to_value(MathSeekError::ImageError("bad".to_string())).unwrap();
+        assert_eq!(json["type"], "ImageError");
+        assert!(json.get("status").is_none());
+    }
+}
@@ -4,6 +4,8 @@ use crate::*;
 pub fn example_usage() {
     // Create a default configuration
     let mut config = AppConfig::default();
+    config.backend = openai_backend::OpenAiBackend::NAME.to_string();
+    config.model = openai_backend::DEFAULT_OPENAI_MODEL.to_string();
     config.api_endpoint = "https://api.openai.com/v1".to_string();
     config.api_key = "sk-example-key".to_string();
     
@@ -83,6 +85,26 @@ pub fn example_usage() {
             "Particle physics".to_string(),
             "Cosmology".to_string(),
         ],
+        variables: vec![
+            FormulaVariable {
+                symbol: "E".to_string(),
+                description: "Energy".to_string(),
+                unit: Some("J".to_string()),
+            },
+            FormulaVariable {
+                symbol: "m".to_string(),
+                description: "Rest mass".to_string(),
+                unit: Some("kg".to_string()),
+            },
+            FormulaVariable {
+                symbol: "c".to_string(),
+                description: "Speed of light in vacuum".to_string(),
+                unit: Some("m/s".to_string()),
+            },
+        ],
+        domain: Some("Special relativity".to_string()),
+        related_formulas: vec!["E^2 = (pc)^2 + (mc^2)^2".to_string()],
+        token_usage: None,
     };
     
     println!("Analysis result: {:?}", analysis);
@@ -1,6 +1,6 @@
 use crate::{
     MathSeekError, MathSeekResult, AppConfig, FormulaResult, DocumentContent, 
-    DocumentSection, FormulaBlock, ExportFormat, InputType, InlineFormat, BlockFormat
+    DocumentSection, FormulaBlock, ExportFormat, InputType, InlineFormat, BlockFormat, mathml
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -603,6 +603,81 @@ impl ExportManager {
             })
     }
 
+    /// HTML fragment with MathML formulas for the rich clipboard flavor
+    ///
+    /// A single formula is a bare `<math>` element; a document keeps its
+    /// title, headings and paragraphs with the formulas in place.
+    pub fn to_clipboard_html(&self, result: &FormulaResult) -> String {
+        let doc = match &result.content {
+            crate::ResultContent::SingleFormula(latex) => return mathml::latex_to_mathml(latex, true),
+            crate::ResultContent::Document(doc) => doc,
+        };
+
+        let mut html = String::new();
+        if let Some(title) = &doc.title {
+            html.push_str(&format!("<h1>{}</h1>\n", mathml::escape_xml(title)));
+        }
+
+        for section in &doc.sections {
+            if let Some(heading) = &section.heading {
+                html.push_str(&format!("<h2>{}</h2>\n", mathml::escape_xml(heading)));
+            }
+
+            // Formulas are placed by byte offset into the text, moved back to a
+            // character boundary; the stable sort keeps formulas sharing a
+            // position in their original order
+            let mut formulas: Vec<&FormulaBlock> = section.formulas.iter().collect();
+            formulas.sort_by_key(|formula| formula.position);
+
+            // Only the text is split into paragraphs, so that the newlines of
+            // multi-line formulas stay inside their markup
+            let mut paragraphs = vec![String::new()];
+            let mut offset = 0;
+            for formula in formulas {
+                let mut position = formula.position.clamp(offset, section.text.len());
+                while !section.text.is_char_boundary(position) {
+                    position -= 1;
+                }
+                Self::push_html_text(&mut paragraphs, &section.text[offset..position], false);
+                if let Some(paragraph) = paragraphs.last_mut() {
+                    paragraph.push_str(&mathml::latex_to_mathml(&formula.latex, !formula.is_inline));
+                }
+                offset = position;
+            }
+            Self::push_html_text(&mut paragraphs, &section.text[offset..], true);
+
+            for paragraph in paragraphs.iter().filter(|paragraph| !paragraph.is_empty()) {
+                html.push_str(&format!("<p>{}</p>\n", paragraph));
+            }
+        }
+
+        html
+    }
+
+    /// Append escaped text to `paragraphs`, starting a new one at every blank line
+    ///
+    /// Whitespace around paragraph breaks and at the end of a section is
+    /// dropped, and the remaining newlines become line breaks.
+    fn push_html_text(paragraphs: &mut Vec<String>, text: &str, ends_section: bool) {
+        let parts: Vec<&str> = text.split("\n\n").collect();
+        for (index, part) in parts.iter().enumerate() {
+            if index > 0 {
+                paragraphs.push(String::new());
+            }
+            let Some(paragraph) = paragraphs.last_mut() else {
+                return;
+            };
+            let mut part = *part;
+            if paragraph.is_empty() {
+                part = part.trim_start();
+            }
+            if ends_section || index + 1 < parts.len() {
+                part = part.trim_end();
+            }
+            paragraph.push_str(&mathml::escape_xml(part).replace('\n', "<br>"));
+        }
+    }
+
     /// Update the configuration
     pub fn update_config(&mut self, config: AppConfig) {
         self.config = config;
@@ -661,4 +736,51 @@ mod tests {
         assert!(doc_formats.contains(&ExportFormat::LaTeX));
         assert!(doc_formats.contains(&ExportFormat::HTML));
     }
+
+    #[test]
+    fn test_clipboard_html() {
+        let manager = ExportManager::new(create_test_config());
+
+        let html = manager.to_clipboard_html(&create_test_single_formula());
+        assert!(html.starts_with("<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\">"));
+        assert!(html.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+
+        let doc = DocumentContent {
+            title: Some("Circles & <Areas>".to_string()),
+            sections: vec![DocumentSection {
+                heading: None,
+                text: "The area is  for radius r.\n\nHence:".to_string(),
+                formulas: vec![
+                    FormulaBlock { latex: "A = \\pi r^2".to_string(), position: 12, is_inline: true },
+                    FormulaBlock { latex: "A \\geq 0".to_string(), position: 100, is_inline: false },
+                ],
+            }],
+        };
+        let result = FormulaResult::new_document("".to_string(), 0.9, doc);
+        let html = manager.to_clipboard_html(&result);
+
+        assert!(html.starts_with("<h1>Circles &amp; &lt;Areas&gt;</h1>\n<p>The area is <math"));
+        assert!(html.contains("display=\"inline\"><semantics><mrow><mi>A</mi><mo>=</mo><mi>π</mi>"));
+        assert!(html.contains("</math> for radius r.</p>\n<p>Hence:<math"));
+        assert!(html.contains("display=\"block\"><semantics><mrow><mi>A</mi><mo>≥</mo><mn>0</mn></mrow>"));
+
+        // Line breaks and blank lines inside formulas stay in their annotations
+        let aligned = "\\begin{aligned}a\\\\\nb\\end{aligned}";
+        let spaced = "\\begin{aligned}c\\\\\n\nd\\end{aligned}";
+        let doc = DocumentContent {
+            title: None,
+            sections: vec![DocumentSection {
+                heading: None,
+                text: "Given\nthat\n\nwe have".to_string(),
+                formulas: vec![
+                    FormulaBlock { latex: aligned.to_string(), position: 19, is_inline: false },
+                    FormulaBlock { latex: spaced.to_string(), position: 19, is_inline: false },
+                ],
+            }],
+        };
+        let html = manager.to_clipboard_html(&FormulaResult::new_document("".to_string(), 0.9, doc));
+        let (aligned, spaced) = (mathml::latex_to_mathml(aligned, true), mathml::latex_to_mathml(spaced, true));
+        assert!(aligned.contains("a\\\\\nb") && spaced.contains("c\\\\\n\nd"));
+        assert_eq!(html, format!("<p>Given<br>that</p>\n<p>we have{}{}</p>\n", aligned, spaced));
+    }
 }
\ No newline at end of file
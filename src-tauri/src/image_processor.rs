@@ -1,27 +1,94 @@
-use crate::{MathSeekError, MathSeekResult, ImageLayout, Region, InputType};
-use image::{ImageFormat};
+use crate::{layout, orientation, preprocessing, theme, Orientation, Theme, Screenshot, PreprocessingConfig, PreprocessingStage, MathSeekError, MathSeekResult, ImageLayout, InputType, ScreenshotOptions};
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageFormat, Rgb};
+use image::codecs::jpeg::JpegEncoder;
+use serde::{Deserialize, Serialize};
 use std::io::Cursor;
 use base64::{Engine as _, engine::general_purpose};
 
+/// Image encodings that can be uploaded to a provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl PayloadFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+        }
+    }
+
+    fn of(data: &[u8]) -> Option<Self> {
+        match image::guess_format(data).ok()? {
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            ImageFormat::WebP => Some(Self::WebP),
+            _ => None,
+        }
+    }
+}
+
+/// Upload limit of a provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadBudget {
+    /// Largest base64-encoded image the provider accepts
+    pub max_encoded_bytes: u64,
+    /// Formats the provider accepts, in order of preference
+    pub formats: &'static [PayloadFormat],
+}
+
+impl Default for PayloadBudget {
+    fn default() -> Self {
+        Self {
+            max_encoded_bytes: 10 * 1024 * 1024,
+            formats: &[PayloadFormat::Png, PayloadFormat::Jpeg],
+        }
+    }
+}
+
+/// The image actually uploaded for a recognition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImagePayload {
+    pub format: PayloadFormat,
+    /// Encoder quality for JPEG, `None` for lossless formats
+    pub quality: Option<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the image before it was fitted to the budget
+    pub original_bytes: u64,
+    /// Size of the uploaded image
+    pub bytes: u64,
+    /// Size of the base64 text sent, which the budget applies to
+    pub encoded_bytes: u64,
+}
+
+/// Scales tried when an image does not fit; below half size thin strokes blur together
+const PAYLOAD_SCALES: [f32; 4] = [1.0, 0.85, 0.7, 0.5];
+
+/// JPEG qualities tried; lower qualities smear fraction bars and sub- and superscripts
+const JPEG_QUALITIES: [u8; 3] = [90, 80, 70];
+
 /// Image processor for handling screenshot capture, clipboard operations, and image analysis
 pub struct ImageProcessor;
 
 impl ImageProcessor {
-    /// Capture a screenshot from the system
-    /// This is a placeholder implementation - actual screenshot capture would require platform-specific APIs
-    pub async fn capture_screenshot() -> MathSeekResult<Vec<u8>> {
-        // TODO: Implement actual screenshot capture using platform-specific APIs
-        // For now, return an error indicating this needs to be implemented
-        Err(MathSeekError::ImageError(
-            "Screenshot capture not yet implemented - requires platform-specific implementation".to_string()
-        ))
+    /// Capture a screenshot as PNG, see `screenshot::capture`
+    pub async fn capture_screenshot(options: &ScreenshotOptions) -> MathSeekResult<Screenshot> {
+        crate::screenshot::capture(options).await
     }
 
-    /// Get image data from the system clipboard
+    /// Get image data from the system clipboard as PNG
+    ///
+    /// Returns `None` when the clipboard holds neither an image nor a copied image file.
     pub async fn get_clipboard_image() -> MathSeekResult<Option<Vec<u8>>> {
-        // TODO: Implement clipboard image retrieval using tauri-plugin-clipboard-manager
-        // For now, return None indicating no image in clipboard
-        Ok(None)
+        tokio::task::spawn_blocking(crate::clipboard::read_image)
+            .await
+            .map_err(|e| MathSeekError::ImageError(format!("Clipboard read was interrupted: {}", e)))?
     }
 
     /// Validate that the provided data is a valid image
@@ -38,27 +105,168 @@ impl ImageProcessor {
     }
 
     /// Preprocess image data for better recognition results
-    pub fn preprocess_image(data: &[u8]) -> MathSeekResult<Vec<u8>> {
+    pub fn preprocess_image(data: &[u8], config: &PreprocessingConfig) -> MathSeekResult<Vec<u8>> {
+        let gray = Self::load_grayscale(data, config)?;
+        Self::encode_grayscale(&preprocessing::run(gray, config, |_, _| {}))
+    }
+
+    /// Preprocess image data and return the image after every stage as PNG
+    pub fn preprocess_image_stages(data: &[u8], config: &PreprocessingConfig) -> MathSeekResult<Vec<(PreprocessingStage, Vec<u8>)>> {
+        let gray = Self::load_grayscale(data, config)?;
+        let mut stages = Vec::new();
+        preprocessing::run(gray, config, |stage, image| stages.push((stage, image.clone())));
+
+        stages.into_iter()
+            .map(|(stage, image)| Ok((stage, Self::encode_grayscale(&image)?)))
+            .collect()
+    }
+
+    /// Rotation that preprocessing with `config` applies to an image
+    pub fn estimate_orientation(data: &[u8], config: &PreprocessingConfig) -> MathSeekResult<Orientation> {
+        if !config.deskew {
+            return Ok(Orientation::default());
+        }
+        let gray = Self::load_grayscale(data, config)?;
+        Ok(orientation::estimate(&gray))
+    }
+
+    /// Background colour of an image, to tell dark and coloured themes from plain pages
+    pub fn detect_theme(data: &[u8]) -> MathSeekResult<Theme> {
         let img = image::load_from_memory(data)
             .map_err(|e| MathSeekError::ImageError(format!("Failed to load image: {}", e)))?;
 
-        // Convert to grayscale for better OCR results
-        let gray_img = img.grayscale();
-        
-        // Resize if image is too large (max 2048x2048)
-        let processed_img = if gray_img.width() > 2048 || gray_img.height() > 2048 {
-            gray_img.resize(2048, 2048, image::imageops::FilterType::Lanczos3)
+        Ok(theme::detect(&Self::flatten_to_8bit(img).to_rgb8()))
+    }
+
+    /// Grayscale of an image on white, scaled down to the configured size
+    fn load_grayscale(data: &[u8], config: &PreprocessingConfig) -> MathSeekResult<GrayImage> {
+        let img = image::load_from_memory(data)
+            .map_err(|e| MathSeekError::ImageError(format!("Failed to load image: {}", e)))?;
+
+        let img = Self::flatten_to_8bit(img);
+        let max_size = config.max_size.max(1);
+        let img = if img.width() > max_size || img.height() > max_size {
+            img.resize(max_size, max_size, image::imageops::FilterType::Lanczos3)
         } else {
-            gray_img
+            img
         };
 
-        // Convert back to bytes
+        if config.polarity {
+            Ok(theme::to_grayscale(&img.to_rgb8()))
+        } else {
+            Ok(img.to_luma8())
+        }
+    }
+
+    fn encode_grayscale(image: &GrayImage) -> MathSeekResult<Vec<u8>> {
         let mut buffer = Vec::new();
-        let mut cursor = Cursor::new(&mut buffer);
-        
-        processed_img.write_to(&mut cursor, ImageFormat::Png)
+        image.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
             .map_err(|e| MathSeekError::ImageError(format!("Failed to encode processed image: {}", e)))?;
+        Ok(buffer)
+    }
+
+    /// Re-encode an image so that it fits a provider's payload budget
+    ///
+    /// An image that already fits in an accepted format is sent unchanged.
+    /// Otherwise every scale is tried with the lossless formats first and
+    /// JPEG after them, and the first encoding that fits is used. The image
+    /// is never shrunk below half its size or below the minimum size that
+    /// `is_image_suitable_for_processing` accepts.
+    pub fn fit_payload(data: &[u8], budget: &PayloadBudget) -> MathSeekResult<(Vec<u8>, ImagePayload)> {
+        let original_bytes = data.len() as u64;
+        let payload = |data: &[u8], format, quality, width, height| ImagePayload {
+            format,
+            quality,
+            width,
+            height,
+            original_bytes,
+            bytes: data.len() as u64,
+            encoded_bytes: Self::encoded_len(data.len()),
+        };
+
+        if let Some(format) = PayloadFormat::of(data).filter(|format| budget.formats.contains(format)) {
+            if Self::encoded_len(data.len()) <= budget.max_encoded_bytes {
+                let (width, height) = Self::get_image_dimensions(data)?;
+                return Ok((data.to_vec(), payload(data, format, None, width, height)));
+            }
+        }
+
+        let img = image::load_from_memory(data)
+            .map_err(|e| MathSeekError::ImageError(format!("Failed to load image: {}", e)))?;
+        let img = Self::flatten_to_8bit(img);
+
+        let mut candidates: Vec<(PayloadFormat, Option<u8>)> = budget.formats.iter()
+            .filter(|&&format| format != PayloadFormat::Jpeg)
+            .map(|&format| (format, None))
+            .collect();
+        if budget.formats.contains(&PayloadFormat::Jpeg) {
+            candidates.extend(JPEG_QUALITIES.iter().map(|&quality| (PayloadFormat::Jpeg, Some(quality))));
+        }
+
+        for scale in PAYLOAD_SCALES {
+            let width = (img.width() as f32 * scale).round() as u32;
+            let height = (img.height() as f32 * scale).round() as u32;
+            if width < 50 || height < 50 {
+                break;
+            }
+            let scaled = if scale < 1.0 {
+                img.resize_exact(width, height, image::imageops::FilterType::Lanczos3)
+            } else {
+                img.clone()
+            };
+
+            for &(format, quality) in &candidates {
+                let encoded = Self::encode(&scaled, format, quality)?;
+                if Self::encoded_len(encoded.len()) <= budget.max_encoded_bytes {
+                    let payload = payload(&encoded, format, quality, width, height);
+                    return Ok((encoded, payload));
+                }
+            }
+        }
+
+        Err(MathSeekError::ImageError(format!(
+            "Image does not fit the provider's payload limit of {} bytes without becoming illegible",
+            budget.max_encoded_bytes
+        )))
+    }
+
+    /// Length of the base64 text of `len` bytes
+    fn encoded_len(len: usize) -> u64 {
+        len.div_ceil(3) as u64 * 4
+    }
+
+    /// Composite transparency onto white and drop to 8 bits per channel, which every encoder takes
+    fn flatten_to_8bit(img: DynamicImage) -> DynamicImage {
+        let has_color = img.color().has_color();
+        let img = if img.color().has_alpha() {
+            let rgba = img.to_rgba8();
+            DynamicImage::ImageRgb8(ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let pixel = rgba.get_pixel(x, y);
+                let alpha = pixel[3] as u32;
+                Rgb([0, 1, 2].map(|channel| ((pixel[channel] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8))
+            }))
+        } else {
+            img
+        };
+
+        if has_color {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        } else {
+            DynamicImage::ImageLuma8(img.to_luma8())
+        }
+    }
 
+    fn encode(img: &DynamicImage, format: PayloadFormat, quality: Option<u8>) -> MathSeekResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        let result = match format {
+            PayloadFormat::Png => img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png),
+            PayloadFormat::WebP => img.write_to(&mut Cursor::new(&mut buffer), ImageFormat::WebP),
+            PayloadFormat::Jpeg => {
+                img.write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality.unwrap_or(JPEG_QUALITIES[0])))
+            }
+        };
+
+        result.map_err(|e| MathSeekError::ImageError(format!("Failed to encode image as {:?}: {}", format, e)))?;
         Ok(buffer)
     }
 
@@ -66,7 +274,7 @@ impl ImageProcessor {
     pub fn detect_input_type(data: &[u8]) -> MathSeekResult<InputType> {
         let layout = Self::analyze_image_layout(data)?;
         
-        // Simple heuristic: if there are multiple formula regions or text regions, it's likely a document
+        // Text next to a formula, or several formulas, make a document
         if layout.has_multiple_formulas || layout.has_text_content {
             Ok(InputType::Document)
         } else {
@@ -79,74 +287,7 @@ impl ImageProcessor {
         let img = image::load_from_memory(data)
             .map_err(|e| MathSeekError::ImageError(format!("Failed to load image for analysis: {}", e)))?;
 
-        // Convert to grayscale for analysis
-        let gray_img = img.to_luma8();
-        let (width, height) = gray_img.dimensions();
-
-        // Simple layout analysis - this is a basic implementation
-        // In a real application, you would use more sophisticated computer vision techniques
-        let mut formula_regions = Vec::new();
-        let mut text_regions = Vec::new();
-
-        // Analyze image in blocks to detect content regions
-        let block_size = 50;
-        let mut _content_blocks = 0;
-        let mut _total_blocks = 0;
-
-        for y in (0..height).step_by(block_size) {
-            for x in (0..width).step_by(block_size) {
-                _total_blocks += 1;
-                
-                let block_width = std::cmp::min(block_size as u32, width - x);
-                let block_height = std::cmp::min(block_size as u32, height - y);
-                
-                // Calculate average brightness in this block
-                let mut sum = 0u32;
-                let mut pixel_count = 0u32;
-                
-                for by in y..std::cmp::min(y + block_height, height) {
-                    for bx in x..std::cmp::min(x + block_width, width) {
-                        sum += gray_img.get_pixel(bx, by)[0] as u32;
-                        pixel_count += 1;
-                    }
-                }
-                
-                let avg_brightness = sum / pixel_count;
-                
-                // If block has significant content (not too bright/white), consider it a content region
-                if avg_brightness < 240 {
-                    _content_blocks += 1;
-                    
-                    // Simple heuristic: assume mathematical symbols are more dense/complex
-                    // This is a placeholder - real implementation would use ML or advanced CV
-                    if avg_brightness < 200 {
-                        formula_regions.push(Region {
-                            x,
-                            y,
-                            width: block_width,
-                            height: block_height,
-                        });
-                    } else {
-                        text_regions.push(Region {
-                            x,
-                            y,
-                            width: block_width,
-                            height: block_height,
-                        });
-                    }
-                }
-            }
-        }
-
-        let has_multiple_formulas = formula_regions.len() > 1;
-        let has_text_content = !text_regions.is_empty();
-
-        Ok(ImageLayout {
-            has_multiple_formulas,
-            has_text_content,
-            formula_regions,
-            text_regions,
-        })
+        Ok(layout::analyze(&theme::to_grayscale(&Self::flatten_to_8bit(img).to_rgb8())))
     }
 
     /// Convert image data to base64 string for frontend display
@@ -206,6 +347,7 @@ impl ImageProcessor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::PreprocessingProfile;
 
     #[test]
     fn test_validate_image_empty_data() {
@@ -250,4 +392,186 @@ mod tests {
         
         assert!(ImageProcessor::is_image_suitable_for_processing(&buffer).unwrap());
     }
+
+    #[test]
+    fn test_fit_payload() {
+        use image::{GrayImage, Luma};
+
+        // Noise compresses badly, so the lossless encodings are far over budget
+        let mut rng = fastrand::Rng::with_seed(7);
+        let img = GrayImage::from_fn(400, 300, |_, _| Luma([rng.u8(..)]));
+        let mut png = Vec::new();
+        DynamicImage::ImageLuma8(img).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+
+        let generous = PayloadBudget { max_encoded_bytes: png.len() as u64 * 2, ..Default::default() };
+        let (data, payload) = ImageProcessor::fit_payload(&png, &generous).unwrap();
+        assert_eq!(data, png);
+        assert_eq!((payload.format, payload.quality, payload.width), (PayloadFormat::Png, None, 400));
+
+        let tight = PayloadBudget { max_encoded_bytes: png.len() as u64 / 2, ..Default::default() };
+        let (data, payload) = ImageProcessor::fit_payload(&png, &tight).unwrap();
+        assert_eq!(payload.format, PayloadFormat::Jpeg);
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Jpeg);
+        assert!(payload.encoded_bytes <= tight.max_encoded_bytes);
+        assert_eq!((payload.original_bytes, payload.bytes), (png.len() as u64, data.len() as u64));
+
+        // A format the provider does not take is converted even when small enough
+        let webp_only = PayloadBudget { formats: &[PayloadFormat::WebP], ..generous.clone() };
+        let (data, payload) = ImageProcessor::fit_payload(&png, &webp_only).unwrap();
+        assert_eq!(payload.format, PayloadFormat::WebP);
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::WebP);
+
+        let impossible = PayloadBudget { max_encoded_bytes: 100, ..Default::default() };
+        assert!(ImageProcessor::fit_payload(&png, &impossible).is_err());
+    }
+
+    #[test]
+    fn test_flatten_transparency_onto_white() {
+        use image::{Rgba, RgbaImage};
+
+        let img = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([0, 0, 0, 0]) } else { Rgba([0, 0, 0, 255]) });
+        let flat = ImageProcessor::flatten_to_8bit(DynamicImage::ImageRgba8(img)).to_rgb8();
+        assert_eq!(flat.get_pixel(0, 0), &Rgb([255, 255, 255]));
+        assert_eq!(flat.get_pixel(1, 0), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_orientation_follows_deskew_setting() {
+        use image::{GrayImage, Luma};
+
+        // Five lines of words, turned sideways
+        let page = GrayImage::from_fn(320, 120, |x, y| {
+            let in_line = y >= 10 && (y - 10) % 20 < 6 && y < 110;
+            let in_word = (10..310).contains(&x) && (x - 10) % 13 < 10;
+            Luma([if in_line && in_word { 0 } else { 255 }])
+        });
+        let mut data = Vec::new();
+        DynamicImage::ImageLuma8(image::imageops::rotate90(&page))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+
+        let orientation = ImageProcessor::estimate_orientation(&data, &PreprocessingConfig::default()).unwrap();
+        assert_eq!(orientation.rotation % 180, 90);
+
+        let config = PreprocessingConfig { deskew: false, ..Default::default() };
+        assert!(ImageProcessor::estimate_orientation(&data, &config).unwrap().is_upright());
+    }
+
+    #[test]
+    fn test_detect_input_type() {
+        use image::{GrayImage, Luma};
+
+        fn png(boxes: &[(u32, u32, u32, u32)], paper: u8, ink: u8) -> Vec<u8> {
+            let mut img = GrayImage::from_pixel(200, 80, Luma([paper]));
+            for &(x, y, width, height) in boxes {
+                for py in y..y + height {
+                    for px in x..x + width {
+                        img.put_pixel(px, py, Luma([ink]));
+                    }
+                }
+            }
+            let mut buffer = Vec::new();
+            DynamicImage::ImageLuma8(img).write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png).unwrap();
+            buffer
+        }
+
+        // A fraction in light ink on a dark background is one formula
+        let fraction = [(90, 30, 6, 10), (104, 30, 6, 10), (86, 43, 28, 2), (97, 48, 6, 10)];
+        let data = png(&fraction, 30, 220);
+        assert_eq!(ImageProcessor::detect_input_type(&data).unwrap(), InputType::SingleFormula);
+        let layout = ImageProcessor::analyze_image_layout(&data).unwrap();
+        assert_eq!(layout.formula_regions.len(), 1);
+        assert_eq!((layout.formula_regions[0].x, layout.formula_regions[0].y), (86, 30));
+
+        // A line of text above it makes a document
+        let mut page = fraction.to_vec();
+        page.extend((0..6).map(|letter| (20 + letter * 8, 5, 6, 10)));
+        let data = png(&page, 255, 0);
+        assert_eq!(ImageProcessor::detect_input_type(&data).unwrap(), InputType::Document);
+    }
+
+    #[test]
+    fn test_detect_two_formulas_on_a_dark_theme() {
+        // Two fractions on their own lines of an editor with a dark theme
+        let fraction = |x: u32, y: u32| [(x + 4, y, 6, 10), (x + 18, y, 6, 10), (x, y + 13, 28, 2), (x + 11, y + 18, 6, 10)];
+        let mut img = ImageBuffer::from_pixel(200, 120, Rgb([40, 42, 54]));
+        for (x, y, width, height) in fraction(40, 10).into_iter().chain(fraction(120, 70)) {
+            for py in y..y + height {
+                for px in x..x + width {
+                    img.put_pixel(px, py, Rgb([248, 248, 242]));
+                }
+            }
+        }
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(img).write_to(&mut Cursor::new(&mut data), ImageFormat::Png).unwrap();
+
+        assert!(ImageProcessor::detect_theme(&data).unwrap().dark);
+        assert_eq!(ImageProcessor::detect_input_type(&data).unwrap(), InputType::Document);
+        let layout = ImageProcessor::analyze_image_layout(&data).unwrap();
+        assert!(layout.has_multiple_formulas && !layout.has_text_content);
+        let corners: Vec<_> = layout.formula_regions.iter().map(|region| (region.x, region.y)).collect();
+        assert_eq!(corners, vec![(40, 10), (120, 70)]);
+    }
+
+    #[test]
+    fn test_photo_on_a_dark_desk_keeps_its_polarity() {
+        // The page covers less of the photo than the wooden desk around it
+        let photo = crate::photo::tests::photo();
+        let img = ImageBuffer::from_fn(photo.width(), photo.height(), |x, y| match photo.get_pixel(x, y)[0] {
+            50 => Rgb([120, 72, 40]),
+            level => Rgb([level; 3]),
+        });
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(img).write_to(&mut Cursor::new(&mut data), ImageFormat::Png).unwrap();
+        assert!(ImageProcessor::detect_theme(&data).unwrap().dark);
+
+        let config = PreprocessingConfig::for_profile(PreprocessingProfile::Photo);
+        let page = image::load_from_memory(&ImageProcessor::preprocess_image(&data, &config).unwrap()).unwrap().to_luma8();
+        let (width, height) = page.dimensions();
+        assert!((300..=320).contains(&width) && (205..=220).contains(&height), "{}x{}", width, height);
+        assert_eq!(page.get_pixel(width * 42 / 100, height / 2)[0], 0);
+        for (x, y) in [(5, 5), (width - 5, height - 5), (width / 2, height / 5)] {
+            assert_eq!(page.get_pixel(x, y)[0], 255, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_themes_normalize_to_dark_on_light() {
+        // A fraction above a line of text, as a syntax-highlighted editor would show it
+        fn png(background: [u8; 3], ink: [u8; 3]) -> Vec<u8> {
+            let boxes = [(90, 30, 6, 10), (104, 30, 6, 10), (86, 43, 28, 2), (97, 48, 6, 10)].into_iter()
+                .chain((0..6).map(|letter| (20 + letter * 8, 5, 6, 10)));
+            let mut img = ImageBuffer::from_pixel(200, 80, Rgb(background));
+            for (x, y, width, height) in boxes {
+                for py in y..y + height {
+                    for px in x..x + width {
+                        img.put_pixel(px, py, Rgb(ink));
+                    }
+                }
+            }
+            let mut buffer = Vec::new();
+            DynamicImage::ImageRgb8(img).write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png).unwrap();
+            buffer
+        }
+
+        let light = ImageProcessor::analyze_image_layout(&png([255, 255, 255], [0, 0, 0])).unwrap();
+        assert_eq!(light.formula_regions.len(), 1);
+
+        // Red on blue is lost in plain grayscale
+        for data in [png([40, 42, 54], [248, 248, 242]), png([30, 90, 200], [220, 40, 40])] {
+            let theme = ImageProcessor::detect_theme(&data).unwrap();
+            assert!(!theme.is_plain());
+            let layout = ImageProcessor::analyze_image_layout(&data).unwrap();
+            assert_eq!(serde_json::to_value(layout).unwrap(), serde_json::to_value(&light).unwrap());
+
+            let config = PreprocessingConfig { deskew: false, ..PreprocessingConfig::for_profile(PreprocessingProfile::Scan) };
+            let processed = image::load_from_memory(&ImageProcessor::preprocess_image(&data, &config).unwrap()).unwrap().to_luma8();
+            assert_eq!((processed.get_pixel(5, 70)[0], processed.get_pixel(100, 44)[0]), (255, 0));
+        }
+
+        // Without it the light writing of a dark theme binarizes as paper
+        let config = PreprocessingConfig { deskew: false, polarity: false, ..PreprocessingConfig::for_profile(PreprocessingProfile::Scan) };
+        let data = ImageProcessor::preprocess_image(&png([40, 42, 54], [248, 248, 242]), &config).unwrap();
+        assert_eq!(image::load_from_memory(&data).unwrap().to_luma8().get_pixel(100, 44)[0], 255);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,750 @@
+use crate::{preprocessing, ImageLayout, Region};
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Longest side analyzed; larger images are scaled down first
+const MAX_ANALYSIS_SIZE: u32 = 2400;
+
+/// Smallest gap between the mean gray of ink and paper that counts as content
+const MIN_CONTRAST: f32 = 40.0;
+
+// Thresholds below are fractions of the typical glyph height of a line,
+// which for text is about the x-height.
+
+/// Dots such as those on `i` or in `:`, which are merged into the glyph they sit on
+const DOT_SIZE: f32 = 0.4;
+/// How far apart a dot and its glyph may be
+const DOT_GAP: f32 = 0.6;
+/// Rules such as fraction bars and minus signs: at most this tall...
+const BAR_HEIGHT: f32 = 0.25;
+/// ...and at least this wide
+const BAR_WIDTH: f32 = 0.6;
+/// Band of rules joined with the lines above and below it when this close
+const RULE_REACH: f32 = 1.0;
+/// Bands shorter than this, like the limits of a sum, join the nearer line...
+const SMALL_BAND: f32 = 0.6;
+/// ...when this close
+const SMALL_BAND_REACH: f32 = 0.5;
+/// Column gap that separates words
+const WORD_GAP: f32 = 0.45;
+/// Integral signs, large operators and tall fences are at least this tall
+const TALL_GLYPH: f32 = 2.2;
+/// Scripts are between these heights...
+const SCRIPT_MIN_HEIGHT: f32 = 0.45;
+const SCRIPT_MAX_HEIGHT: f32 = 0.8;
+/// ...and a superscript ends this far above the baseline...
+const SUPERSCRIPT_RISE: f32 = 0.35;
+/// ...while a subscript starts below the x-height and ends this far below the baseline
+const SUBSCRIPT_DROP: f32 = 0.25;
+
+/// What a region of a page holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionKind {
+    Text,
+    InlineMath,
+    DisplayMath,
+}
+
+impl RegionKind {
+    pub fn is_math(self) -> bool {
+        matches!(self, RegionKind::InlineMath | RegionKind::DisplayMath)
+    }
+}
+
+/// A classified region with a tight bounding box
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutRegion {
+    pub kind: RegionKind,
+    /// Index of the text line, counted from the top
+    pub line: usize,
+    #[serde(flatten)]
+    pub bounds: Region,
+}
+
+/// Find text, inline math and display math in a page image
+///
+/// The image is binarized with Otsu's threshold, taking the minority class
+/// as ink so that light-on-dark images work too. Connected components are
+/// grouped into lines by the horizontal projection profile, with fraction
+/// bars and limits joined to their formula, and lines are cut into words at
+/// wide gaps in the vertical profile. Words with fraction bars, scripts or
+/// tall glyphs are math; a line without text words is display math.
+/// Regions are in reading order.
+pub fn analyze(gray: &GrayImage) -> ImageLayout {
+    let (width, height) = gray.dimensions();
+    let longest = width.max(height);
+    let (gray, scale) = if longest > MAX_ANALYSIS_SIZE {
+        let factor = MAX_ANALYSIS_SIZE as f32 / longest as f32;
+        let scaled = image::imageops::resize(
+            gray,
+            ((width as f32 * factor).round() as u32).max(1),
+            ((height as f32 * factor).round() as u32).max(1),
+            image::imageops::FilterType::Triangle,
+        );
+        (scaled, 1.0 / factor)
+    } else {
+        (gray.clone(), 1.0)
+    };
+
+    let page = Page::new(&gray);
+    let regions: Vec<LayoutRegion> = page.regions()
+        .into_iter()
+        .map(|region| LayoutRegion { bounds: scale_region(&region.bounds, scale, width, height), ..region })
+        .collect();
+
+    let formula_regions: Vec<Region> = regions.iter()
+        .filter(|region| region.kind.is_math())
+        .map(|region| region.bounds.clone())
+        .collect();
+    let text_regions: Vec<Region> = regions.iter()
+        .filter(|region| region.kind == RegionKind::Text)
+        .map(|region| region.bounds.clone())
+        .collect();
+
+    ImageLayout {
+        has_multiple_formulas: formula_regions.len() > 1,
+        has_text_content: !text_regions.is_empty(),
+        formula_regions,
+        text_regions,
+        regions,
+    }
+}
+
+fn scale_region(region: &Region, scale: f32, width: u32, height: u32) -> Region {
+    if scale == 1.0 {
+        return region.clone();
+    }
+    let x = ((region.x as f32 * scale) as u32).min(width.saturating_sub(1));
+    let y = ((region.y as f32 * scale) as u32).min(height.saturating_sub(1));
+    Region {
+        x,
+        y,
+        width: ((region.width as f32 * scale).ceil() as u32).clamp(1, width - x),
+        height: ((region.height as f32 * scale).ceil() as u32).clamp(1, height - y),
+    }
+}
+
+/// Bounding box of a connected component, edges inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Component {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+impl Component {
+    fn width(&self) -> f32 {
+        (self.right - self.left + 1) as f32
+    }
+
+    fn height(&self) -> f32 {
+        (self.bottom - self.top + 1) as f32
+    }
+
+    fn union(&self, other: &Component) -> Component {
+        Component {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn is_bar(&self, glyph_height: f32) -> bool {
+        self.height() <= BAR_HEIGHT * glyph_height
+            && self.width() >= BAR_WIDTH * glyph_height
+            && self.width() >= 3.0 * self.height()
+    }
+
+    fn region(&self) -> Region {
+        Region { x: self.left, y: self.top, width: self.width() as u32, height: self.height() as u32 }
+    }
+}
+
+/// A binarized page and its components
+struct Page {
+    ink: Vec<bool>,
+    width: usize,
+    components: Vec<Component>,
+    /// Median component height over the page
+    glyph_height: f32,
+}
+
+/// Components of one text line, left to right
+struct Line {
+    top: u32,
+    bottom: u32,
+    members: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WordKind {
+    Text,
+    Math,
+    /// Too few glyphs to tell, such as `a`, `x` or `+`
+    Unclear,
+}
+
+impl Page {
+    fn new(gray: &GrayImage) -> Self {
+        let (width, height) = (gray.width() as usize, gray.height() as usize);
+        let ink = binarize(gray);
+        let mut components = label_components(&ink, width, height);
+
+        // Frames around screenshots are not content
+        components.retain(|component| {
+            !(component.width() >= 0.95 * width as f32 && component.height() >= 0.95 * height as f32)
+        });
+
+        let glyph_height = median(components.iter().map(Component::height).filter(|&height| height >= 3.0))
+            .or_else(|| median(components.iter().map(Component::height)))
+            .unwrap_or(1.0);
+        let components = merge_dots(components, glyph_height);
+
+        Self { ink, width, components, glyph_height }
+    }
+
+    fn regions(&self) -> Vec<LayoutRegion> {
+        let mut regions = Vec::new();
+        for (index, line) in self.lines().iter().enumerate() {
+            self.classify_line(index, line, &mut regions);
+        }
+        regions
+    }
+
+    /// Bands of the horizontal projection profile, with rules and small bands joined to their formula
+    fn lines(&self) -> Vec<Line> {
+        let mut profile = vec![0u32; self.ink.len() / self.width.max(1)];
+        for (index, &ink) in self.ink.iter().enumerate() {
+            if ink {
+                profile[index / self.width] += 1;
+            }
+        }
+
+        let mut lines: Vec<Line> = Vec::new();
+        let mut start = None;
+        for (row, &count) in profile.iter().chain(std::iter::once(&0)).enumerate() {
+            match (count > 0, start) {
+                (true, None) => start = Some(row as u32),
+                (false, Some(top)) => {
+                    lines.push(Line { top, bottom: row as u32 - 1, members: Vec::new() });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        // A component is connected, so it lies within one band
+        for component in &self.components {
+            if let Some(line) = lines.iter_mut().find(|line| line.top <= component.top && component.bottom <= line.bottom) {
+                line.members.push(*component);
+            }
+        }
+        lines.retain(|line| !line.members.is_empty());
+
+        let glyph_height = self.glyph_height;
+        let gap = |above: &Line, below: &Line| (below.top - above.bottom - 1) as f32;
+        'merging: loop {
+            for index in 0..lines.len() {
+                let line = &lines[index];
+                let is_rule = line.members.iter().all(|component| component.is_bar(glyph_height));
+                let is_small = ((line.bottom - line.top + 1) as f32) < SMALL_BAND * glyph_height;
+                if !is_rule && !is_small {
+                    continue;
+                }
+
+                let above = index.checked_sub(1).map(|above| gap(&lines[above], line));
+                let below = lines.get(index + 1).map(|below| gap(line, below));
+                let (join_above, join_below) = if is_rule {
+                    let reach = RULE_REACH * glyph_height;
+                    (above.is_some_and(|gap| gap <= reach), below.is_some_and(|gap| gap <= reach))
+                } else {
+                    let reach = SMALL_BAND_REACH * glyph_height;
+                    match (above.filter(|&gap| gap <= reach), below.filter(|&gap| gap <= reach)) {
+                        (Some(above), Some(below)) => (above <= below, below < above),
+                        (above, below) => (above.is_some(), below.is_some()),
+                    }
+                };
+
+                if join_below {
+                    let below = lines.remove(index + 1);
+                    join(&mut lines[index], below);
+                }
+                if join_above {
+                    let line = lines.remove(index);
+                    join(&mut lines[index - 1], line);
+                }
+                if join_above || join_below {
+                    continue 'merging;
+                }
+            }
+            break;
+        }
+
+        for line in &mut lines {
+            line.members.sort_by_key(|component| component.left);
+        }
+        lines
+    }
+
+    /// Components of `line` split at wide gaps in its vertical projection profile
+    fn words(&self, line: &Line, glyph_height: f32) -> Vec<Vec<Component>> {
+        let mut profile = vec![0u32; self.width];
+        for row in line.top as usize..=line.bottom as usize {
+            for (column, count) in profile.iter_mut().enumerate() {
+                if self.ink[row * self.width + column] {
+                    *count += 1;
+                }
+            }
+        }
+
+        // Column ranges of words
+        let word_gap = (WORD_GAP * glyph_height).max(2.0);
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        let mut gap = 0;
+        for (column, &count) in profile.iter().enumerate() {
+            if count == 0 {
+                gap += 1;
+                continue;
+            }
+            match spans.last_mut() {
+                Some(span) if (gap as f32) < word_gap => span.1 = column,
+                _ => spans.push((column, column)),
+            }
+            gap = 0;
+        }
+
+        spans.iter()
+            .map(|&(left, right)| {
+                line.members.iter()
+                    .filter(|component| (left..=right).contains(&(component.left as usize)))
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|word| !word.is_empty())
+            .collect()
+    }
+
+    fn classify_line(&self, index: usize, line: &Line, regions: &mut Vec<LayoutRegion>) {
+        let glyph_height = median(
+            line.members.iter()
+                .filter(|component| !component.is_bar(self.glyph_height) && component.height() > DOT_SIZE * self.glyph_height)
+                .map(Component::height),
+        )
+        .unwrap_or(self.glyph_height);
+        let baseline = median(
+            line.members.iter()
+                .filter(|component| (0.7..=1.3).contains(&(component.height() / glyph_height)))
+                .map(|component| component.bottom as f32),
+        )
+        .unwrap_or(line.bottom as f32);
+
+        let words = self.words(line, glyph_height);
+        let mut kinds: Vec<WordKind> = words.iter().map(|word| word_kind(word, glyph_height, baseline)).collect();
+
+        if !kinds.contains(&WordKind::Text) {
+            // A formula on its own line, unless it is a lone short word such as a list number
+            let lone_word = words.len() == 1 && words[0].len() <= 2;
+            if kinds.contains(&WordKind::Math) || !lone_word {
+                push_region(regions, RegionKind::DisplayMath, index, &line.members);
+                return;
+            }
+        }
+
+        // Short words next to a formula, like `+` or `y` in `x^2 + y`, belong to it
+        let mut spreading = true;
+        while spreading {
+            spreading = false;
+            for word in 0..kinds.len() {
+                let beside_math = (word > 0 && kinds[word - 1] == WordKind::Math)
+                    || kinds.get(word + 1) == Some(&WordKind::Math);
+                if kinds[word] == WordKind::Unclear && beside_math {
+                    kinds[word] = WordKind::Math;
+                    spreading = true;
+                }
+            }
+        }
+
+        let mut start = 0;
+        while start < words.len() {
+            let is_math = kinds[start] == WordKind::Math;
+            let end = (start..words.len())
+                .find(|&word| (kinds[word] == WordKind::Math) != is_math)
+                .unwrap_or(words.len());
+            let kind = if is_math { RegionKind::InlineMath } else { RegionKind::Text };
+            push_region(regions, kind, index, &words[start..end].concat());
+            start = end;
+        }
+    }
+}
+
+fn join(line: &mut Line, other: Line) {
+    line.top = line.top.min(other.top);
+    line.bottom = line.bottom.max(other.bottom);
+    line.members.extend(other.members);
+}
+
+fn push_region(regions: &mut Vec<LayoutRegion>, kind: RegionKind, line: usize, components: &[Component]) {
+    if let Some(bounds) = components.iter().copied().reduce(|bounds, component| bounds.union(&component)) {
+        regions.push(LayoutRegion { kind, line, bounds: bounds.region() });
+    }
+}
+
+fn word_kind(word: &[Component], glyph_height: f32, baseline: f32) -> WordKind {
+    let is_math = word.iter().any(|component| {
+        let height = component.height() / glyph_height;
+        let top = (component.top as f32 - baseline) / glyph_height;
+        let bottom = (component.bottom as f32 - baseline) / glyph_height;
+        let is_script = (SCRIPT_MIN_HEIGHT..=SCRIPT_MAX_HEIGHT).contains(&height);
+
+        component.is_bar(glyph_height)
+            || height >= TALL_GLYPH
+            || (is_script && bottom <= -SUPERSCRIPT_RISE)
+            || (height >= SCRIPT_MIN_HEIGHT && top >= -(1.0 - SCRIPT_MIN_HEIGHT) && bottom >= SUBSCRIPT_DROP)
+    });
+
+    if is_math {
+        WordKind::Math
+    } else if word.len() >= 3 {
+        WordKind::Text
+    } else {
+        WordKind::Unclear
+    }
+}
+
+/// Ink mask by Otsu's threshold, or an empty mask for a blank image
+fn binarize(gray: &GrayImage) -> Vec<bool> {
+    let histogram = preprocessing::histogram(gray);
+    let threshold = match preprocessing::otsu_threshold(&histogram) {
+        Some((threshold, contrast)) if contrast >= MIN_CONTRAST => threshold as usize,
+        _ => return vec![false; gray.len()],
+    };
+
+    // Ink is whichever side of the threshold covers less of the page
+    let dark: u64 = histogram[..=threshold].iter().sum();
+    let dark_is_ink = dark * 2 <= gray.len() as u64;
+    gray.pixels().map(|pixel| (pixel[0] as usize <= threshold) == dark_is_ink).collect()
+}
+
+/// Bounding boxes of 8-connected components, in order of their first pixel
+fn label_components(ink: &[bool], width: usize, height: usize) -> Vec<Component> {
+    fn root(parents: &mut [usize], mut label: usize) -> usize {
+        while parents[label] != label {
+            parents[label] = parents[parents[label]];
+            label = parents[label];
+        }
+        label
+    }
+
+    // Label 0 is the background
+    let mut labels = vec![0usize; ink.len()];
+    let mut parents = vec![0usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            if !ink[index] {
+                continue;
+            }
+
+            let mut neighbours = [0usize; 4];
+            if x > 0 {
+                neighbours[0] = labels[index - 1];
+            }
+            if y > 0 {
+                neighbours[1] = labels[index - width];
+                if x > 0 {
+                    neighbours[2] = labels[index - width - 1];
+                }
+                if x + 1 < width {
+                    neighbours[3] = labels[index - width + 1];
+                }
+            }
+
+            let mut label = 0;
+            for neighbour in neighbours.into_iter().filter(|&neighbour| neighbour != 0) {
+                let neighbour = root(&mut parents, neighbour);
+                if label == 0 {
+                    label = neighbour;
+                } else if neighbour != label {
+                    let (low, high) = (label.min(neighbour), label.max(neighbour));
+                    parents[high] = low;
+                    label = low;
+                }
+            }
+            if label == 0 {
+                label = parents.len();
+                parents.push(label);
+            }
+            labels[index] = label;
+        }
+    }
+
+    let mut boxes: Vec<Option<Component>> = vec![None; parents.len()];
+    let mut order = Vec::new();
+    for (index, &label) in labels.iter().enumerate() {
+        if label == 0 {
+            continue;
+        }
+        let label = root(&mut parents, label);
+        let (x, y) = ((index % width) as u32, (index / width) as u32);
+        let pixel = Component { left: x, top: y, right: x, bottom: y };
+        boxes[label] = Some(match boxes[label] {
+            Some(component) => component.union(&pixel),
+            None => {
+                order.push(label);
+                pixel
+            }
+        });
+    }
+
+    order.into_iter().filter_map(|label| boxes[label]).collect()
+}
+
+/// Merge dots into the glyph above or below them, as for `i`, `j` and `ä`
+///
+/// Components are bucketed into bands of rows one glyph high, so each dot
+/// only compares itself with the components near its own rows.
+fn merge_dots(mut components: Vec<Component>, glyph_height: f32) -> Vec<Component> {
+    let is_dot = |component: &Component| {
+        component.width() <= DOT_SIZE * glyph_height && component.height() <= DOT_SIZE * glyph_height
+    };
+    let max_gap = (DOT_GAP * glyph_height).floor() as u32;
+    let band_height = (glyph_height.ceil() as u32).max(1);
+    let bands_of = |component: &Component| component.top / band_height..=component.bottom / band_height;
+
+    let mut bands: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (index, component) in components.iter().enumerate() {
+        for band in bands_of(component) {
+            bands.entry(band).or_default().push(index);
+        }
+    }
+
+    let mut merged = vec![false; components.len()];
+    for index in 0..components.len() {
+        let dot = components[index];
+        if !is_dot(&dot) || merged[index] {
+            continue;
+        }
+
+        let center = (dot.left + dot.right) / 2;
+        let first_band = dot.top.saturating_sub(max_gap) / band_height;
+        let last_band = (dot.bottom + max_gap) / band_height;
+        let target = (first_band..=last_band)
+            .filter_map(|band| bands.get(&band))
+            .flatten()
+            .copied()
+            .filter(|&other| other != index && !merged[other])
+            .filter(|&other| {
+                let component = &components[other];
+                let gap = if component.top > dot.bottom {
+                    component.top - dot.bottom
+                } else {
+                    dot.top.saturating_sub(component.bottom)
+                };
+                component.left <= center && center <= component.right && gap <= max_gap
+            })
+            // The largest glyph wins, and among equal sizes the later glyph wins
+            .max_by(|&a, &b| {
+                let area = |component: &Component| component.width() * component.height();
+                area(&components[a]).total_cmp(&area(&components[b])).then(a.cmp(&b))
+            });
+
+        if let Some(target) = target {
+            let old_bands = bands_of(&components[target]);
+            components[target] = components[target].union(&dot);
+            merged[index] = true;
+            for band in bands_of(&components[target]).filter(|band| !old_bands.contains(band)) {
+                bands.entry(band).or_default().push(target);
+            }
+        }
+    }
+
+    components.into_iter()
+        .zip(merged)
+        .filter_map(|(component, merged)| (!merged).then_some(component))
+        .collect()
+}
+
+fn median(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let mut values: Vec<f32> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    Some(values[values.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// Canvas with glyphs drawn as filled boxes of ink
+    struct Canvas(GrayImage);
+
+    impl Canvas {
+        fn new(width: u32, height: u32) -> Self {
+            Self(GrayImage::from_pixel(width, height, Luma([255])))
+        }
+
+        fn glyph(&mut self, x: u32, y: u32, width: u32, height: u32) -> &mut Self {
+            for py in y..y + height {
+                for px in x..x + width {
+                    self.0.put_pixel(px, py, Luma([20]));
+                }
+            }
+            self
+        }
+
+        /// `letters` boxes 6x10 with their baseline at `baseline`, 2px apart; returns the column after the word
+        fn word(&mut self, x: u32, baseline: u32, letters: u32) -> u32 {
+            for letter in 0..letters {
+                self.glyph(x + letter * 8, baseline - 10, 6, 10);
+            }
+            x + letters * 8 - 2
+        }
+
+        /// Numerator, bar and denominator centered on `center`
+        fn fraction(&mut self, center: u32, top: u32) {
+            self.glyph(center - 10, top, 6, 10).glyph(center + 4, top, 6, 10);
+            self.glyph(center - 14, top + 13, 28, 2);
+            self.glyph(center - 3, top + 18, 6, 10);
+        }
+    }
+
+    fn regions(layout: &ImageLayout) -> Vec<(RegionKind, usize, u32, u32, u32, u32)> {
+        layout.regions.iter()
+            .map(|region| (region.kind, region.line, region.bounds.x, region.bounds.y, region.bounds.width, region.bounds.height))
+            .collect()
+    }
+
+    #[test]
+    fn test_blank_and_low_contrast_images() {
+        let blank = analyze(&GrayImage::from_pixel(50, 50, Luma([255])));
+        assert!(blank.regions.is_empty());
+
+        let faint = analyze(&GrayImage::from_fn(50, 50, |x, _| Luma([230 + (x % 2) as u8 * 10])));
+        assert!(faint.regions.is_empty());
+    }
+
+    #[test]
+    fn test_text_lines_with_dots_and_display_fraction() {
+        let mut canvas = Canvas::new(220, 120);
+        // "iiii word" with dots above the first word, then a display fraction, then text
+        for letter in 0..4 {
+            canvas.glyph(10 + letter * 8, 13, 2, 2).glyph(10 + letter * 8, 17, 2, 8);
+        }
+        canvas.word(50, 25, 5);
+        canvas.fraction(110, 40);
+        canvas.word(10, 100, 4);
+        canvas.word(50, 100, 6);
+
+        let layout = analyze(&canvas.0);
+        assert_eq!(regions(&layout), vec![
+            (RegionKind::Text, 0, 10, 13, 78, 12),
+            (RegionKind::DisplayMath, 1, 96, 40, 28, 28),
+            (RegionKind::Text, 2, 10, 90, 86, 10),
+        ]);
+        assert!(layout.has_text_content);
+        assert!(!layout.has_multiple_formulas);
+        assert_eq!(layout.formula_regions.len(), 1);
+    }
+
+    #[test]
+    fn test_inline_math_with_scripts_and_neighbours() {
+        let mut canvas = Canvas::new(260, 40);
+        let end = canvas.word(10, 30, 4);
+        // x^2 with the superscript well above the baseline
+        canvas.glyph(end + 8, 20, 6, 10).glyph(end + 16, 14, 4, 6);
+        // "+ y" next to it, then more text
+        canvas.glyph(end + 28, 22, 6, 6);
+        canvas.glyph(end + 40, 20, 6, 10);
+        canvas.word(end + 56, 30, 5);
+
+        let layout = analyze(&canvas.0);
+        assert_eq!(regions(&layout), vec![
+            (RegionKind::Text, 0, 10, 20, 30, 10),
+            (RegionKind::InlineMath, 0, 48, 14, 38, 16),
+            (RegionKind::Text, 0, 96, 20, 38, 10),
+        ]);
+    }
+
+    #[test]
+    fn test_subscripts_and_tall_glyphs_are_math() {
+        let mut canvas = Canvas::new(120, 60);
+        // An integral sign followed by x_i on a line of its own
+        canvas.glyph(10, 10, 4, 40).glyph(20, 25, 6, 10).glyph(28, 30, 4, 6);
+        let layout = analyze(&canvas.0);
+        assert_eq!(regions(&layout), vec![(RegionKind::DisplayMath, 0, 10, 10, 22, 40)]);
+
+        // A lone list number is not a formula
+        let mut canvas = Canvas::new(60, 30);
+        canvas.glyph(10, 10, 6, 10).glyph(18, 18, 2, 2);
+        let layout = analyze(&canvas.0);
+        assert_eq!(regions(&layout), vec![(RegionKind::Text, 0, 10, 10, 10, 10)]);
+    }
+
+    #[test]
+    fn test_dark_background_and_large_images() {
+        let mut canvas = Canvas::new(220, 60);
+        canvas.word(10, 30, 4);
+        canvas.fraction(120, 15);
+        let light = regions(&analyze(&canvas.0));
+
+        let mut dark = canvas.0.clone();
+        image::imageops::invert(&mut dark);
+        assert_eq!(regions(&analyze(&dark)), light);
+
+        // Scaled down for analysis, boxes come back in full-size coordinates
+        let large = image::imageops::resize(&canvas.0, 4400, 1200, image::imageops::FilterType::Nearest);
+        let scaled = regions(&analyze(&large));
+        assert_eq!(scaled.len(), light.len());
+        for (scaled, light) in scaled.iter().zip(&light) {
+            assert_eq!((scaled.0, scaled.1), (light.0, light.1));
+            assert!((scaled.2 as i64 - light.2 as i64 * 20).abs() <= 20, "{:?} {:?}", scaled, light);
+            assert!((scaled.4 as i64 - light.4 as i64 * 20).abs() <= 40, "{:?} {:?}", scaled, light);
+        }
+    }
+
+    #[test]
+    fn test_merge_dots_into_nearest_glyph() {
+        let glyph = |left, top, right, bottom| Component { left, top, right, bottom };
+        let components = vec![
+            // `i`: dot above its stem
+            glyph(10, 0, 12, 2),
+            glyph(10, 5, 12, 19),
+            // `:` on its own, whose dots join each other
+            glyph(30, 8, 32, 10),
+            glyph(30, 15, 32, 17),
+            // A period too far below the `i` to belong to it
+            glyph(10, 40, 12, 42),
+        ];
+
+        assert_eq!(merge_dots(components, 10.0), vec![
+            glyph(10, 0, 12, 19),
+            glyph(30, 8, 32, 17),
+            glyph(10, 40, 12, 42),
+        ]);
+    }
+
+    #[test]
+    fn test_merge_dots_on_a_full_page() {
+        // Every glyph of a dense page is an `i`
+        let mut components = Vec::new();
+        for row in 0..200 {
+            for column in 0..100 {
+                let (left, top) = (column * 10, row * 30);
+                components.push(Component { left, top, right: left + 2, bottom: top + 2 });
+                components.push(Component { left, top: top + 5, right: left + 2, bottom: top + 19 });
+            }
+        }
+
+        let merged = merge_dots(components, 15.0);
+        assert_eq!(merged.len(), 200 * 100);
+        assert!(merged.iter().all(|component| component.height() == 20.0));
+    }
+}
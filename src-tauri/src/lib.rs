@@ -1,24 +1,83 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use tauri::Emitter;
 
 pub mod error;
 pub use error::{MathSeekError, MathSeekResult};
 
 pub mod image_processor;
-pub use image_processor::ImageProcessor;
+pub use image_processor::{ImageProcessor, ImagePayload, PayloadBudget, PayloadFormat};
+
+pub mod clipboard;
+
+pub mod screenshot;
+pub use screenshot::{ScreenshotOptions, Screenshot, CapturedScreenshot, MonitorInfo};
+
+pub mod preprocessing;
+pub use preprocessing::{PreprocessingConfig, PreprocessingProfile, PreprocessingStage, PreprocessingStep, Binarization};
+
+pub mod photo;
+
+pub mod theme;
+pub use theme::Theme;
+
+pub mod orientation;
+pub use orientation::Orientation;
+
+pub mod layout;
+pub use layout::{LayoutRegion, RegionKind};
 
 pub mod api_client;
 pub use api_client::{ApiClient, ApiConfig};
+pub use rate_limiter::RateLimitStatus;
+
+pub mod network;
+pub use network::NetworkConfig;
+
+pub mod circuit_breaker;
+
+pub mod cassette;
+
+pub mod rate_limiter;
+
+pub mod usage;
+pub use usage::{TokenUsage, ProviderSpend, ModelPrice};
+
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock_server;
+
+pub mod recognition_backend;
+pub use recognition_backend::RecognitionBackend;
+
+pub mod prompt_templates;
+pub use prompt_templates::PromptTemplates;
+
+pub mod openai_backend;
+
+pub mod ollama_backend;
+
+pub mod mathpix_backend;
 
 pub mod config_manager;
 pub use config_manager::{ConfigManager, ConfigValidation};
 
 pub mod recognition_engine;
-pub use recognition_engine::{RecognitionEngine, RecognitionConfig, RecognitionStats};
+pub use recognition_engine::{
+    RecognitionEngine, RecognitionConfig, RecognitionStats, RecognitionPartial, RecognitionFinal,
+    RecognitionProvenance, SkippedProvider, SkipReason
+};
+
+pub mod ensemble;
+pub use ensemble::{EnsembleConfig, RecognitionAlternative};
+
+pub mod recognition_jobs;
+pub use recognition_jobs::{RecognitionJobs, RecognitionJob, RecognitionStarted, CancellationToken};
 
 pub mod export_manager;
 pub use export_manager::{ExportManager, ExportConfig, ExportResult, ExportMetadata};
 
+pub mod mathml;
+
 #[cfg(test)]
 mod models_test;
 
@@ -71,11 +130,60 @@ pub struct MarkdownFormulaFormat {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
+    #[serde(default = "recognition_backend::default_backend_name")]
+    pub backend: String,
     pub api_endpoint: String,
     pub api_key: String,
+    #[serde(default)]
+    pub app_id: String,
+    #[serde(default)]
+    pub model: String,
+    /// Client-side request rate limit for the provider; 0 for no limit
+    #[serde(default)]
+    pub requests_per_minute: u32,
+    /// Client-side cap on simultaneous requests to the provider; 0 for no limit
+    #[serde(default)]
+    pub max_concurrent_requests: u32,
+    /// Prices used to estimate the cost of each provider call
+    #[serde(default = "usage::default_price_table")]
+    pub model_prices: usage::PriceTable,
+    /// Providers tried in order when the primary one is down or unsure
+    #[serde(default)]
+    pub fallback_providers: Vec<ProviderConfig>,
+    /// Upload limit for images in bytes of base64; 0 for the backend's own limit
+    #[serde(default)]
+    pub max_payload_bytes: u64,
+    /// Proxy and TLS settings shared by all providers
+    #[serde(default)]
+    pub network: NetworkConfig,
     pub default_export_format: HashMap<InputType, ExportFormat>,
     pub render_engine: RenderEngine,
     pub markdown_formula_format: MarkdownFormulaFormat,
+    /// System prompts for chat model backends
+    #[serde(default)]
+    pub prompt_templates: PromptTemplates,
+    /// Stages run on images before recognition, unless a command is given a profile
+    #[serde(default)]
+    pub preprocessing: PreprocessingConfig,
+}
+
+/// Connection settings of a fallback provider
+///
+/// Rate limits and prices are shared with the primary provider in `AppConfig`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    #[serde(default = "recognition_backend::default_backend_name")]
+    pub backend: String,
+    pub api_endpoint: String,
+    #[serde(default)]
+    pub api_key: String,
+    #[serde(default)]
+    pub app_id: String,
+    #[serde(default)]
+    pub model: String,
+    /// Upload limit for images in bytes of base64; 0 for the backend's own limit
+    #[serde(default)]
+    pub max_payload_bytes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,6 +193,17 @@ pub struct FormulaResult {
     pub timestamp: u64,
     pub input_type: InputType,
     pub content: ResultContent,
+    #[serde(default)]
+    pub token_usage: Option<TokenUsage>,
+    /// Provider that produced the result and why earlier ones were skipped
+    #[serde(default)]
+    pub provenance: Option<RecognitionProvenance>,
+    /// Answers that lost an ensemble vote, most votes first
+    #[serde(default)]
+    pub alternatives: Vec<RecognitionAlternative>,
+    /// Image uploaded to the provider
+    #[serde(default)]
+    pub payload: Option<ImagePayload>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -119,6 +238,22 @@ pub struct AnalysisResult {
     pub description: String,
     pub usage: String,
     pub examples: Vec<String>,
+    #[serde(default)]
+    pub variables: Vec<FormulaVariable>,
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub related_formulas: Vec<String>,
+    #[serde(default)]
+    pub token_usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormulaVariable {
+    pub symbol: String,
+    pub description: String,
+    #[serde(default)]
+    pub unit: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -127,6 +262,9 @@ pub struct ImageLayout {
     pub has_text_content: bool,
     pub formula_regions: Vec<Region>,
     pub text_regions: Vec<Region>,
+    /// Text, inline math and display math regions in reading order
+    #[serde(default)]
+    pub regions: Vec<LayoutRegion>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -147,72 +285,115 @@ pub struct SystemStatus {
 
 // Tauri commands for MathSeek
 #[tauri::command]
-async fn get_app_version() -> Result<String, String> {
+async fn get_app_version() -> Result<String, MathSeekError> {
     Ok(env!("CARGO_PKG_VERSION").to_string())
 }
 
 // Image input commands
+/// Capture the screen, or the region the user drags out when `interactive`,
+/// as base64 ready for `recognize_content_auto`
 #[tauri::command]
-async fn capture_screenshot() -> Result<String, String> {
-    match ImageProcessor::capture_screenshot().await {
-        Ok(image_data) => {
-            ImageProcessor::image_to_base64(&image_data)
-                .map_err(|e| e.to_string())
-        }
-        Err(e) => Err(e.to_string())
-    }
+async fn capture_screenshot(options: Option<ScreenshotOptions>) -> Result<CapturedScreenshot, MathSeekError> {
+    let screenshot = ImageProcessor::capture_screenshot(&options.unwrap_or_default()).await?;
+    Ok(CapturedScreenshot {
+        image: ImageProcessor::image_to_base64(&screenshot.png)?,
+        leftover_file: screenshot.leftover_file,
+    })
 }
 
 #[tauri::command]
-async fn get_clipboard_image() -> Result<Option<String>, String> {
-    match ImageProcessor::get_clipboard_image().await {
-        Ok(Some(image_data)) => {
-            match ImageProcessor::image_to_base64(&image_data) {
-                Ok(base64_str) => Ok(Some(base64_str)),
-                Err(e) => Err(e.to_string())
-            }
-        }
-        Ok(None) => Ok(None),
-        Err(e) => Err(e.to_string())
-    }
+async fn list_monitors() -> Result<Vec<MonitorInfo>, MathSeekError> {
+    screenshot::list_monitors().await
+}
+
+#[tauri::command]
+async fn get_clipboard_image() -> Result<Option<String>, MathSeekError> {
+    ImageProcessor::get_clipboard_image().await?
+        .map(|image_data| ImageProcessor::image_to_base64(&image_data))
+        .transpose()
 }
 
 #[tauri::command]
-async fn validate_image_data(base64_data: String) -> Result<bool, String> {
-    match ImageProcessor::base64_to_image(&base64_data) {
-        Ok(image_data) => Ok(ImageProcessor::validate_image(&image_data)),
-        Err(e) => Err(e.to_string())
+async fn validate_image_data(base64_data: String) -> Result<bool, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
+    
+    Ok(ImageProcessor::validate_image(&image_data))
+}
+
+/// Stages for an image command: the given ones, a profile's, or the saved ones
+async fn preprocessing_config(
+    config: Option<PreprocessingConfig>,
+    profile: Option<PreprocessingProfile>,
+) -> MathSeekResult<PreprocessingConfig> {
+    if let Some(config) = config {
+        return Ok(config);
+    }
+    if let Some(profile) = profile {
+        return Ok(PreprocessingConfig::for_profile(profile));
     }
+    let saved = ConfigManager::new()?.load_config().await?;
+    Ok(saved.map(|config| config.preprocessing).unwrap_or_default())
 }
 
 #[tauri::command]
-async fn preprocess_image(base64_data: String) -> Result<String, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn preprocess_image(
+    base64_data: String,
+    config: Option<PreprocessingConfig>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<String, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
+    let config = preprocessing_config(config, profile).await?;
     
-    let processed_data = ImageProcessor::preprocess_image(&image_data)
-        .map_err(|e| e.to_string())?;
+    let processed_data = ImageProcessor::preprocess_image(&image_data, &config)?;
     
     ImageProcessor::image_to_base64(&processed_data)
-        .map_err(|e| e.to_string())
 }
 
+/// Every intermediate image of preprocessing, for inspecting the pipeline
 #[tauri::command]
-async fn get_image_info(base64_data: String) -> Result<serde_json::Value, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn preprocess_image_stages(
+    base64_data: String,
+    config: Option<PreprocessingConfig>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<Vec<PreprocessingStep>, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
+    let config = preprocessing_config(config, profile).await?;
+
+    let stages = tokio::task::spawn_blocking(move || ImageProcessor::preprocess_image_stages(&image_data, &config))
+        .await??;
+
+    stages.into_iter()
+        .map(|(stage, image)| Ok(PreprocessingStep { stage, image: ImageProcessor::image_to_base64(&image)? }))
+        .collect()
+}
+
+/// Size, suitability, theme and the rotation that preprocessing applies
+#[tauri::command]
+async fn get_image_info(
+    base64_data: String,
+    config: Option<PreprocessingConfig>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<serde_json::Value, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
+    let config = preprocessing_config(config, profile).await?;
     
-    let (width, height) = ImageProcessor::get_image_dimensions(&image_data)
-        .map_err(|e| e.to_string())?;
+    let (width, height) = ImageProcessor::get_image_dimensions(&image_data)?;
     
-    let is_suitable = ImageProcessor::is_image_suitable_for_processing(&image_data)
-        .map_err(|e| e.to_string())?;
+    let is_suitable = ImageProcessor::is_image_suitable_for_processing(&image_data)?;
+
+    let orientation = ImageProcessor::estimate_orientation(&image_data, &config)?;
+
+    let theme = ImageProcessor::detect_theme(&image_data)?;
     
     let info = serde_json::json!({
         "width": width,
         "height": height,
         "size": image_data.len(),
-        "is_suitable": is_suitable
+        "is_suitable": is_suitable,
+        "rotation": orientation.rotation,
+        "skew": orientation.skew,
+        "angle": orientation.angle(),
+        "theme": theme
     });
     
     Ok(info)
@@ -220,32 +401,26 @@ async fn get_image_info(base64_data: String) -> Result<serde_json::Value, String
 
 // Input type detection commands
 #[tauri::command]
-async fn detect_input_type(base64_data: String) -> Result<String, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn detect_input_type(base64_data: String) -> Result<String, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
     
-    let input_type = ImageProcessor::detect_input_type(&image_data)
-        .map_err(|e| e.to_string())?;
+    let input_type = ImageProcessor::detect_input_type(&image_data)?;
     
     Ok(input_type.into())
 }
 
 #[tauri::command]
-async fn analyze_image_layout(base64_data: String) -> Result<ImageLayout, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn analyze_image_layout(base64_data: String) -> Result<ImageLayout, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
     
     ImageProcessor::analyze_image_layout(&image_data)
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn get_detection_confidence(base64_data: String) -> Result<f32, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn get_detection_confidence(base64_data: String) -> Result<f32, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
     
-    let layout = ImageProcessor::analyze_image_layout(&image_data)
-        .map_err(|e| e.to_string())?;
+    let layout = ImageProcessor::analyze_image_layout(&image_data)?;
     
     // Calculate confidence based on layout analysis
     let confidence = calculate_detection_confidence(&layout);
@@ -276,10 +451,10 @@ fn calculate_detection_confidence(layout: &ImageLayout) -> f32 {
 }
 
 #[tauri::command]
-async fn check_system_status() -> Result<SystemStatus, String> {
+async fn check_system_status() -> Result<SystemStatus, MathSeekError> {
     let status = SystemStatus {
         clipboard_available: true,
-        screenshot_available: true,
+        screenshot_available: screenshot::is_available(),
         api_configured: false,
         render_engine_ready: true,
     };
@@ -287,159 +462,298 @@ async fn check_system_status() -> Result<SystemStatus, String> {
 }
 
 #[tauri::command]
-async fn save_config(config: AppConfig) -> Result<(), String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn save_config(config: AppConfig) -> Result<(), MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.save_config(&config).await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn load_config() -> Result<Option<AppConfig>, String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn load_config() -> Result<Option<AppConfig>, MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.load_config().await
-        .map_err(|e| e.to_string())
 }
 
 // API client commands
 #[tauri::command]
-async fn test_api_connection(config: AppConfig) -> Result<bool, String> {
-    let api_client = ApiClient::from_app_config(&config)
-        .map_err(|e| e.to_string())?;
+async fn test_api_connection(config: AppConfig) -> Result<bool, MathSeekError> {
+    let api_client = ApiClient::from_app_config(&config)?;
     
     api_client.test_connection().await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn recognize_formula(base64_data: String, input_type: String, config: AppConfig) -> Result<FormulaResult, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn recognize_formula(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, RecognitionJobs>,
+    base64_data: String,
+    input_type: String,
+    config: AppConfig,
+    job_id: Option<String>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<FormulaResult, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
     
-    let input_type_enum = InputType::try_from(input_type)
-        .map_err(|e| e.to_string())?;
+    let input_type_enum = InputType::try_from(input_type)?;
     
-    let recognition_engine = RecognitionEngine::new(&config)
-        .map_err(|e| e.to_string())?;
+    let job = start_job(&app, &jobs, job_id)?;
+    let recognition_engine = recognition_engine(&config, profile, &job)?;
     
     recognition_engine.recognize_content(image_data, Some(input_type_enum)).await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn recognize_content_auto(base64_data: String, config: AppConfig) -> Result<FormulaResult, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn recognize_content_auto(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, RecognitionJobs>,
+    base64_data: String,
+    config: AppConfig,
+    job_id: Option<String>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<FormulaResult, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
     
-    let recognition_engine = RecognitionEngine::new(&config)
-        .map_err(|e| e.to_string())?;
+    let job = start_job(&app, &jobs, job_id)?;
+    let recognition_engine = recognition_engine(&config, profile, &job)?;
     
     recognition_engine.recognize_content(image_data, None).await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn re_recognize_with_type(base64_data: String, forced_type: String, config: AppConfig) -> Result<FormulaResult, String> {
-    let image_data = ImageProcessor::base64_to_image(&base64_data)
-        .map_err(|e| e.to_string())?;
+async fn re_recognize_with_type(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, RecognitionJobs>,
+    base64_data: String,
+    forced_type: String,
+    config: AppConfig,
+    job_id: Option<String>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<FormulaResult, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
     
-    let input_type_enum = InputType::try_from(forced_type)
-        .map_err(|e| e.to_string())?;
+    let input_type_enum = InputType::try_from(forced_type)?;
     
-    let recognition_engine = RecognitionEngine::new(&config)
-        .map_err(|e| e.to_string())?;
+    let job = start_job(&app, &jobs, job_id)?;
+    let recognition_engine = recognition_engine(&config, profile, &job)?;
     
     recognition_engine.re_recognize_with_type(image_data, input_type_enum).await
-        .map_err(|e| e.to_string())
 }
 
+/// Recognize with every configured provider and return the majority answer
+// Command arguments are the fields of the frontend's invoke payload
+#[allow(clippy::too_many_arguments)]
 #[tauri::command]
-async fn get_recognition_stats(config: AppConfig) -> Result<RecognitionStats, String> {
-    let recognition_engine = RecognitionEngine::new(&config)
-        .map_err(|e| e.to_string())?;
+async fn recognize_ensemble(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, RecognitionJobs>,
+    base64_data: String,
+    input_type: Option<String>,
+    config: AppConfig,
+    ensemble: Option<EnsembleConfig>,
+    job_id: Option<String>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<FormulaResult, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
+    
+    let input_type_enum = input_type.map(InputType::try_from).transpose()?;
+    
+    let job = start_job(&app, &jobs, job_id)?;
+    let recognition_engine = recognition_engine(&config, profile, &job)?;
+    
+    recognition_engine
+        .recognize_ensemble(image_data, input_type_enum, &ensemble.unwrap_or_default())
+        .await
+}
+
+/// Engine for a recognition job, with the configured stages unless a profile is given
+fn recognition_engine(config: &AppConfig, profile: Option<PreprocessingProfile>, job: &RecognitionJob) -> MathSeekResult<RecognitionEngine> {
+    let engine = RecognitionEngine::new(config)?.with_cancellation(job.token());
+    Ok(match profile {
+        Some(profile) => engine.with_preprocessing(PreprocessingConfig::for_profile(profile)),
+        None => engine,
+    })
+}
+
+/// Event carrying a `RecognitionStarted` when a recognition command registers its job
+pub const RECOGNITION_STARTED_EVENT: &str = "recognition://started";
+
+/// Register a recognition job and announce its ID, so that jobs started
+/// without an ID can still be cancelled
+fn start_job<'a>(app: &tauri::AppHandle, jobs: &'a RecognitionJobs, job_id: Option<String>) -> MathSeekResult<RecognitionJob<'a>> {
+    let job = jobs.start(job_id)?;
+    // A dropped event only means the job cannot be cancelled by a generated ID
+    let _ = app.emit(RECOGNITION_STARTED_EVENT, RecognitionStarted { job_id: job.id().to_string() });
+    Ok(job)
+}
+
+/// Abort a running recognition; returns false if the job is not running
+#[tauri::command]
+async fn cancel_recognition(jobs: tauri::State<'_, RecognitionJobs>, job_id: String) -> Result<bool, MathSeekError> {
+    Ok(jobs.cancel(&job_id))
+}
+
+#[tauri::command]
+async fn get_active_recognitions(jobs: tauri::State<'_, RecognitionJobs>) -> Result<Vec<String>, MathSeekError> {
+    Ok(jobs.active_jobs())
+}
+
+/// Event carrying a `RecognitionPartial` while a streamed recognition runs
+pub const RECOGNITION_PARTIAL_EVENT: &str = "recognition://partial";
+
+/// Event carrying a `RecognitionFinal` with the validated result of a streamed recognition
+pub const RECOGNITION_FINAL_EVENT: &str = "recognition://final";
+
+#[tauri::command]
+async fn recognize_content_streaming(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, RecognitionJobs>,
+    base64_data: String,
+    input_type: Option<String>,
+    config: AppConfig,
+    job_id: Option<String>,
+    profile: Option<PreprocessingProfile>,
+) -> Result<FormulaResult, MathSeekError> {
+    let image_data = ImageProcessor::base64_to_image(&base64_data)?;
+    
+    let input_type_enum = input_type.map(InputType::try_from).transpose()?;
+    
+    let job = start_job(&app, &jobs, job_id)?;
+    let recognition_engine = recognition_engine(&config, profile, &job)?;
+    
+    // A dropped progress event is harmless, the final result still arrives
+    let emitter = app.clone();
+    let partial_job_id = job.id().to_string();
+    let on_partial = move |partial: RecognitionPartial| {
+        let _ = emitter.emit(RECOGNITION_PARTIAL_EVENT, RecognitionPartial { job_id: Some(partial_job_id.clone()), ..partial });
+    };
+    let result = recognition_engine
+        .recognize_content_streaming(image_data, input_type_enum, &on_partial)
+        .await?;
+    
+    let event = RecognitionFinal { job_id: job.id().to_string(), result };
+    app.emit(RECOGNITION_FINAL_EVENT, &event)
+        .map_err(|e| MathSeekError::Unknown(format!("Failed to emit recognition result: {}", e)))?;
+    
+    Ok(event.result)
+}
+
+#[tauri::command]
+async fn get_recognition_stats(config: AppConfig) -> Result<RecognitionStats, MathSeekError> {
+    let recognition_engine = RecognitionEngine::new(&config)?;
     
     Ok(recognition_engine.get_recognition_stats())
 }
 
+/// Render the system prompt a chat model backend would receive for `input_type`
+#[tauri::command]
+async fn preview_prompt(config: AppConfig, input_type: String, streaming: Option<bool>) -> Result<String, MathSeekError> {
+    let input_type_enum = InputType::try_from(input_type)?;
+    
+    config.prompt_templates
+        .render(&input_type_enum, &config.markdown_formula_format, streaming.unwrap_or(false))
+}
+
+#[tauri::command]
+async fn get_available_backends() -> Result<Vec<String>, MathSeekError> {
+    Ok(recognition_backend::available_backends()
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect())
+}
+
+#[tauri::command]
+async fn list_models(config: AppConfig) -> Result<Vec<String>, MathSeekError> {
+    let api_client = ApiClient::from_app_config(&config)?;
+    
+    api_client.list_models().await
+}
+
+#[tauri::command]
+async fn get_rate_limit_status(config: AppConfig) -> Result<RateLimitStatus, MathSeekError> {
+    let api_client = ApiClient::from_app_config(&config)?;
+    
+    Ok(api_client.rate_limit_status())
+}
+
+/// Estimated spend per provider in a `YYYY-MM` month, the current one by default
+#[tauri::command]
+async fn get_monthly_spend(month: Option<String>) -> Result<Vec<ProviderSpend>, MathSeekError> {
+    let ledger = usage::UsageLedger::new(usage::UsageLedger::default_path()?);
+    let month = month.unwrap_or_else(usage::current_month);
+
+    ledger.monthly_spend(&month)
+}
+
+/// Months with recorded usage, oldest first
+#[tauri::command]
+async fn get_usage_months() -> Result<Vec<String>, MathSeekError> {
+    let ledger = usage::UsageLedger::new(usage::UsageLedger::default_path()?);
+
+    ledger.months()
+}
+
 #[tauri::command]
-async fn analyze_formula(formula: String, config: AppConfig) -> Result<AnalysisResult, String> {
-    let api_client = ApiClient::from_app_config(&config)
-        .map_err(|e| e.to_string())?;
+async fn analyze_formula(formula: String, config: AppConfig) -> Result<AnalysisResult, MathSeekError> {
+    let api_client = ApiClient::from_app_config(&config)?;
     
     api_client.analyze_formula(&formula).await
-        .map_err(|e| e.to_string())
 }
 
 // Configuration management commands
 #[tauri::command]
-async fn validate_config(config: AppConfig) -> Result<ConfigValidation, String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn validate_config(config: AppConfig) -> Result<ConfigValidation, MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.validate_config(&config).await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn reset_config() -> Result<AppConfig, String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn reset_config() -> Result<AppConfig, MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.reset_config().await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn export_config(include_sensitive: bool) -> Result<String, String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn export_config(include_sensitive: bool) -> Result<String, MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.export_config(include_sensitive).await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn import_config(config_json: String) -> Result<AppConfig, String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn import_config(config_json: String) -> Result<AppConfig, MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.import_config(&config_json).await
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn config_exists() -> Result<bool, String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn config_exists() -> Result<bool, MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     Ok(config_manager.config_exists())
 }
 
 #[tauri::command]
-async fn delete_config() -> Result<(), String> {
-    let config_manager = ConfigManager::new()
-        .map_err(|e| e.to_string())?;
+async fn delete_config() -> Result<(), MathSeekError> {
+    let config_manager = ConfigManager::new()?;
     
     config_manager.delete_config().await
-        .map_err(|e| e.to_string())
 }
 
 // Export management commands
 #[tauri::command]
-async fn export_formula_result(result: FormulaResult, export_config: ExportConfig, app_config: AppConfig) -> Result<ExportResult, String> {
+async fn export_formula_result(result: FormulaResult, export_config: ExportConfig, app_config: AppConfig) -> Result<ExportResult, MathSeekError> {
     let export_manager = ExportManager::new(app_config);
     
     export_manager.export_formula_result(&result, &export_config)
-        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn get_available_export_formats(input_type: String, app_config: AppConfig) -> Result<Vec<String>, String> {
-    let input_type_enum = InputType::try_from(input_type)
-        .map_err(|e| e.to_string())?;
+async fn get_available_export_formats(input_type: String, app_config: AppConfig) -> Result<Vec<String>, MathSeekError> {
+    let input_type_enum = InputType::try_from(input_type)?;
     
     let export_manager = ExportManager::new(app_config);
     let formats = export_manager.get_available_formats(&input_type_enum);
@@ -448,9 +762,8 @@ async fn get_available_export_formats(input_type: String, app_config: AppConfig)
 }
 
 #[tauri::command]
-async fn get_default_export_format(input_type: String, app_config: AppConfig) -> Result<String, String> {
-    let input_type_enum = InputType::try_from(input_type)
-        .map_err(|e| e.to_string())?;
+async fn get_default_export_format(input_type: String, app_config: AppConfig) -> Result<String, MathSeekError> {
+    let input_type_enum = InputType::try_from(input_type)?;
     
     let export_manager = ExportManager::new(app_config);
     let format = export_manager.get_default_format(&input_type_enum);
@@ -458,15 +771,36 @@ async fn get_default_export_format(input_type: String, app_config: AppConfig) ->
     Ok(format.into())
 }
 
+/// Copy an export as plain text plus HTML with MathML, so that pasting into
+/// Word or a browser renders the formulas
+///
+/// Applications that understand HTML paste the MathML; everything else
+/// pastes the export text.
 #[tauri::command]
-async fn export_to_file(result: FormulaResult, export_config: ExportConfig, app_config: AppConfig, file_path: String) -> Result<(), String> {
+async fn copy_export_result(
+    app: tauri::AppHandle,
+    result: FormulaResult,
+    export_result: ExportResult,
+    app_config: AppConfig,
+) -> Result<(), MathSeekError> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+
+    let export_manager = ExportManager::new(app_config);
+    let html = export_manager.to_clipboard_html(&result);
+
+    tokio::task::spawn_blocking(move || app.clipboard().write_html(html.as_str(), Some(export_result.content.as_str())))
+        .await?
+        .map_err(|e| MathSeekError::ExportError(format!("Failed to write to the clipboard: {}", e)))
+}
+
+#[tauri::command]
+async fn export_to_file(result: FormulaResult, export_config: ExportConfig, app_config: AppConfig, file_path: String) -> Result<(), MathSeekError> {
     let export_manager = ExportManager::new(app_config);
     
-    let export_result = export_manager.export_formula_result(&result, &export_config)
-        .map_err(|e| e.to_string())?;
+    let export_result = export_manager.export_formula_result(&result, &export_config)?;
     
     std::fs::write(&file_path, export_result.content)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+        .map_err(|e| MathSeekError::ExportError(format!("Failed to write file: {}", e)))?;
     
     Ok(())
 }
@@ -479,11 +813,22 @@ impl Default for AppConfig {
         default_formats.insert(InputType::Document, ExportFormat::Markdown);
         
         Self {
+            backend: recognition_backend::default_backend_name(),
             api_endpoint: String::new(),
             api_key: String::new(),
+            app_id: String::new(),
+            model: String::new(),
+            requests_per_minute: 0,
+            max_concurrent_requests: 0,
+            model_prices: usage::default_price_table(),
+            fallback_providers: Vec::new(),
+            max_payload_bytes: 0,
+            network: NetworkConfig::default(),
             default_export_format: default_formats,
             render_engine: RenderEngine::MathJax,
             markdown_formula_format: MarkdownFormulaFormat::default(),
+            prompt_templates: PromptTemplates::default(),
+            preprocessing: PreprocessingConfig::default(),
         }
     }
 }
@@ -508,6 +853,10 @@ impl FormulaResult {
                 .as_secs(),
             input_type: InputType::SingleFormula,
             content: ResultContent::SingleFormula(latex),
+            token_usage: None,
+            provenance: None,
+            alternatives: Vec::new(),
+            payload: None,
         }
     }
     
@@ -521,6 +870,10 @@ impl FormulaResult {
                 .as_secs(),
             input_type: InputType::Document,
             content: ResultContent::Document(document),
+            token_usage: None,
+            provenance: None,
+            alternatives: Vec::new(),
+            payload: None,
         }
     }
 }
@@ -565,23 +918,48 @@ impl FormulaBlock {
 // Validation methods
 impl AppConfig {
     pub fn validate(&self) -> MathSeekResult<()> {
-        if self.api_endpoint.is_empty() {
-            return Err(MathSeekError::ConfigError("API endpoint cannot be empty".to_string()));
-        }
+        validate_provider(&self.backend, &self.api_endpoint, &self.api_key, &self.app_id)?;
         
-        if self.api_key.is_empty() {
-            return Err(MathSeekError::ConfigError("API key cannot be empty".to_string()));
+        for provider in &self.fallback_providers {
+            provider.validate()?;
         }
         
-        // Validate URL format
-        if !self.api_endpoint.starts_with("http://") && !self.api_endpoint.starts_with("https://") {
-            return Err(MathSeekError::ConfigError("API endpoint must be a valid URL".to_string()));
-        }
+        self.prompt_templates.validate()?;
         
         Ok(())
     }
 }
 
+impl ProviderConfig {
+    pub fn validate(&self) -> MathSeekResult<()> {
+        validate_provider(&self.backend, &self.api_endpoint, &self.api_key, &self.app_id)
+    }
+}
+
+fn validate_provider(backend: &str, api_endpoint: &str, api_key: &str, app_id: &str) -> MathSeekResult<()> {
+    let backend = recognition_backend::create_backend(backend)?;
+    
+    if api_endpoint.is_empty() {
+        return Err(MathSeekError::ConfigError("API endpoint cannot be empty".to_string()));
+    }
+    
+    // Local backends such as Ollama run without credentials
+    if api_key.is_empty() && backend.requires_api_key() {
+        return Err(MathSeekError::ConfigError("API key cannot be empty".to_string()));
+    }
+    
+    if app_id.is_empty() && backend.requires_app_id() {
+        return Err(MathSeekError::ConfigError("App ID cannot be empty".to_string()));
+    }
+    
+    // Validate URL format
+    if !api_endpoint.starts_with("http://") && !api_endpoint.starts_with("https://") {
+        return Err(MathSeekError::ConfigError("API endpoint must be a valid URL".to_string()));
+    }
+    
+    Ok(())
+}
+
 impl FormulaResult {
     pub fn validate(&self) -> MathSeekResult<()> {
         if self.latex.is_empty() {
@@ -684,15 +1062,18 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(RecognitionJobs::default())
         .invoke_handler(tauri::generate_handler![
             get_app_version,
             check_system_status,
             save_config,
             load_config,
             capture_screenshot,
+            list_monitors,
             get_clipboard_image,
             validate_image_data,
             preprocess_image,
+            preprocess_image_stages,
             get_image_info,
             detect_input_type,
             analyze_image_layout,
@@ -701,7 +1082,17 @@ pub fn run() {
             recognize_formula,
             recognize_content_auto,
             re_recognize_with_type,
+            recognize_content_streaming,
+            recognize_ensemble,
+            cancel_recognition,
+            get_active_recognitions,
             get_recognition_stats,
+            get_available_backends,
+            preview_prompt,
+            list_models,
+            get_rate_limit_status,
+            get_monthly_spend,
+            get_usage_months,
             analyze_formula,
             validate_config,
             reset_config,
@@ -712,6 +1103,7 @@ pub fn run() {
             export_formula_result,
             get_available_export_formats,
             get_default_export_format,
+            copy_export_result,
             export_to_file
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,631 @@
+use crate::recognition_backend;
+
+const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Commands whose braced argument is text rather than math
+const TEXT_COMMANDS: &[&str] = &["\\text", "\\textrm", "\\textit", "\\textbf", "\\mbox", "\\operatorname"];
+
+/// Letters and other symbols rendered as identifiers
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"), ("epsilon", "ϵ"),
+    ("varepsilon", "ε"), ("zeta", "ζ"), ("eta", "η"), ("theta", "θ"), ("vartheta", "ϑ"),
+    ("iota", "ι"), ("kappa", "κ"), ("lambda", "λ"), ("mu", "μ"), ("nu", "ν"), ("xi", "ξ"),
+    ("pi", "π"), ("varpi", "ϖ"), ("rho", "ρ"), ("varrho", "ϱ"), ("sigma", "σ"),
+    ("varsigma", "ς"), ("tau", "τ"), ("upsilon", "υ"), ("phi", "ϕ"), ("varphi", "φ"),
+    ("chi", "χ"), ("psi", "ψ"), ("omega", "ω"),
+    ("Gamma", "Γ"), ("Delta", "Δ"), ("Theta", "Θ"), ("Lambda", "Λ"), ("Xi", "Ξ"),
+    ("Pi", "Π"), ("Sigma", "Σ"), ("Upsilon", "Υ"), ("Phi", "Φ"), ("Psi", "Ψ"), ("Omega", "Ω"),
+    ("infty", "∞"), ("partial", "∂"), ("nabla", "∇"), ("hbar", "ℏ"), ("ell", "ℓ"),
+    ("emptyset", "∅"), ("varnothing", "∅"), ("aleph", "ℵ"), ("Re", "ℜ"), ("Im", "ℑ"),
+    ("angle", "∠"), ("triangle", "△"), ("prime", "′"),
+];
+
+/// Relations, binary operators, arrows and delimiters
+const OPERATORS: &[(&str, &str)] = &[
+    ("cdot", "⋅"), ("times", "×"), ("div", "÷"), ("pm", "±"), ("mp", "∓"), ("ast", "∗"),
+    ("star", "⋆"), ("circ", "∘"), ("bullet", "∙"), ("oplus", "⊕"), ("otimes", "⊗"),
+    ("leq", "≤"), ("le", "≤"), ("geq", "≥"), ("ge", "≥"), ("neq", "≠"), ("ne", "≠"),
+    ("approx", "≈"), ("equiv", "≡"), ("sim", "∼"), ("simeq", "≃"), ("cong", "≅"),
+    ("propto", "∝"), ("ll", "≪"), ("gg", "≫"), ("perp", "⊥"), ("parallel", "∥"),
+    ("mid", "∣"), ("in", "∈"), ("notin", "∉"), ("ni", "∋"), ("subset", "⊂"),
+    ("subseteq", "⊆"), ("supset", "⊃"), ("supseteq", "⊇"), ("cup", "∪"), ("cap", "∩"),
+    ("setminus", "∖"), ("land", "∧"), ("wedge", "∧"), ("lor", "∨"), ("vee", "∨"),
+    ("neg", "¬"), ("forall", "∀"), ("exists", "∃"),
+    ("to", "→"), ("rightarrow", "→"), ("leftarrow", "←"), ("gets", "←"),
+    ("leftrightarrow", "↔"), ("Rightarrow", "⇒"), ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"), ("implies", "⟹"), ("iff", "⟺"), ("mapsto", "↦"),
+    ("uparrow", "↑"), ("downarrow", "↓"),
+    ("ldots", "…"), ("dots", "…"), ("cdots", "⋯"), ("vdots", "⋮"), ("ddots", "⋱"),
+    ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"), ("lceil", "⌈"),
+    ("rceil", "⌉"), ("lbrace", "{"), ("rbrace", "}"), ("vert", "|"), ("lvert", "|"),
+    ("rvert", "|"), ("Vert", "‖"), ("lVert", "‖"), ("rVert", "‖"),
+    ("{", "{"), ("}", "}"), ("|", "‖"), ("%", "%"), ("$", "$"), ("#", "#"), ("&", "&"), ("_", "_"),
+];
+
+/// Large operators; the flag marks those taking limits above and below
+const LARGE_OPERATORS: &[(&str, &str, bool)] = &[
+    ("sum", "∑", true), ("prod", "∏", true), ("coprod", "∐", true), ("bigcup", "⋃", true),
+    ("bigcap", "⋂", true), ("bigoplus", "⨁", true), ("bigotimes", "⨂", true),
+    ("int", "∫", false), ("iint", "∬", false), ("iiint", "∭", false), ("oint", "∮", false),
+];
+
+/// Function names set upright; the flag marks those taking limits below
+const FUNCTIONS: &[(&str, bool)] = &[
+    ("sin", false), ("cos", false), ("tan", false), ("cot", false), ("sec", false), ("csc", false),
+    ("arcsin", false), ("arccos", false), ("arctan", false), ("sinh", false), ("cosh", false),
+    ("tanh", false), ("log", false), ("ln", false), ("lg", false), ("exp", false), ("deg", false),
+    ("dim", false), ("ker", false), ("arg", false), ("hom", false),
+    ("lim", true), ("liminf", true), ("limsup", true), ("max", true), ("min", true),
+    ("sup", true), ("inf", true), ("det", true), ("gcd", true), ("Pr", true),
+];
+
+/// Accents above (`true`) or below their argument
+const ACCENTS: &[(&str, &str, bool)] = &[
+    ("hat", "^", true), ("widehat", "^", true), ("bar", "¯", true), ("overline", "¯", true),
+    ("vec", "→", true), ("overrightarrow", "→", true), ("dot", "˙", true), ("ddot", "¨", true),
+    ("tilde", "~", true), ("widetilde", "~", true), ("overbrace", "⏞", true),
+    ("underline", "_", false), ("underbrace", "⏟", false),
+];
+
+const FONTS: &[(&str, &str)] = &[
+    ("mathbb", "double-struck"), ("mathbf", "bold"), ("boldsymbol", "bold-italic"),
+    ("mathcal", "script"), ("mathscr", "script"), ("mathfrak", "fraktur"),
+    ("mathit", "italic"), ("mathrm", "normal"), ("mathsf", "sans-serif"), ("mathtt", "monospace"),
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.167em"), (":", "0.222em"), (">", "0.222em"), (";", "0.278em"), (" ", "0.25em"),
+    ("quad", "1em"), ("qquad", "2em"), ("!", "-0.167em"),
+];
+
+/// Convert LaTeX to a MathML `<math>` element, e.g. for the clipboard
+///
+/// Covers what recognition produces: scripts, fractions, roots, Greek
+/// letters, operators, fences, accents, font commands and matrix-like
+/// environments. Unknown commands are kept as text so that nothing is
+/// silently dropped, and the LaTeX source travels along as an annotation.
+pub fn latex_to_mathml(latex: &str, display: bool) -> String {
+    let source = recognition_backend::strip_math_delimiters(latex).trim();
+    let mut parser = Parser { tokens: tokenize(source), index: 0 };
+
+    format!(
+        "<math xmlns=\"{}\" display=\"{}\"><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        MATHML_NAMESPACE,
+        if display { "block" } else { "inline" },
+        row(parser.top_level()),
+        escape_xml(source)
+    )
+}
+
+/// Escape text for use in XML and HTML content and attribute values
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    /// A command including its backslash, e.g. `\frac` or `\{`
+    Command(String),
+    Char(char),
+    /// Raw argument of a text command
+    Text(String),
+}
+
+/// Split LaTeX into commands and characters, keeping the argument of text commands verbatim
+///
+/// Whitespace outside text arguments is dropped.
+pub(crate) fn tokenize(latex: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = latex.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch.is_whitespace() {
+            continue;
+        }
+        if ch != '\\' {
+            tokens.push(Token::Char(ch));
+            continue;
+        }
+
+        let mut command = String::from('\\');
+        match chars.peek() {
+            Some(next) if next.is_ascii_alphabetic() => {
+                while let Some(&next) = chars.peek().filter(|next| next.is_ascii_alphabetic()) {
+                    command.push(next);
+                    chars.next();
+                }
+            }
+            Some(&next) => {
+                command.push(next);
+                chars.next();
+            }
+            None => {}
+        }
+
+        let is_text = TEXT_COMMANDS.contains(&command.as_str());
+        tokens.push(Token::Command(command));
+
+        if is_text {
+            while chars.next_if(|next| next.is_whitespace()).is_some() {}
+            if chars.next_if_eq(&'{').is_some() {
+                let mut text = String::new();
+                let mut depth = 0;
+                for next in chars.by_ref() {
+                    match next {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => break,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    text.push(next);
+                }
+                tokens.push(Token::Text(text));
+            }
+        }
+    }
+
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn peek_is(&self, expected: &Token) -> bool {
+        self.peek() == Some(expected)
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self.peek_is(expected);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    /// Parse everything, treating stray closing tokens and `\\` outside an environment leniently
+    fn top_level(&mut self) -> Vec<String> {
+        let mut nodes = self.sequence();
+        while let Some(token) = self.next() {
+            if token == Token::Command("\\\\".to_string()) {
+                nodes.push("<mspace linebreak=\"newline\"/>".to_string());
+            } else if token == Token::Command("\\right".to_string()) {
+                nodes.extend(self.delimiter());
+            }
+            nodes.extend(self.sequence());
+        }
+        nodes
+    }
+
+    /// Atoms up to the end of a group, a cell or row separator, `\end` or `\right`
+    fn sequence(&mut self) -> Vec<String> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.peek() {
+            let stops = match token {
+                Token::Char(ch) => matches!(ch, '}' | '&'),
+                Token::Command(command) => matches!(command.as_str(), "\\\\" | "\\end" | "\\right" | "\\cr"),
+                Token::Text(_) => false,
+            };
+            if stops {
+                break;
+            }
+            nodes.push(self.atom());
+        }
+        nodes
+    }
+
+    /// A base with its subscript, superscript and primes
+    fn atom(&mut self) -> String {
+        let (base, limits) = self.base();
+        let mut subscript = None;
+        let mut superscript = None;
+        let mut primes = String::new();
+
+        loop {
+            if subscript.is_none() && self.eat(&Token::Char('_')) {
+                subscript = Some(self.argument());
+            } else if superscript.is_none() && self.eat(&Token::Char('^')) {
+                superscript = Some(self.argument());
+            } else if self.eat(&Token::Char('\'')) {
+                primes.push('′');
+            } else {
+                break;
+            }
+        }
+
+        if !primes.is_empty() {
+            let primes = format!("<mo>{}</mo>", primes);
+            superscript = Some(match superscript {
+                Some(superscript) => row(vec![primes, superscript]),
+                None => primes,
+            });
+        }
+
+        let (under, over, both) = if limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        match (subscript, superscript) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base, sup),
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base, sub, sup),
+        }
+    }
+
+    /// Argument of a command or script: a group or a single token
+    fn argument(&mut self) -> String {
+        if self.eat(&Token::Char('{')) {
+            let nodes = self.sequence();
+            self.eat(&Token::Char('}'));
+            return row(nodes);
+        }
+        match self.peek() {
+            Some(Token::Char(ch)) if ch.is_ascii_digit() => {
+                let digit = *ch;
+                self.index += 1;
+                format!("<mn>{}</mn>", digit)
+            }
+            _ => self.base().0,
+        }
+    }
+
+    /// `[...]` after a command such as `\sqrt`
+    fn optional_argument(&mut self) -> Option<String> {
+        if !self.peek_is(&Token::Char('[')) {
+            return None;
+        }
+
+        let mut depth = 0;
+        let mut end = self.index + 1;
+        while let Some(token) = self.tokens.get(end) {
+            match token {
+                Token::Char('{') => depth += 1,
+                Token::Char('}') => depth -= 1,
+                Token::Char(']') if depth == 0 => break,
+                _ => {}
+            }
+            end += 1;
+        }
+
+        let mut inner = Parser { tokens: self.tokens[self.index + 1..end.min(self.tokens.len())].to_vec(), index: 0 };
+        self.index = end + 1;
+        Some(row(inner.top_level()))
+    }
+
+    /// The next node and whether scripts attach to it as limits
+    fn base(&mut self) -> (String, bool) {
+        let Some(token) = self.peek().cloned() else {
+            return ("<mrow></mrow>".to_string(), false);
+        };
+
+        match token {
+            // A script without a base, e.g. `^2` at the start
+            Token::Char('_' | '^' | '}' | '&') | Token::Command(_) if self.is_stop(&token) => {
+                ("<mrow></mrow>".to_string(), false)
+            }
+            Token::Char('{') => (self.argument(), false),
+            Token::Char(ch) if ch.is_ascii_digit() || (ch == '.' && self.next_is_digit(1)) => {
+                let mut number = String::new();
+                while let Some(Token::Char(ch)) = self.peek() {
+                    if ch.is_ascii_digit() || (*ch == '.' && self.next_is_digit(1)) {
+                        number.push(*ch);
+                        self.index += 1;
+                    } else {
+                        break;
+                    }
+                }
+                (format!("<mn>{}</mn>", number), false)
+            }
+            Token::Char(ch) => {
+                self.index += 1;
+                let node = if ch.is_alphabetic() {
+                    format!("<mi>{}</mi>", escape_xml(&ch.to_string()))
+                } else if ch == '~' {
+                    "<mspace width=\"0.25em\"/>".to_string()
+                } else {
+                    let symbol = match ch {
+                        '-' => "−".to_string(),
+                        '*' => "∗".to_string(),
+                        _ => ch.to_string(),
+                    };
+                    format!("<mo>{}</mo>", escape_xml(&symbol))
+                };
+                (node, false)
+            }
+            Token::Text(text) => {
+                self.index += 1;
+                (format!("<mtext>{}</mtext>", escape_xml(&text)), false)
+            }
+            Token::Command(command) => {
+                self.index += 1;
+                self.command(&command)
+            }
+        }
+    }
+
+    fn is_stop(&self, token: &Token) -> bool {
+        match token {
+            Token::Char(ch) => matches!(ch, '_' | '^' | '}' | '&'),
+            Token::Command(command) => matches!(command.as_str(), "\\\\" | "\\end" | "\\right" | "\\cr"),
+            Token::Text(_) => false,
+        }
+    }
+
+    fn next_is_digit(&self, offset: usize) -> bool {
+        matches!(self.tokens.get(self.index + offset), Some(Token::Char(ch)) if ch.is_ascii_digit())
+    }
+
+    fn command(&mut self, command: &str) -> (String, bool) {
+        let name = &command[1..];
+
+        if let Some((_, symbol)) = IDENTIFIERS.iter().find(|(entry, _)| *entry == name) {
+            let variant = if symbol.chars().all(char::is_uppercase) { " mathvariant=\"normal\"" } else { "" };
+            return (format!("<mi{}>{}</mi>", variant, symbol), false);
+        }
+        if let Some((_, symbol)) = OPERATORS.iter().find(|(entry, _)| *entry == name) {
+            return (format!("<mo>{}</mo>", escape_xml(symbol)), false);
+        }
+        if let Some((_, symbol, limits)) = LARGE_OPERATORS.iter().find(|(entry, _, _)| *entry == name) {
+            return (format!("<mo largeop=\"true\">{}</mo>", symbol), *limits);
+        }
+        if let Some((_, limits)) = FUNCTIONS.iter().find(|(entry, _)| *entry == name) {
+            return (format!("<mi>{}</mi>", name), *limits);
+        }
+        if let Some((_, width)) = SPACES.iter().find(|(entry, _)| *entry == name) {
+            return (format!("<mspace width=\"{}\"/>", width), false);
+        }
+        if let Some((_, accent, over)) = ACCENTS.iter().find(|(entry, _, _)| *entry == name) {
+            let argument = self.argument();
+            return if *over {
+                (format!("<mover accent=\"true\">{}<mo stretchy=\"true\">{}</mo></mover>", argument, accent), false)
+            } else {
+                (format!("<munder accentunder=\"true\">{}<mo stretchy=\"true\">{}</mo></munder>", argument, accent), false)
+            };
+        }
+        if let Some((_, variant)) = FONTS.iter().find(|(entry, _)| *entry == name) {
+            let argument = self.argument()
+                .replace("<mi>", &format!("<mi mathvariant=\"{}\">", variant))
+                .replace("<mn>", &format!("<mn mathvariant=\"{}\">", variant));
+            return (argument, false);
+        }
+
+        match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                (format!("<mfrac>{}{}</mfrac>", numerator, denominator), false)
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.argument();
+                let bottom = self.argument();
+                (format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>", top, bottom), false)
+            }
+            "sqrt" => {
+                let index = self.optional_argument();
+                let radicand = self.argument();
+                match index {
+                    Some(index) => (format!("<mroot>{}{}</mroot>", radicand, index), false),
+                    None => (format!("<msqrt>{}</msqrt>", radicand), false),
+                }
+            }
+            "left" => {
+                let mut nodes = self.delimiter();
+                nodes.extend(self.sequence());
+                if self.eat(&Token::Command("\\right".to_string())) {
+                    nodes.extend(self.delimiter());
+                }
+                (format!("<mrow>{}</mrow>", nodes.concat()), false)
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl" | "biggr" | "Biggl" | "Biggr" => {
+                (self.delimiter().concat(), false)
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mbox" | "operatorname" => {
+                let text = match self.peek() {
+                    Some(Token::Text(text)) => {
+                        let text = text.clone();
+                        self.index += 1;
+                        text
+                    }
+                    _ => String::new(),
+                };
+                if name == "operatorname" {
+                    (format!("<mi>{}</mi>", escape_xml(text.trim())), false)
+                } else {
+                    (format!("<mtext>{}</mtext>", escape_xml(&text)), false)
+                }
+            }
+            "begin" => (self.environment(), false),
+            // Style switches only change sizes, which MathML derives from `display`
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => self.base(),
+            _ => (format!("<mtext>{}</mtext>", escape_xml(command)), false),
+        }
+    }
+
+    /// The fence after `\left`, `\right` or `\big`; `.` is invisible
+    fn delimiter(&mut self) -> Vec<String> {
+        let symbol = match self.next() {
+            Some(Token::Char('.')) | None => return Vec::new(),
+            Some(Token::Char(ch)) => ch.to_string(),
+            Some(Token::Command(command)) => OPERATORS.iter()
+                .find(|(entry, _)| *entry == &command[1..])
+                .map(|(_, symbol)| symbol.to_string())
+                .unwrap_or(command),
+            Some(Token::Text(text)) => text,
+        };
+        vec![format!("<mo fence=\"true\">{}</mo>", escape_xml(&symbol))]
+    }
+
+    /// Name in braces after `\begin` or `\end`
+    fn environment_name(&mut self) -> String {
+        let mut name = String::new();
+        if self.eat(&Token::Char('{')) {
+            while let Some(token) = self.next() {
+                match token {
+                    Token::Char('}') => break,
+                    Token::Char(ch) => name.push(ch),
+                    _ => {}
+                }
+            }
+        }
+        name
+    }
+
+    /// `\begin{...}` ... `\end{...}` as a table with the environment's fences
+    fn environment(&mut self) -> String {
+        let name = self.environment_name();
+        if name == "array" {
+            // Column specification
+            self.argument();
+        }
+
+        let mut rows = Vec::new();
+        loop {
+            let mut cells = Vec::new();
+            loop {
+                cells.push(format!("<mtd>{}</mtd>", row(self.sequence())));
+                if !self.eat(&Token::Char('&')) {
+                    break;
+                }
+            }
+            rows.push(cells);
+
+            if self.eat(&Token::Command("\\\\".to_string())) || self.eat(&Token::Command("\\cr".to_string())) {
+                continue;
+            }
+            if self.eat(&Token::Command("\\end".to_string())) {
+                self.environment_name();
+                break;
+            }
+            // A stray `}` or `\right` inside the environment, or the end of input
+            if self.peek().is_none() {
+                break;
+            }
+            self.index += 1;
+        }
+        // `\\` after the last row
+        if rows.len() > 1 && rows.last().is_some_and(|cells| cells.len() == 1 && cells[0] == "<mtd><mrow></mrow></mtd>") {
+            rows.pop();
+        }
+
+        let alignment = match name.trim_end_matches('*') {
+            "cases" | "aligned" | "align" | "split" => " columnalign=\"left\"",
+            _ => "",
+        };
+        let table = format!(
+            "<mtable{}>{}</mtable>",
+            alignment,
+            rows.iter().map(|cells| format!("<mtr>{}</mtr>", cells.concat())).collect::<String>()
+        );
+
+        let (open, close) = match name.as_str() {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => return table,
+        };
+        let close = if close.is_empty() { String::new() } else { format!("<mo fence=\"true\">{}</mo>", close) };
+        format!("<mrow><mo fence=\"true\">{}</mo>{}{}</mrow>", open, table, close)
+    }
+}
+
+/// Group nodes, leaving a single node as it is
+fn row(nodes: Vec<String>) -> String {
+    if nodes.len() == 1 {
+        nodes.into_iter().next().unwrap_or_default()
+    } else {
+        format!("<mrow>{}</mrow>", nodes.concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The presentation markup without the `<math>` wrapper and annotation
+    fn body(latex: &str) -> String {
+        let mathml = latex_to_mathml(latex, false);
+        let start = mathml.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+        mathml[start..end].to_string()
+    }
+
+    #[test]
+    fn test_scripts_fractions_and_roots() {
+        assert_eq!(body("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(body("x_{i}^{10}"), "<msubsup><mi>x</mi><mi>i</mi><mn>10</mn></msubsup>");
+        assert_eq!(body("\\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+        assert_eq!(
+            body("\\sqrt[3]{x+1}"),
+            "<mroot><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow><mn>3</mn></mroot>"
+        );
+        assert_eq!(body("f'(x)"), "<mrow><msup><mi>f</mi><mo>′</mo></msup><mo>(</mo><mi>x</mi><mo>)</mo></mrow>");
+        assert_eq!(body("3.14"), "<mn>3.14</mn>");
+    }
+
+    #[test]
+    fn test_symbols_operators_and_fonts() {
+        assert_eq!(
+            body("\\sum_{i=1}^{n} \\alpha_i"),
+            "<mrow><munderover><mo largeop=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover>\
+             <msub><mi>α</mi><mi>i</mi></msub></mrow>"
+        );
+        assert_eq!(body("\\int_0^1"), "<msubsup><mo largeop=\"true\">∫</mo><mn>0</mn><mn>1</mn></msubsup>");
+        assert_eq!(body("x \\in \\mathbb{R}"), "<mrow><mi>x</mi><mo>∈</mo><mi mathvariant=\"double-struck\">R</mi></mrow>");
+        assert_eq!(body("\\sin x"), "<mrow><mi>sin</mi><mi>x</mi></mrow>");
+        assert_eq!(body("a<b"), "<mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>");
+        assert_eq!(body("\\text{if } x"), "<mrow><mtext>if </mtext><mi>x</mi></mrow>");
+        assert_eq!(body("\\vec{v}"), "<mover accent=\"true\"><mi>v</mi><mo stretchy=\"true\">→</mo></mover>");
+        assert_eq!(body("\\foo"), "<mtext>\\foo</mtext>");
+    }
+
+    #[test]
+    fn test_fences_and_environments() {
+        assert_eq!(
+            body("\\left( x \\right."),
+            "<mrow><mo fence=\"true\">(</mo><mi>x</mi></mrow>"
+        );
+        assert_eq!(
+            body("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}"),
+            "<mrow><mo fence=\"true\">(</mo><mtable>\
+             <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>\
+             </mtable><mo fence=\"true\">)</mo></mrow>"
+        );
+        // Unbalanced input still produces well-formed markup
+        assert_eq!(body("x}^{2"), "<mrow><mi>x</mi><msup><mrow></mrow><mn>2</mn></msup></mrow>");
+    }
+
+    #[test]
+    fn test_math_element() {
+        let mathml = latex_to_mathml("$$a & b$$", true);
+        assert!(mathml.starts_with("<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\">"));
+        assert!(mathml.ends_with("<annotation encoding=\"application/x-tex\">a &amp; b</annotation></semantics></math>"));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{
+    MathSeekError, MathSeekResult, FormulaResult, AnalysisResult, InputType, ApiClient, ApiConfig,
+    DocumentContent, DocumentSection, FormulaBlock, PayloadFormat
+};
+use crate::recognition_backend::{self, RecognitionBackend};
+use async_trait::async_trait;
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Request body for `/v3/text`
+#[derive(Debug, Serialize)]
+struct MathpixTextRequest {
+    src: String,
+    formats: Vec<String>,
+    include_line_data: bool,
+}
+
+/// Response body of `/v3/text`
+#[derive(Debug, Default, Deserialize)]
+struct MathpixTextResponse {
+    text: Option<String>,
+    latex_styled: Option<String>,
+    confidence: Option<f32>,
+    confidence_rate: Option<f32>,
+    #[serde(default)]
+    line_data: Vec<MathpixLine>,
+    error: Option<String>,
+}
+
+/// One entry of `line_data`, describing a single recognized line
+#[derive(Debug, Deserialize)]
+struct MathpixLine {
+    #[serde(rename = "type")]
+    line_type: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default = "default_included")]
+    included: bool,
+}
+
+fn default_included() -> bool {
+    true
+}
+
+/// Backend for the Mathpix-compatible `/v3/text` OCR protocol
+///
+/// Authenticates with the `app_id`/`app_key` header pair, where `app_key` is
+/// taken from `ApiConfig::api_key`.
+pub struct MathpixBackend;
+
+impl MathpixBackend {
+    pub const NAME: &'static str = "mathpix";
+
+    fn build_request(image_data: &[u8], format: PayloadFormat, input_type: &InputType) -> MathpixTextRequest {
+        let formats = match input_type {
+            InputType::SingleFormula => vec!["text".to_string(), "latex_styled".to_string()],
+            InputType::Document => vec!["text".to_string()],
+        };
+
+        MathpixTextRequest {
+            src: format!("data:{};base64,{}", format.mime_type(), BASE64_STANDARD.encode(image_data)),
+            formats,
+            include_line_data: matches!(input_type, InputType::Document),
+        }
+    }
+
+    /// Convert a `/v3/text` response into a validated `FormulaResult`
+    fn parse_response(response: MathpixTextResponse, input_type: InputType) -> MathSeekResult<FormulaResult> {
+        if let Some(error) = response.error {
+            return Err(MathSeekError::ApiError(format!("Mathpix error: {}", error)));
+        }
+
+        let confidence = response.confidence
+            .or(response.confidence_rate)
+            .unwrap_or(0.0);
+        let text = response.text.unwrap_or_default();
+
+        let result = match input_type {
+            InputType::SingleFormula => {
+                let latex = response.latex_styled
+                    .filter(|latex| !latex.trim().is_empty())
+                    .unwrap_or_else(|| recognition_backend::strip_math_delimiters(&text).to_string());
+                FormulaResult::new_single_formula(latex, confidence)
+            }
+            InputType::Document => {
+                let mut document = Self::document_from_lines(&response.line_data);
+                if document.validate().is_err() {
+                    // Fallback: keep the full text as a single section
+                    document = DocumentContent::new(None);
+                    document.add_section(DocumentSection::new(None, text.clone()));
+                }
+                FormulaResult::new_document(text, confidence, document)
+            }
+        };
+
+        result.validate()?;
+        Ok(result)
+    }
+
+    /// Rebuild the document structure from Mathpix `line_data`
+    ///
+    /// Headers start new sections, `math` lines become display formulas and
+    /// `\( ... \)` spans inside text lines become inline formulas.
+    fn document_from_lines(lines: &[MathpixLine]) -> DocumentContent {
+        let mut document = DocumentContent::new(None);
+        let mut section = DocumentSection::new(None, String::new());
+
+        for line in lines.iter().filter(|line| line.included) {
+            let content = line.text.trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            if line.line_type == "title" {
+                document.title = Some(Self::strip_command(content, "title").to_string());
+                continue;
+            }
+
+            if line.line_type == "section_header" || content.starts_with("\\section") {
+                let finished = std::mem::replace(
+                    &mut section,
+                    DocumentSection::new(Some(Self::strip_command(content, "section").to_string()), String::new()),
+                );
+                Self::push_section(&mut document, finished);
+                continue;
+            }
+
+            if !section.text.is_empty() {
+                section.text.push('\n');
+            }
+
+            if line.line_type == "math" {
+                let latex = recognition_backend::strip_math_delimiters(content).to_string();
+                section.add_formula(FormulaBlock::new(latex, section.text.len(), false));
+            } else {
+                recognition_backend::append_text_with_inline_math(&mut section, content, &[("\\(", "\\)")]);
+            }
+        }
+
+        Self::push_section(&mut document, section);
+        document
+    }
+
+    /// Strip a `\command{...}` or `\command*{...}` wrapper, keeping its argument
+    fn strip_command<'a>(content: &'a str, command: &str) -> &'a str {
+        let body = content.strip_prefix('\\')
+            .and_then(|rest| rest.strip_prefix(command))
+            .map(|rest| rest.trim_start_matches('*'))
+            .and_then(|rest| rest.strip_prefix('{'))
+            .and_then(|rest| rest.strip_suffix('}'));
+        body.unwrap_or(content).trim()
+    }
+
+    fn push_section(document: &mut DocumentContent, section: DocumentSection) {
+        if !section.text.is_empty() || !section.formulas.is_empty() {
+            document.add_section(section);
+        }
+    }
+}
+
+#[async_trait]
+impl RecognitionBackend for MathpixBackend {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn recognize(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        format: PayloadFormat,
+        input_type: InputType,
+    ) -> MathSeekResult<FormulaResult> {
+        let request = Self::build_request(image_data, format, &input_type);
+        let response: MathpixTextResponse = client.make_request_with_retry("/v3/text", &request).await?;
+
+        // Mathpix bills per image, so the usage carries no token counts
+        let token_usage = client.record_usage(client.token_usage(&client.api_config().model, 0, 0));
+        let mut result = Self::parse_response(response, input_type)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn analyze(&self, _client: &ApiClient, _formula: &str) -> MathSeekResult<AnalysisResult> {
+        Err(MathSeekError::ApiError("Formula analysis is not supported by the Mathpix backend".to_string()))
+    }
+
+    async fn health(&self, client: &ApiClient) -> MathSeekResult<bool> {
+        // Listing a single past result is the cheapest authenticated call
+        let response = client.send_get("/v3/ocr-results?page=1&per_page=1").await?;
+
+        Ok(response.status().is_success())
+    }
+
+    fn requires_app_id(&self) -> bool {
+        true
+    }
+
+    fn auth_headers(&self, config: &ApiConfig) -> Vec<(&'static str, String)> {
+        vec![
+            ("app_id", config.app_id.clone()),
+            ("app_key", config.api_key.clone()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_formula_response() {
+        let response: MathpixTextResponse = serde_json::from_str(
+            r#"{"request_id": "1", "text": "\\( x^{2} \\)", "latex_styled": "x^{2}", "confidence": 0.98}"#
+        ).unwrap();
+        let result = MathpixBackend::parse_response(response, InputType::SingleFormula).unwrap();
+        assert_eq!(result.latex, "x^{2}");
+        assert_eq!(result.confidence, 0.98);
+
+        let error: MathpixTextResponse = serde_json::from_str(r#"{"error": "Invalid credentials"}"#).unwrap();
+        assert!(MathpixBackend::parse_response(error, InputType::SingleFormula).is_err());
+    }
+
+    #[test]
+    fn test_line_data_mapping() {
+        let response: MathpixTextResponse = serde_json::from_str(r#"{
+            "text": "full text",
+            "confidence_rate": 0.9,
+            "line_data": [
+                {"type": "text", "text": "\\section*{Energy}"},
+                {"type": "text", "text": "Mass \\( m \\) relates to energy:"},
+                {"type": "math", "text": "\\[ E = m c^{2} \\]"},
+                {"type": "diagram", "text": "", "included": false},
+                {"type": "section_header", "text": "Motion"},
+                {"type": "text", "text": "Velocity is \\( v \\)."}
+            ]
+        }"#).unwrap();
+        let result = MathpixBackend::parse_response(response, InputType::Document).unwrap();
+        assert_eq!(result.confidence, 0.9);
+
+        let doc = match result.content {
+            crate::ResultContent::Document(doc) => doc,
+            _ => panic!("Expected Document content"),
+        };
+        assert_eq!(doc.sections.len(), 2);
+        assert_eq!(doc.sections[0].heading.as_deref(), Some("Energy"));
+        assert_eq!(doc.sections[0].text, "Mass  relates to energy:\n");
+        assert_eq!(doc.sections[0].formulas[0].latex, "m");
+        assert!(doc.sections[0].formulas[0].is_inline);
+        assert_eq!(doc.sections[0].formulas[0].position, 5);
+        assert_eq!(doc.sections[0].formulas[1].latex, "E = m c^{2}");
+        assert!(!doc.sections[0].formulas[1].is_inline);
+        assert_eq!(doc.sections[1].heading.as_deref(), Some("Motion"));
+        assert_eq!(doc.sections[1].formulas[0].position, 12);
+    }
+
+    #[test]
+    fn test_auth_headers() {
+        let config = ApiConfig {
+            app_id: "my-app".to_string(),
+            api_key: "secret".to_string(),
+            ..Default::default()
+        };
+        let headers = MathpixBackend.auth_headers(&config);
+        assert!(headers.contains(&("app_id", "my-app".to_string())));
+        assert!(headers.contains(&("app_key", "secret".to_string())));
+    }
+}
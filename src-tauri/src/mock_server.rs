@@ -0,0 +1,320 @@
+use crate::{MathSeekError, MathSeekResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Scripted answer to one request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub body: serde_json::Value,
+    /// Extra delay before this response is sent
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Value of the `Retry-After` header, in seconds
+    #[serde(default)]
+    pub retry_after: Option<u64>,
+    /// Close the connection without answering, simulating a network failure
+    #[serde(default)]
+    pub drop_connection: bool,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl MockResponse {
+    pub fn ok(body: serde_json::Value) -> Self {
+        Self::status(200, body)
+    }
+
+    pub fn status(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            body,
+            delay_ms: 0,
+            retry_after: None,
+            drop_connection: false,
+        }
+    }
+
+    /// A connection that is closed before any response is written
+    pub fn dropped() -> Self {
+        Self {
+            drop_connection: true,
+            ..Self::status(200, serde_json::Value::Null)
+        }
+    }
+
+    pub fn with_delay(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+/// Behaviour of a `MockServer`, loadable from JSON for the standalone binary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockScript {
+    /// Responses per path, served in order; the last one keeps repeating
+    pub routes: HashMap<String, Vec<MockResponse>>,
+    /// Latency added to every response
+    pub latency_ms: u64,
+    /// Probability in `[0, 1]` of answering any request with a 503
+    pub failure_rate: f64,
+    /// Bearer key every request must carry; any key is accepted when unset
+    pub api_key: Option<String>,
+}
+
+impl Default for MockScript {
+    fn default() -> Self {
+        let routes = [
+            ("/health", serde_json::json!({ "status": "ok" })),
+            ("/recognize", serde_json::json!({
+                "success": true,
+                "latex": "x^2 + y^2 = r^2",
+                "confidence": 0.95,
+            })),
+            ("/analyze", serde_json::json!({
+                "success": true,
+                "formula_type": "Circle Equation",
+                "description": "Points at distance r from the origin",
+                "usage": "Analytic geometry",
+                "examples": ["Unit circle: x^2 + y^2 = 1"],
+                "variables": [{ "symbol": "r", "description": "Radius" }],
+                "domain": "Geometry",
+                "related_formulas": ["(x - a)^2 + (y - b)^2 = r^2"],
+            })),
+        ];
+
+        Self {
+            routes: routes.into_iter()
+                .map(|(path, body)| (path.to_string(), vec![MockResponse::ok(body)]))
+                .collect(),
+            latency_ms: 0,
+            failure_rate: 0.0,
+            api_key: None,
+        }
+    }
+}
+
+impl MockScript {
+    /// Replace the responses of one path
+    pub fn route(mut self, path: &str, responses: Vec<MockResponse>) -> Self {
+        self.routes.insert(path.to_string(), responses);
+        self
+    }
+
+    pub fn latency(mut self, latency_ms: u64) -> Self {
+        self.latency_ms = latency_ms;
+        self
+    }
+
+    pub fn failure_rate(mut self, failure_rate: f64) -> Self {
+        self.failure_rate = failure_rate;
+        self
+    }
+
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+}
+
+/// A request received by the mock server
+#[derive(Debug, Clone, Serialize)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    /// Responses already served per path
+    served: HashMap<String, usize>,
+    requests: Vec<MockRequest>,
+}
+
+/// Local HTTP server speaking the MathSeek `/health`, `/recognize` and `/analyze` protocol
+///
+/// Runs on a background task until dropped.
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start on a random local port
+    pub async fn start(script: MockScript) -> MathSeekResult<Self> {
+        Self::bind("127.0.0.1:0", script).await
+    }
+
+    pub async fn bind(address: &str, script: MockScript) -> MathSeekResult<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+
+        // The port may have belonged to an earlier server in this process
+        let url = format!("http://{}", address);
+        crate::circuit_breaker::forget(&url);
+        crate::rate_limiter::forget(&url);
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let script = Arc::new(script);
+
+        let task_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = script.clone();
+                let state = task_state.clone();
+                tokio::spawn(async move {
+                    // A client hanging up mid-request is not an error for a mock
+                    let _ = Self::handle(stream, &script, &state).await;
+                });
+            }
+        });
+
+        Ok(Self { address, state, task: Some(task) })
+    }
+
+    /// Base URL to use as `ApiConfig::endpoint`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// All requests received so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock().requests.clone()
+    }
+
+    /// Number of requests received for a path
+    pub fn request_count(&self, path: &str) -> usize {
+        self.lock().requests.iter().filter(|request| request.path == path).count()
+    }
+
+    /// Wait until the server task stops, which only happens on a listener error
+    pub async fn wait(mut self) {
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    async fn handle(stream: TcpStream, script: &MockScript, state: &Mutex<MockState>) -> MathSeekResult<()> {
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default();
+        let path = target.split('?').next().unwrap_or_default().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+        }
+
+        let length = headers.get("content-length").and_then(|value| value.parse().ok()).unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+
+        let response = {
+            let mut state = state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.requests.push(MockRequest {
+                method,
+                path: path.clone(),
+                body: serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+            });
+            Self::pick_response(script, &mut state, &path, headers.get("authorization"))
+        };
+
+        tokio::time::sleep(Duration::from_millis(script.latency_ms + response.delay_ms)).await;
+
+        let mut stream = reader.into_inner();
+        if response.drop_connection {
+            return Ok(());
+        }
+
+        let body = serde_json::to_string(&response.body)?;
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status,
+            reqwest::StatusCode::from_u16(response.status).ok().and_then(|status| status.canonical_reason()).unwrap_or(""),
+            body.len(),
+        );
+        if let Some(seconds) = response.retry_after {
+            head.push_str(&format!("Retry-After: {}\r\n", seconds));
+        }
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Choose the response for a request, applying auth and failure injection
+    fn pick_response(script: &MockScript, state: &mut MockState, path: &str, authorization: Option<&String>) -> MockResponse {
+        if let Some(key) = &script.api_key {
+            if authorization.map(String::as_str) != Some(format!("Bearer {}", key).as_str()) {
+                return MockResponse::status(401, serde_json::json!({
+                    "error": { "code": "invalid_api_key", "message": "Invalid API key" }
+                }));
+            }
+        }
+
+        if script.failure_rate > 0.0 && fastrand::f64() < script.failure_rate {
+            return MockResponse::status(503, serde_json::json!({
+                "error": { "code": "injected_failure", "message": "Injected failure" }
+            }));
+        }
+
+        let Some(responses) = script.routes.get(path).filter(|responses| !responses.is_empty()) else {
+            return MockResponse::status(404, serde_json::json!({
+                "error": { "code": "not_found", "message": format!("No route for {}", path) }
+            }));
+        };
+
+        let served = state.served.entry(path.to_string()).or_insert(0);
+        let response = responses[(*served).min(responses.len() - 1)].clone();
+        *served += 1;
+        response
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+/// Parse a script file for the standalone server
+pub fn load_script(path: &str) -> MathSeekResult<MockScript> {
+    let content = std::fs::read_to_string(path)?;
+    serde_json::from_str(&content)
+        .map_err(|e| MathSeekError::ConfigError(format!("Invalid mock script {}: {}", path, e)))
+}
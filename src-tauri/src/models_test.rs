@@ -103,6 +103,48 @@ mod tests {
         assert!(config.validate().is_ok());
     }
 
+    #[test]
+    fn test_app_config_validation_keyless_backend() {
+        let mut config = AppConfig::default();
+        config.api_endpoint = "http://localhost:11434".to_string();
+        
+        // The default backend needs a key
+        assert!(config.validate().is_err());
+        
+        // Ollama runs without credentials
+        config.backend = "ollama".to_string();
+        assert!(config.validate().is_ok());
+        
+        // Unknown backends are rejected
+        config.backend = "unknown".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_app_config_validation_mathpix_app_id() {
+        let mut config = AppConfig::default();
+        config.backend = "mathpix".to_string();
+        config.api_endpoint = "https://api.mathpix.com".to_string();
+        config.api_key = "test-key".to_string();
+        
+        // Mathpix authenticates with an app ID as well as a key
+        assert!(config.validate().is_err());
+        
+        config.app_id = "my-app".to_string();
+        assert!(config.validate().is_ok());
+        
+        // Fallback providers are held to the same rule
+        config.fallback_providers.push(ProviderConfig {
+            backend: "mathpix".to_string(),
+            api_endpoint: "https://api.mathpix.com".to_string(),
+            api_key: "test-key".to_string(),
+            app_id: String::new(),
+            model: String::new(),
+            max_payload_bytes: 0,
+        });
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_formula_result_validation() {
         let mut result = FormulaResult::new_single_formula("E = mc^2".to_string(), 0.95);
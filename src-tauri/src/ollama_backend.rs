@@ -0,0 +1,344 @@
+use crate::{MathSeekError, MathSeekResult, FormulaResult, AnalysisResult, InputType, ApiClient, TokenUsage, PayloadFormat};
+use crate::recognition_backend::{self, PartialCallback, RecognitionBackend};
+use async_trait::async_trait;
+use base64::prelude::*;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Address of a default local Ollama installation
+pub const DEFAULT_OLLAMA_ENDPOINT: &str = "http://localhost:11434";
+
+/// Vision model used when `ApiConfig::model` is left empty
+pub const DEFAULT_OLLAMA_MODEL: &str = "llava";
+
+/// Request body for `/api/chat`
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+}
+
+/// Response body of a non-streaming `/api/chat` call
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: Option<OllamaResponseMessage>,
+    error: Option<String>,
+    /// Prompt tokens, reported once the answer is complete
+    #[serde(default)]
+    prompt_eval_count: u64,
+    /// Generated tokens, reported once the answer is complete
+    #[serde(default)]
+    eval_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponseMessage {
+    content: String,
+}
+
+/// Response body of `/api/tags`
+#[derive(Debug, Deserialize)]
+struct OllamaTagsResponse {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+/// Backend for a local or self-hosted Ollama server
+///
+/// Needs no API key, so it can run fully offline on air-gapped machines.
+pub struct OllamaBackend;
+
+impl OllamaBackend {
+    pub const NAME: &'static str = "ollama";
+
+    /// Model configured for the client, falling back to the default model
+    fn model(client: &ApiClient) -> String {
+        let model = &client.api_config().model;
+        if model.is_empty() {
+            DEFAULT_OLLAMA_MODEL.to_string()
+        } else {
+            model.clone()
+        }
+    }
+
+    fn build_recognition_request(
+        model: String,
+        temperature: f32,
+        prompt: String,
+        image_data: &[u8],
+        input_type: &InputType,
+        stream: bool,
+    ) -> OllamaChatRequest {
+        // Streamed documents are requested as Markdown, which must not be forced into JSON
+        let format = (matches!(input_type, InputType::Document) && !stream).then(|| "json".to_string());
+
+        OllamaChatRequest {
+            model,
+            messages: vec![
+                OllamaMessage {
+                    role: "system".to_string(),
+                    content: prompt,
+                    images: Vec::new(),
+                },
+                OllamaMessage {
+                    role: "user".to_string(),
+                    content: "Transcribe this image.".to_string(),
+                    images: vec![BASE64_STANDARD.encode(image_data)],
+                },
+            ],
+            stream,
+            format,
+            options: OllamaOptions { temperature },
+        }
+    }
+
+    fn build_analysis_request(model: String, formula: &str) -> OllamaChatRequest {
+        OllamaChatRequest {
+            model,
+            messages: vec![
+                OllamaMessage {
+                    role: "system".to_string(),
+                    content: recognition_backend::ANALYSIS_PROMPT.to_string(),
+                    images: Vec::new(),
+                },
+                OllamaMessage {
+                    role: "user".to_string(),
+                    content: formula.to_string(),
+                    images: Vec::new(),
+                },
+            ],
+            stream: false,
+            format: Some("json".to_string()),
+            options: OllamaOptions { temperature: 0.0 },
+        }
+    }
+
+    /// Extract the assistant message from a chat response
+    fn message_content(response: OllamaChatResponse) -> MathSeekResult<String> {
+        if let Some(error) = response.error {
+            return Err(MathSeekError::ApiError(format!("Ollama error: {}", error)));
+        }
+
+        response.message
+            .map(|message| message.content)
+            .ok_or_else(|| MathSeekError::ApiError("No message in Ollama response".to_string()))
+    }
+
+    fn token_usage(client: &ApiClient, model: &str, response: &OllamaChatResponse) -> TokenUsage {
+        client.token_usage(model, response.prompt_eval_count, response.eval_count)
+    }
+
+    /// Add a hint to network errors, which usually mean Ollama is not running
+    fn map_network_error(client: &ApiClient, err: MathSeekError) -> MathSeekError {
+        match err {
+            MathSeekError::NetworkError(msg) => MathSeekError::NetworkError(format!(
+                "Ollama is not reachable at {}: {}",
+                client.api_config().endpoint, msg
+            )),
+            other => other,
+        }
+    }
+
+    async fn fetch_tags(client: &ApiClient) -> MathSeekResult<OllamaTagsResponse> {
+        let response = client.send_get("/api/tags")
+            .await
+            .map_err(|e| Self::map_network_error(client, e))?;
+
+        if !response.status().is_success() {
+            return Err(client.error_from_response(response).await);
+        }
+
+        response.json()
+            .await
+            .map_err(|e| MathSeekError::SerializationError(format!("Failed to parse Ollama model list: {}", e)))
+    }
+}
+
+#[async_trait]
+impl RecognitionBackend for OllamaBackend {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    // Ollama takes bare base64 images and detects their format itself
+    async fn recognize(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        _format: PayloadFormat,
+        input_type: InputType,
+    ) -> MathSeekResult<FormulaResult> {
+        let model = Self::model(client);
+        let prompt = client.recognition_prompt(&input_type, false)?;
+        let request = Self::build_recognition_request(model.clone(), client.api_config().temperature, prompt, image_data, &input_type, false);
+        let response: OllamaChatResponse = client.make_request_with_retry("/api/chat", &request)
+            .await
+            .map_err(|e| Self::map_network_error(client, e))?;
+        let token_usage = client.record_usage(Self::token_usage(client, &model, &response));
+
+        let mut result = recognition_backend::parse_chat_recognition(&Self::message_content(response)?, input_type)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn recognize_streaming(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        _format: PayloadFormat,
+        input_type: InputType,
+        on_partial: PartialCallback<'_>,
+    ) -> MathSeekResult<FormulaResult> {
+        let model = Self::model(client);
+        let prompt = client.recognition_prompt(&input_type, true)?;
+        let request = Self::build_recognition_request(model.clone(), client.api_config().temperature, prompt, image_data, &input_type, true);
+        let mut text = String::new();
+        let mut token_usage = client.token_usage(&model, 0, 0);
+
+        // Each line of a streamed `/api/chat` response is a chat response with the next piece
+        client.stream_lines("/api/chat", &request, |line| {
+            let chunk: OllamaChatResponse = serde_json::from_str(line)?;
+            if let Some(error) = chunk.error {
+                return Err(MathSeekError::ApiError(format!("Ollama error: {}", error)));
+            }
+            // Only the final `done` line carries the token counts
+            if chunk.prompt_eval_count > 0 || chunk.eval_count > 0 {
+                token_usage = Self::token_usage(client, &model, &chunk);
+            }
+            if let Some(message) = chunk.message.filter(|message| !message.content.is_empty()) {
+                text.push_str(&message.content);
+                on_partial(&text);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e| Self::map_network_error(client, e))?;
+
+        let token_usage = client.record_usage(token_usage);
+        let mut result = recognition_backend::parse_chat_stream(&text, input_type)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn analyze(&self, client: &ApiClient, formula: &str) -> MathSeekResult<AnalysisResult> {
+        let model = Self::model(client);
+        let request = Self::build_analysis_request(model.clone(), formula);
+        let response: OllamaChatResponse = client.make_request_with_retry("/api/chat", &request)
+            .await
+            .map_err(|e| Self::map_network_error(client, e))?;
+        let token_usage = client.record_usage(Self::token_usage(client, &model, &response));
+
+        let mut result = recognition_backend::parse_chat_analysis(&Self::message_content(response)?)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn health(&self, client: &ApiClient) -> MathSeekResult<bool> {
+        Self::fetch_tags(client).await?;
+        Ok(true)
+    }
+
+    async fn list_models(&self, client: &ApiClient) -> MathSeekResult<Vec<String>> {
+        let tags = Self::fetch_tags(client).await?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    fn reports_confidence(&self) -> bool {
+        false
+    }
+
+    fn error_from_response(&self, status: StatusCode, retry_after_secs: Option<u64>, body: &str) -> MathSeekError {
+        let (code, message) = recognition_backend::parse_error_body(status, body);
+
+        if status == StatusCode::NOT_FOUND {
+            MathSeekError::ConfigError(format!(
+                "Ollama model not available: {} (install it with `ollama pull`)", message
+            ))
+        } else {
+            MathSeekError::http(status.as_u16(), code, format!("Ollama: {}", message), retry_after_secs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recognition_request_serialization() {
+        let request = OllamaBackend::build_recognition_request(
+            "llava".to_string(),
+            0.0,
+            "Transcribe.".to_string(),
+            b"image",
+            &InputType::Document,
+            false,
+        );
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["model"], "llava");
+        assert_eq!(json["stream"], false);
+        assert_eq!(json["format"], "json");
+
+        let streamed = OllamaBackend::build_recognition_request(
+            "llava".to_string(),
+            0.7,
+            "Transcribe.".to_string(),
+            b"image",
+            &InputType::Document,
+            true,
+        );
+        let streamed = serde_json::to_value(&streamed).unwrap();
+        assert_eq!(streamed["stream"], true);
+        assert_eq!(streamed["options"]["temperature"], 0.7f32);
+        assert!(streamed.get("format").is_none());
+        assert!(json["messages"][0].get("images").is_none());
+        assert_eq!(json["messages"][1]["images"][0], BASE64_STANDARD.encode(b"image"));
+    }
+
+    #[test]
+    fn test_tags_response_parsing() {
+        let tags: OllamaTagsResponse = serde_json::from_str(
+            r#"{"models": [{"name": "llava:latest", "model": "llava:latest", "size": 4733363377}]}"#
+        ).unwrap();
+        assert_eq!(tags.models[0].name, "llava:latest");
+    }
+
+    #[test]
+    fn test_error_mapping() {
+        let backend = OllamaBackend;
+        assert!(!backend.requires_api_key());
+
+        let missing = backend.error_from_response(StatusCode::NOT_FOUND, None, r#"{"error": "model \"llava\" not found"}"#);
+        assert!(matches!(missing, MathSeekError::ConfigError(_)));
+
+        let failure = backend.error_from_response(StatusCode::INTERNAL_SERVER_ERROR, None, r#"{"error": "boom"}"#);
+        assert!(matches!(failure, MathSeekError::HttpError { status: 500, ref message, .. } if message.contains("boom")));
+        assert!(failure.is_retryable());
+    }
+}
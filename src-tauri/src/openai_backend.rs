@@ -0,0 +1,346 @@
+use crate::{MathSeekError, MathSeekResult, FormulaResult, AnalysisResult, InputType, ApiClient, TokenUsage, PayloadBudget, PayloadFormat};
+use crate::recognition_backend::{self, PartialCallback, RecognitionBackend};
+use async_trait::async_trait;
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Model used when `ApiConfig::model` is left empty
+pub const DEFAULT_OPENAI_MODEL: &str = "gpt-4o";
+
+/// Request body for `/chat/completions`
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+/// Asks for a final chunk carrying the token usage of a streamed completion
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage {
+    role: String,
+    content: MessageContent,
+}
+
+/// Message content, either plain text or a list of typed parts
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+/// Response body of `/chat/completions`
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+}
+
+/// Token counts of a completion
+#[derive(Debug, Deserialize)]
+struct ChatUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseMessage {
+    content: Option<String>,
+}
+
+/// One `data:` event of a streamed completion
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    choices: Vec<ChatChunkChoice>,
+    /// Only set on the last chunk, whose `choices` are empty
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChunkChoice {
+    delta: ChatResponseMessage,
+}
+
+/// Backend for OpenAI-compatible `/chat/completions` vision servers
+///
+/// Works with OpenAI itself as well as vLLM, llama.cpp server and other
+/// gateways implementing the same API.
+pub struct OpenAiBackend;
+
+impl OpenAiBackend {
+    pub const NAME: &'static str = "openai";
+
+    /// Model configured for the client, falling back to the default model
+    fn model(client: &ApiClient) -> String {
+        let model = &client.api_config().model;
+        if model.is_empty() {
+            DEFAULT_OPENAI_MODEL.to_string()
+        } else {
+            model.clone()
+        }
+    }
+
+    /// Encode image bytes as a `data:` URI of their payload format
+    fn image_data_uri(image_data: &[u8], format: PayloadFormat) -> String {
+        format!("data:{};base64,{}", format.mime_type(), BASE64_STANDARD.encode(image_data))
+    }
+
+    fn build_recognition_request(
+        model: String,
+        temperature: f32,
+        prompt: String,
+        image_data: &[u8],
+        format: PayloadFormat,
+        stream: bool,
+    ) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model,
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: MessageContent::Text(prompt),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: MessageContent::Parts(vec![
+                        ContentPart::Text { text: "Transcribe this image.".to_string() },
+                        ContentPart::ImageUrl { image_url: ImageUrl { url: Self::image_data_uri(image_data, format) } },
+                    ]),
+                },
+            ],
+            temperature,
+            stream,
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+        }
+    }
+
+    fn build_analysis_request(model: String, formula: &str) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model,
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: MessageContent::Text(recognition_backend::ANALYSIS_PROMPT.to_string()),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: MessageContent::Text(formula.to_string()),
+                },
+            ],
+            temperature: 0.0,
+            stream: false,
+            stream_options: None,
+        }
+    }
+
+    /// Extract `choices[0].message.content` from a completion
+    fn message_content(response: ChatCompletionResponse) -> MathSeekResult<String> {
+        response.choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| MathSeekError::ApiError("No message content in chat completion".to_string()))
+    }
+
+    fn token_usage(client: &ApiClient, model: &str, usage: Option<&ChatUsage>) -> TokenUsage {
+        let (input_tokens, output_tokens) = usage.map_or((0, 0), |usage| (usage.prompt_tokens, usage.completion_tokens));
+        client.token_usage(model, input_tokens, output_tokens)
+    }
+
+    /// Chunk carried by one SSE line, or `None` for comments, keep-alives and `[DONE]`
+    fn sse_chunk(line: &str) -> MathSeekResult<Option<ChatCompletionChunk>> {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return Ok(None);
+        };
+        if data == "[DONE]" {
+            return Ok(None);
+        }
+
+        match serde_json::from_str::<ChatCompletionChunk>(data) {
+            Ok(chunk) => Ok(Some(chunk)),
+            // Providers report failures after the stream has started as an error event
+            Err(_) => {
+                let (_, message) = recognition_backend::parse_error_body(reqwest::StatusCode::OK, data);
+                Err(MathSeekError::ApiError(format!("Stream error: {}", message)))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RecognitionBackend for OpenAiBackend {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn reports_confidence(&self) -> bool {
+        false
+    }
+
+    /// Vision models take PNG, JPEG and WebP images of up to 20 MB
+    fn payload_budget(&self) -> PayloadBudget {
+        PayloadBudget {
+            max_encoded_bytes: 20 * 1024 * 1024,
+            formats: &[PayloadFormat::Png, PayloadFormat::WebP, PayloadFormat::Jpeg],
+        }
+    }
+
+    async fn recognize(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        format: PayloadFormat,
+        input_type: InputType,
+    ) -> MathSeekResult<FormulaResult> {
+        let model = Self::model(client);
+        let prompt = client.recognition_prompt(&input_type, false)?;
+        let request = Self::build_recognition_request(model.clone(), client.api_config().temperature, prompt, image_data, format, false);
+        let response: ChatCompletionResponse = client.make_request_with_retry("/chat/completions", &request).await?;
+        let token_usage = client.record_usage(Self::token_usage(client, &model, response.usage.as_ref()));
+
+        let mut result = recognition_backend::parse_chat_recognition(&Self::message_content(response)?, input_type)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn recognize_streaming(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        format: PayloadFormat,
+        input_type: InputType,
+        on_partial: PartialCallback<'_>,
+    ) -> MathSeekResult<FormulaResult> {
+        let model = Self::model(client);
+        let prompt = client.recognition_prompt(&input_type, true)?;
+        let request = Self::build_recognition_request(model.clone(), client.api_config().temperature, prompt, image_data, format, true);
+        let mut text = String::new();
+        let mut usage = None;
+
+        client.stream_lines("/chat/completions", &request, |line| {
+            let Some(chunk) = Self::sse_chunk(line)? else {
+                return Ok(());
+            };
+            if chunk.usage.is_some() {
+                usage = chunk.usage;
+            }
+            if let Some(delta) = chunk.choices.into_iter().next().and_then(|choice| choice.delta.content) {
+                text.push_str(&delta);
+                on_partial(&text);
+            }
+            Ok(())
+        }).await?;
+
+        let token_usage = client.record_usage(Self::token_usage(client, &model, usage.as_ref()));
+        let mut result = recognition_backend::parse_chat_stream(&text, input_type)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn analyze(&self, client: &ApiClient, formula: &str) -> MathSeekResult<AnalysisResult> {
+        let model = Self::model(client);
+        let request = Self::build_analysis_request(model.clone(), formula);
+        let response: ChatCompletionResponse = client.make_request_with_retry("/chat/completions", &request).await?;
+        let token_usage = client.record_usage(Self::token_usage(client, &model, response.usage.as_ref()));
+
+        let mut result = recognition_backend::parse_chat_analysis(&Self::message_content(response)?)?;
+        result.token_usage = Some(token_usage);
+        Ok(result)
+    }
+
+    async fn health(&self, client: &ApiClient) -> MathSeekResult<bool> {
+        let response = client.send_get("/models").await?;
+
+        Ok(response.status().is_success())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recognition_request_serialization() {
+        let request = OpenAiBackend::build_recognition_request(
+            "test-model".to_string(),
+            0.0,
+            "Transcribe.".to_string(),
+            b"jpeg bytes",
+            PayloadFormat::Jpeg,
+            false,
+        );
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["model"], "test-model");
+        assert_eq!(json["messages"][0]["role"], "system");
+        assert_eq!(json["messages"][0]["content"], "Transcribe.");
+        assert_eq!(json["messages"][1]["content"][1]["type"], "image_url");
+        assert!(json["messages"][1]["content"][1]["image_url"]["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/jpeg;base64,"));
+    }
+
+    #[test]
+    fn test_message_content_extraction() {
+        let response: ChatCompletionResponse = serde_json::from_str(
+            r#"{"id": "x", "choices": [{"index": 0, "message": {"role": "assistant", "content": "x^2"}}]}"#
+        ).unwrap();
+        assert_eq!(OpenAiBackend::message_content(response).unwrap(), "x^2");
+
+        let empty: ChatCompletionResponse = serde_json::from_str(r#"{"choices": []}"#).unwrap();
+        assert!(OpenAiBackend::message_content(empty).is_err());
+    }
+
+    #[test]
+    fn test_sse_chunk() {
+        fn delta(line: &str) -> Option<String> {
+            OpenAiBackend::sse_chunk(line).unwrap()?.choices.into_iter().next()?.delta.content
+        }
+
+        let line = r#"data: {"id": "x", "choices": [{"index": 0, "delta": {"content": "x^"}}]}"#;
+        assert_eq!(delta(line).as_deref(), Some("x^"));
+
+        let role_only = r#"data: {"choices": [{"index": 0, "delta": {"role": "assistant"}}]}"#;
+        assert_eq!(delta(role_only), None);
+        assert!(OpenAiBackend::sse_chunk("data: [DONE]").unwrap().is_none());
+        assert!(OpenAiBackend::sse_chunk(": keep-alive").unwrap().is_none());
+
+        let usage_line = r#"data: {"choices": [], "usage": {"prompt_tokens": 812, "completion_tokens": 9, "total_tokens": 821}}"#;
+        let usage = OpenAiBackend::sse_chunk(usage_line).unwrap().unwrap().usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (812, 9));
+
+        let error = OpenAiBackend::sse_chunk(r#"data: {"error": {"message": "overloaded"}}"#).unwrap_err();
+        assert!(error.to_string().contains("overloaded"));
+    }
+}
@@ -0,0 +1,409 @@
+use crate::preprocessing;
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+
+/// Longest side analyzed; angles do not depend on scale
+const ANALYSIS_SIZE: u32 = 1000;
+
+/// Ink pixels sampled for the profile search
+const MAX_POINTS: usize = 150_000;
+
+/// Fewer ink pixels than this carry no orientation
+const MIN_POINTS: usize = 50;
+
+/// Smallest gap between the mean gray of ink and paper that counts as content
+const MIN_CONTRAST: f32 = 40.0;
+
+/// Skew searched on either side of each quarter turn, in degrees
+const MAX_SKEW: f32 = 15.0;
+
+/// Skews smaller than this are left alone, in degrees
+const MIN_SKEW: f32 = 0.3;
+
+/// A text line is at least this many times longer than it is thick...
+const LINE_ELONGATION: f32 = 4.0;
+/// ...and ink covers this share of its length
+const LINE_FILL: f32 = 0.6;
+
+/// Sideways text needs this many lines...
+const MIN_SIDEWAYS_LINES: usize = 3;
+/// ...and this many times the line length found upright
+const SIDEWAYS_MARGIN: f32 = 2.0;
+
+/// Upside-down text needs this many lines...
+const MIN_FLIP_LINES: usize = 3;
+/// ...with this many times more ink below their core than above
+const FLIP_MARGIN: f32 = 1.5;
+
+/// How to turn an image so that its text is upright and level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Orientation {
+    /// Quarter turns clockwise, in degrees: 0, 90, 180 or 270
+    pub rotation: u32,
+    /// Further clockwise rotation in degrees that levels the text lines
+    pub skew: f32,
+}
+
+impl Orientation {
+    /// Total clockwise rotation in degrees
+    pub fn angle(&self) -> f32 {
+        self.rotation as f32 + self.skew
+    }
+
+    pub fn is_upright(&self) -> bool {
+        self.rotation == 0 && self.skew == 0.0
+    }
+}
+
+/// Estimate how the text in `gray` is rotated
+///
+/// The skew is the angle at which the horizontal projection profile of the
+/// ink is sharpest, searched near level and near sideways. Text is taken to
+/// run sideways when at least three long, filled lines show up only there,
+/// so a single formula line or a column vector stays as it is. Lines are
+/// upside down when more of their ink lies below the dense x-height core
+/// than above it, as Latin ascenders, capitals and digits outnumber
+/// descenders. That takes several lines too, since a single formula with
+/// many subscripts looks the same.
+pub fn estimate(gray: &GrayImage) -> Orientation {
+    let points = ink_points(gray);
+    if points.len() < MIN_POINTS {
+        return Orientation::default();
+    }
+
+    let level = level_angle(&points, 0.0);
+    let sideways = level_angle(&points, 90.0);
+    let level_lines = lines(&points, level);
+    let sideways_lines = lines(&points, sideways);
+
+    let length = |lines: &[TextLine]| lines.iter().map(|line| line.length).sum::<f32>();
+    let (mut angle, chosen) = if sideways_lines.len() >= MIN_SIDEWAYS_LINES
+        && length(&sideways_lines) > SIDEWAYS_MARGIN * length(&level_lines)
+    {
+        (sideways, sideways_lines)
+    } else {
+        (level, level_lines)
+    };
+
+    let above: u64 = chosen.iter().map(|line| line.above).sum();
+    let below: u64 = chosen.iter().map(|line| line.below).sum();
+    if chosen.len() >= MIN_FLIP_LINES && below as f32 > FLIP_MARGIN * above as f32 {
+        angle += 180.0;
+    }
+
+    let quarter_turns = (angle / 90.0).round();
+    let skew = angle - quarter_turns * 90.0;
+    Orientation {
+        rotation: (quarter_turns as i32).rem_euclid(4) as u32 * 90,
+        skew: if skew.abs() < MIN_SKEW { 0.0 } else { (skew * 10.0).round() / 10.0 },
+    }
+}
+
+/// Turn `gray` by `orientation`, filling uncovered corners with the paper level
+pub fn apply(gray: &GrayImage, orientation: &Orientation) -> GrayImage {
+    let turned = match orientation.rotation {
+        90 => image::imageops::rotate90(gray),
+        180 => image::imageops::rotate180(gray),
+        270 => image::imageops::rotate270(gray),
+        _ => gray.clone(),
+    };
+    if orientation.skew == 0.0 {
+        return turned;
+    }
+
+    let paper = preprocessing::histogram(&turned)
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, &count)| count)
+        .map(|(level, _)| level as f32)
+        .unwrap_or(255.0);
+
+    // Grow the canvas so that no corner is cut off
+    let (sin, cos) = orientation.skew.to_radians().sin_cos();
+    let (width, height) = (turned.width() as f32, turned.height() as f32);
+    let new_width = (width * cos.abs() + height * sin.abs()).ceil() as u32;
+    let new_height = (width * sin.abs() + height * cos.abs()).ceil() as u32;
+    let (center_x, center_y) = (width / 2.0, height / 2.0);
+    let (new_center_x, new_center_y) = (new_width as f32 / 2.0, new_height as f32 / 2.0);
+
+    GrayImage::from_fn(new_width, new_height, |x, y| {
+        // Turn the output pixel back to find where it comes from
+        let (dx, dy) = (x as f32 + 0.5 - new_center_x, y as f32 + 0.5 - new_center_y);
+        let source_x = dx * cos + dy * sin + center_x - 0.5;
+        let source_y = -dx * sin + dy * cos + center_y - 0.5;
+        Luma([preprocessing::sample(&turned, source_x, source_y, paper).round() as u8])
+    })
+}
+
+/// Coordinates of ink pixels in a copy scaled to the analysis size
+///
+/// Ink is the minority side of Otsu's threshold, so light-on-dark images
+/// work too.
+fn ink_points(gray: &GrayImage) -> Vec<(f32, f32)> {
+    let (width, height) = gray.dimensions();
+    let longest = width.max(height);
+    let scaled;
+    let gray = if longest > ANALYSIS_SIZE {
+        let factor = ANALYSIS_SIZE as f32 / longest as f32;
+        scaled = image::imageops::resize(
+            gray,
+            ((width as f32 * factor).round() as u32).max(1),
+            ((height as f32 * factor).round() as u32).max(1),
+            image::imageops::FilterType::Triangle,
+        );
+        &scaled
+    } else {
+        gray
+    };
+
+    let histogram = preprocessing::histogram(gray);
+    let threshold = match preprocessing::otsu_threshold(&histogram) {
+        Some((threshold, contrast)) if contrast >= MIN_CONTRAST => threshold,
+        _ => return Vec::new(),
+    };
+    let dark: u64 = histogram[..=threshold as usize].iter().sum();
+    let dark_is_ink = dark * 2 <= gray.len() as u64;
+
+    let ink = || gray.enumerate_pixels().filter(|(_, _, pixel)| (pixel[0] <= threshold) == dark_is_ink);
+    let step = ink().count().div_ceil(MAX_POINTS).max(1);
+    ink().step_by(step).map(|(x, y, _)| (x as f32, y as f32)).collect()
+}
+
+/// Clockwise rotation by `angle` degrees, as applied to a point
+fn rotation(angle: f32) -> impl Fn(&(f32, f32)) -> (f32, f32) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    move |&(x, y)| (x * cos - y * sin, x * sin + y * cos)
+}
+
+/// Bins of the horizontal projection profile after turning the points by `angle`
+fn profile(points: &[(f32, f32)], angle: f32) -> (Vec<u32>, f32) {
+    let turn = rotation(angle);
+    let rows: Vec<f32> = points.iter().map(|point| turn(point).1).collect();
+    let top = rows.iter().copied().fold(f32::INFINITY, f32::min);
+    let bottom = rows.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+    let mut bins = vec![0u32; (bottom - top) as usize + 1];
+    for row in rows {
+        bins[(row - top) as usize] += 1;
+    }
+    (bins, top)
+}
+
+/// Sum of squared steps between neighbouring bins, largest when lines are level
+fn sharpness(points: &[(f32, f32)], angle: f32) -> f64 {
+    profile(points, angle).0
+        .windows(2)
+        .map(|pair| (pair[1] as f64 - pair[0] as f64).powi(2))
+        .sum()
+}
+
+/// Angle within `MAX_SKEW` of `base` that levels the text lines best
+fn level_angle(points: &[(f32, f32)], base: f32) -> f32 {
+    let best = |angles: &mut dyn Iterator<Item = f32>| {
+        angles
+            .map(|angle| (angle, sharpness(points, angle)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(angle, _)| angle)
+            .unwrap_or(base)
+    };
+
+    let max_skew = MAX_SKEW as i32;
+    let coarse = best(&mut (-max_skew..=max_skew).map(|step| base + step as f32));
+    let fine = best(&mut (-10..=10).map(|step| coarse + step as f32 / 10.0));
+
+    // Keep the base unless turning is clearly better
+    if sharpness(points, fine) > 1.02 * sharpness(points, base) { fine } else { base }
+}
+
+/// A long band of the profile that looks like a line of text
+struct TextLine {
+    length: f32,
+    /// Ink above and below the dense core of the line
+    above: u64,
+    below: u64,
+}
+
+/// Text lines among the bands of the profile after turning the points by `angle`
+fn lines(points: &[(f32, f32)], angle: f32) -> Vec<TextLine> {
+    let (bins, top) = profile(points, angle);
+
+    // Band of each bin, and the bin range of each band
+    let mut band_of = vec![usize::MAX; bins.len()];
+    let mut bands: Vec<(usize, usize)> = Vec::new();
+    for (bin, &count) in bins.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        match bands.last_mut() {
+            Some(band) if band.1 + 1 == bin => band.1 = bin,
+            _ => bands.push((bin, bin)),
+        }
+        band_of[bin] = bands.len() - 1;
+    }
+
+    // Extent of each band along the line
+    let turn = rotation(angle);
+    let turned: Vec<(usize, f32)> = points.iter()
+        .map(|point| {
+            let (x, y) = turn(point);
+            (band_of[(y - top) as usize], x)
+        })
+        .collect();
+    let mut extents = vec![(f32::INFINITY, f32::NEG_INFINITY); bands.len()];
+    for &(band, x) in &turned {
+        extents[band] = (extents[band].0.min(x), extents[band].1.max(x));
+    }
+    let mut covered: Vec<Vec<bool>> = extents.iter()
+        .map(|&(left, right)| vec![false; (right - left) as usize + 1])
+        .collect();
+    for &(band, x) in &turned {
+        covered[band][(x - extents[band].0) as usize] = true;
+    }
+
+    bands.iter()
+        .zip(extents.iter().zip(&covered))
+        .filter_map(|(&(first, last), (&(left, right), covered))| {
+            let thickness = (last - first + 1) as f32;
+            let length = right - left + 1.0;
+            let fill = covered.iter().filter(|&&covered| covered).count() as f32 / covered.len() as f32;
+            if length < LINE_ELONGATION * thickness || fill < LINE_FILL {
+                return None;
+            }
+
+            let counts = &bins[first..=last];
+            let peak = counts.iter().copied().max().unwrap_or(0);
+            let core_start = counts.iter().position(|&count| 2 * count >= peak).unwrap_or(0);
+            let core_end = counts.iter().rposition(|&count| 2 * count >= peak).unwrap_or(counts.len() - 1);
+            Some(TextLine {
+                length,
+                above: counts[..core_start].iter().map(|&count| count as u64).sum(),
+                below: counts[core_end + 1..].iter().map(|&count| count as u64).sum(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines of "text": letters of x-height 10 with an ascender on every
+    /// other letter and a descender on every sixth, in words of six
+    fn page(lines: u32) -> GrayImage {
+        let mut page = GrayImage::from_pixel(400, 60 + lines * 30, Luma([250]));
+        for line in 0..lines {
+            let baseline = 40 + line * 30;
+            for letter in 0..36 {
+                let x = 20 + letter * 9 + letter / 6 * 6 + line * 4 % 9;
+                let top = if letter % 2 == 0 { baseline - 16 } else { baseline - 10 };
+                let bottom = if letter % 6 == 1 { baseline + 5 } else { baseline };
+                for y in top..bottom {
+                    for dx in 0..7 {
+                        let stroke = dx < 2 || (baseline - 10..baseline).contains(&y);
+                        if stroke {
+                            page.put_pixel(x + dx, y, Luma([30]));
+                        }
+                    }
+                }
+            }
+        }
+        page
+    }
+
+    fn turned(image: &GrayImage, angle: f32) -> GrayImage {
+        apply(image, &Orientation { rotation: 0, skew: angle })
+    }
+
+    #[test]
+    fn test_upright_text_is_left_alone() {
+        assert_eq!(estimate(&page(5)), Orientation::default());
+
+        let blank = GrayImage::from_pixel(100, 100, Luma([255]));
+        assert_eq!(estimate(&blank), Orientation::default());
+    }
+
+    #[test]
+    fn test_small_skews_are_levelled() {
+        let upright = page(5);
+        for angle in [-4.0f32, 2.5, 7.0] {
+            let estimated = estimate(&turned(&upright, angle));
+            assert_eq!(estimated.rotation, 0, "{}", angle);
+            assert!((estimated.skew + angle).abs() <= 0.3, "{} estimated as {:?}", angle, estimated);
+        }
+    }
+
+    #[test]
+    fn test_quarter_turns_are_detected() {
+        let upright = page(5);
+        let cases = [
+            (image::imageops::rotate90(&upright), 270),
+            (image::imageops::rotate180(&upright), 180),
+            (image::imageops::rotate270(&upright), 90),
+        ];
+        for (image, expected) in cases {
+            let estimated = estimate(&image);
+            assert_eq!((estimated.rotation, estimated.skew), (expected, 0.0));
+            assert_eq!(apply(&image, &estimated), upright);
+        }
+
+        // Sideways and skewed together
+        let estimated = estimate(&turned(&image::imageops::rotate90(&upright), 3.0));
+        assert_eq!(estimated.rotation, 270);
+        assert!((estimated.skew + 3.0).abs() <= 0.3, "{:?}", estimated);
+    }
+
+    #[test]
+    fn test_column_vector_is_not_turned() {
+        // Brackets around three stacked glyphs
+        let mut image = GrayImage::from_pixel(60, 120, Luma([255]));
+        for y in 10..110 {
+            for x in [10, 11, 48, 49] {
+                image.put_pixel(x, y, Luma([0]));
+            }
+        }
+        for row in 0..3 {
+            for y in 20 + row * 30..32 + row * 30 {
+                for x in 24..34 {
+                    image.put_pixel(x, y, Luma([0]));
+                }
+            }
+        }
+        assert_eq!(estimate(&image).rotation, 0);
+    }
+
+    #[test]
+    fn test_subscripted_formula_is_not_flipped() {
+        // x_1 + x_2 + ...: all the ink outside the core hangs below it
+        let mut image = GrayImage::from_pixel(520, 60, Luma([255]));
+        let baseline = 30;
+        for term in 0..20 {
+            let x = 10 + term * 25;
+            for y in baseline - 10..baseline {
+                for dx in 0..7 {
+                    image.put_pixel(x + dx, y, Luma([0]));
+                }
+            }
+            for y in baseline - 3..baseline + 5 {
+                for dx in 8..13 {
+                    image.put_pixel(x + dx, y, Luma([0]));
+                }
+            }
+            for dx in 15..22 {
+                image.put_pixel(x + dx, baseline - 5, Luma([0]));
+            }
+            for y in baseline - 8..baseline - 1 {
+                image.put_pixel(x + 18, y, Luma([0]));
+            }
+        }
+        assert_eq!(estimate(&image), Orientation::default());
+    }
+
+    #[test]
+    fn test_apply_grows_canvas_with_paper() {
+        let image = GrayImage::from_pixel(100, 50, Luma([240]));
+        let skewed = apply(&image, &Orientation { rotation: 90, skew: 10.0 });
+        assert_eq!(skewed.dimensions(), (67, 108));
+        assert_eq!(skewed.get_pixel(0, 0)[0], 240);
+        assert_eq!(Orientation { rotation: 90, skew: 10.0 }.angle(), 100.0);
+    }
+}
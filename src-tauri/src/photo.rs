@@ -0,0 +1,326 @@
+use crate::preprocessing;
+use image::{GrayImage, Luma};
+
+/// Longest side searched for the page outline
+const PAGE_ANALYSIS_SIZE: u32 = 500;
+
+/// Blur that wipes out writing but keeps the page edges, at the analysis size
+const PAGE_BLUR: f32 = 3.0;
+
+/// Smallest gap between the mean gray of page and surroundings
+const MIN_PAGE_CONTRAST: f32 = 30.0;
+
+/// A page covers at least this share of the photo...
+const MIN_PAGE_AREA: f32 = 0.2;
+/// ...and less than this, or the photo is already of the page alone
+const MAX_PAGE_AREA: f32 = 0.95;
+
+/// Longest side of the copy the illumination is estimated on
+const BACKGROUND_SIZE: u32 = 100;
+
+/// Radius of the median taken as the local background, on that copy
+const BACKGROUND_RADIUS: i64 = 12;
+
+/// Corners of a page: top left, top right, bottom right, bottom left
+pub type Quad = [(f32, f32); 4];
+
+/// Projective map of the plane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography([f64; 9]);
+
+impl Homography {
+    /// The map taking each of `from` to the matching corner of `to`, if the corners are in general position
+    pub fn from_points(from: &Quad, to: &Quad) -> Option<Self> {
+        // Eight equations in the first eight entries, with the last fixed at one
+        let mut system = [[0f64; 9]; 8];
+        for (index, (&(x, y), &(u, v))) in from.iter().zip(to).enumerate() {
+            let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+            system[2 * index] = [x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u, u];
+            system[2 * index + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v, v];
+        }
+
+        for column in 0..8 {
+            let pivot = (column..8).max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))?;
+            if system[pivot][column].abs() < 1e-9 {
+                return None;
+            }
+            system.swap(column, pivot);
+            let pivot_row = system[column];
+            for (index, row) in system.iter_mut().enumerate() {
+                if index != column {
+                    let factor = row[column] / pivot_row[column];
+                    for (entry, pivot_entry) in row.iter_mut().zip(pivot_row).skip(column) {
+                        *entry -= factor * pivot_entry;
+                    }
+                }
+            }
+        }
+
+        let mut matrix = [1.0; 9];
+        for (index, row) in system.iter().enumerate() {
+            matrix[index] = row[8] / row[index];
+        }
+        Some(Self(matrix))
+    }
+
+    pub fn map(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let [a, b, c, d, e, f, g, h, i] = self.0;
+        let (x, y) = (x as f64, y as f64);
+        let w = g * x + h * y + i;
+        (((a * x + b * y + c) / w) as f32, ((d * x + e * y + f) / w) as f32)
+    }
+}
+
+/// Find the outline of a page or board that stands out from its surroundings
+///
+/// The photo is shrunk and blurred until the writing is gone and split into
+/// two classes by Otsu's threshold. The page is the region of whichever
+/// class covers most of the middle of the photo, and its corners are the
+/// points furthest toward each corner of the photo. `None` when there is no
+/// clear outline or the page already fills the photo.
+pub fn find_page(gray: &GrayImage) -> Option<Quad> {
+    let (width, height) = gray.dimensions();
+    let factor = (PAGE_ANALYSIS_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let (small_width, small_height) = (
+        ((width as f32 * factor).round() as u32).max(1),
+        ((height as f32 * factor).round() as u32).max(1),
+    );
+    let small = image::imageops::resize(gray, small_width, small_height, image::imageops::FilterType::Triangle);
+    let small = image::imageops::blur(&small, PAGE_BLUR);
+
+    let (threshold, contrast) = preprocessing::otsu_threshold(&preprocessing::histogram(&small))?;
+    if contrast < MIN_PAGE_CONTRAST {
+        return None;
+    }
+    let is_dark = |x: u32, y: u32| small.get_pixel(x, y)[0] <= threshold;
+    let middle: Vec<(u32, u32)> = (small_height / 4..small_height * 3 / 4)
+        .flat_map(|y| (small_width / 4..small_width * 3 / 4).map(move |x| (x, y)))
+        .collect();
+    let dark_middle = middle.iter().filter(|&&(x, y)| is_dark(x, y)).count();
+    let page_is_dark = dark_middle * 2 > middle.len();
+
+    let score = |(x, y): (u32, u32), corner: usize| match corner {
+        0 => -(x as i64) - y as i64,
+        1 => x as i64 - y as i64,
+        2 => x as i64 + y as i64,
+        _ => y as i64 - x as i64,
+    };
+
+    // The largest region of the page's class reaching into the middle
+    let mut seen = vec![false; small.len()];
+    let mut page: Option<(usize, [(u32, u32); 4])> = None;
+    for seed in middle.into_iter().filter(|&(x, y)| is_dark(x, y) == page_is_dark) {
+        if seen[(seed.1 * small_width + seed.0) as usize] {
+            continue;
+        }
+        seen[(seed.1 * small_width + seed.0) as usize] = true;
+
+        let (mut size, mut corners, mut stack) = (0, [seed; 4], vec![seed]);
+        while let Some((x, y)) = stack.pop() {
+            size += 1;
+            for (corner, best) in corners.iter_mut().enumerate() {
+                if score((x, y), corner) > score(*best, corner) {
+                    *best = (x, y);
+                }
+            }
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx >= small_width || ny >= small_height {
+                    continue;
+                }
+                let index = (ny * small_width + nx) as usize;
+                if !seen[index] && is_dark(nx, ny) == page_is_dark {
+                    seen[index] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+
+        if page.is_none_or(|(largest, _)| size > largest) {
+            page = Some((size, corners));
+        }
+    }
+    let (_, corners) = page?;
+
+    let quad: Quad = corners.map(|(x, y)| ((x as f32 + 0.5) / factor, (y as f32 + 0.5) / factor));
+    let share = area(&quad) / (width as f32 * height as f32);
+    if !(MIN_PAGE_AREA..MAX_PAGE_AREA).contains(&share) || !is_convex(&quad) {
+        return None;
+    }
+    Some(quad)
+}
+
+/// Warp the page inside `quad` to an upright rectangle
+///
+/// The rectangle takes the longer of each pair of opposite sides.
+pub fn flatten_page(gray: &GrayImage, quad: &Quad) -> Option<GrayImage> {
+    let distance = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+    let width = distance(quad[0], quad[1]).max(distance(quad[3], quad[2])).round().max(1.0);
+    let height = distance(quad[0], quad[3]).max(distance(quad[1], quad[2])).round().max(1.0);
+
+    let rectangle: Quad = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    let to_photo = Homography::from_points(&rectangle, quad)?;
+
+    Some(GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        let (source_x, source_y) = to_photo.map((x as f32 + 0.5, y as f32 + 0.5));
+        Luma([preprocessing::sample(gray, source_x - 0.5, source_y - 0.5, 255.0).round() as u8])
+    }))
+}
+
+/// Even out shadows and uneven lighting
+///
+/// The local background is the median over a neighbourhood a quarter of
+/// the photo across, which neither writing nor a filled shape in it can
+/// shift. Light paper is divided by it, so that paper becomes white in shade
+/// and in light alike; on a dark board it is subtracted instead and the
+/// result inverted, so that the board becomes white and the chalk dark.
+pub fn normalize_illumination(gray: &GrayImage) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    let factor = (BACKGROUND_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let small = image::imageops::resize(
+        gray,
+        ((width as f32 * factor).round() as u32).max(1),
+        ((height as f32 * factor).round() as u32).max(1),
+        image::imageops::FilterType::Triangle,
+    );
+
+    let (small_width, small_height) = (small.width() as i64, small.height() as i64);
+    let small_background = GrayImage::from_fn(small.width(), small.height(), |x, y| {
+        let mut levels = Vec::with_capacity(((2 * BACKGROUND_RADIUS + 1) as usize).pow(2));
+        for dy in -BACKGROUND_RADIUS..=BACKGROUND_RADIUS {
+            for dx in -BACKGROUND_RADIUS..=BACKGROUND_RADIUS {
+                // Repeating the border keeps the median centred near the edges
+                let nx = (x as i64 + dx).clamp(0, small_width - 1);
+                let ny = (y as i64 + dy).clamp(0, small_height - 1);
+                levels.push(small.get_pixel(nx as u32, ny as u32)[0]);
+            }
+        }
+        levels.sort_unstable();
+        Luma([levels[levels.len() / 2]])
+    });
+    let background = image::imageops::resize(&small_background, width, height, image::imageops::FilterType::Triangle);
+
+    let mean = background.pixels().map(|pixel| pixel[0] as f64).sum::<f64>() / background.len().max(1) as f64;
+    let light_paper = mean >= 128.0;
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let level = gray.get_pixel(x, y)[0] as f32;
+        let paper = background.get_pixel(x, y)[0] as f32;
+        let normalized = if light_paper {
+            level * 255.0 / paper.max(1.0)
+        } else {
+            255.0 - (level - paper) * 255.0 / (255.0 - paper).max(1.0)
+        };
+        Luma([normalized.round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+fn area(quad: &Quad) -> f32 {
+    let twice: f32 = (0..4)
+        .map(|index| {
+            let ((x1, y1), (x2, y2)) = (quad[index], quad[(index + 1) % 4]);
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    twice.abs() / 2.0
+}
+
+fn is_convex(quad: &Quad) -> bool {
+    let turns: Vec<f32> = (0..4)
+        .map(|index| {
+            let (a, b, c) = (quad[index], quad[(index + 1) % 4], quad[(index + 2) % 4]);
+            (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0)
+        })
+        .collect();
+    turns.iter().all(|&turn| turn > 0.0) || turns.iter().all(|&turn| turn < 0.0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const CORNERS: Quad = [(60.0, 40.0), (330.0, 70.0), (350.0, 280.0), (40.0, 250.0)];
+
+    fn inside(quad: &Quad, (x, y): (f32, f32)) -> bool {
+        (0..4).all(|index| {
+            let (a, b) = (quad[index], quad[(index + 1) % 4]);
+            (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0) >= 0.0
+        })
+    }
+
+    /// A light page seen at an angle on a dark desk, with a dark square in the middle of the page
+    pub(crate) fn photo() -> GrayImage {
+        let to_photo = Homography::from_points(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)], &CORNERS).unwrap();
+        let square: Quad = [(0.4, 0.4), (0.6, 0.4), (0.6, 0.6), (0.4, 0.6)].map(|point| to_photo.map(point));
+        GrayImage::from_fn(400, 320, |x, y| {
+            let point = (x as f32 + 0.5, y as f32 + 0.5);
+            Luma([if inside(&square, point) { 20 } else if inside(&CORNERS, point) { 225 } else { 50 }])
+        })
+    }
+
+    #[test]
+    fn test_homography_maps_corners() {
+        let unit: Quad = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let homography = Homography::from_points(&unit, &CORNERS).unwrap();
+        for (from, to) in unit.iter().zip(&CORNERS) {
+            let mapped = homography.map(*from);
+            assert!((mapped.0 - to.0).abs() < 1e-3 && (mapped.1 - to.1).abs() < 1e-3, "{:?} -> {:?}", from, mapped);
+        }
+
+        // Three corners on a line leave no map
+        let degenerate: Quad = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        assert!(Homography::from_points(&degenerate, &CORNERS).is_none());
+    }
+
+    #[test]
+    fn test_find_and_flatten_page() {
+        let photo = photo();
+        let quad = find_page(&photo).unwrap();
+        for (found, corner) in quad.iter().zip(&CORNERS) {
+            assert!((found.0 - corner.0).abs() <= 4.0 && (found.1 - corner.1).abs() <= 4.0, "{:?} vs {:?}", found, corner);
+        }
+
+        let flat = flatten_page(&photo, &quad).unwrap();
+        let (width, height) = flat.dimensions();
+        assert!((300..=320).contains(&width) && (205..=220).contains(&height), "{}x{}", width, height);
+        // The square sits in the middle, upright, on white paper all the way to the edges
+        assert_eq!(flat.get_pixel(width / 2, height / 2)[0], 20);
+        assert_eq!(flat.get_pixel(width * 35 / 100, height / 2)[0], 225);
+        assert_eq!(flat.get_pixel(width / 2, height * 35 / 100)[0], 225);
+        assert!(flat.get_pixel(width * 42 / 100, height * 42 / 100)[0] < 40);
+        assert!(flat.get_pixel(5, 5)[0] > 200 && flat.get_pixel(width - 5, height - 5)[0] > 200);
+    }
+
+    #[test]
+    fn test_page_filling_the_photo_is_kept() {
+        let flat = GrayImage::from_fn(200, 150, |x, _| Luma([if x % 20 < 2 { 30 } else { 230 }]));
+        assert!(find_page(&flat).is_none());
+        assert!(find_page(&GrayImage::from_pixel(200, 150, Luma([128]))).is_none());
+    }
+
+    #[test]
+    fn test_normalize_illumination() {
+        // Paper fading into shadow to the left, with strokes at half the paper level
+        let shaded = GrayImage::from_fn(300, 200, |x, y| {
+            let paper = 110.0 + 130.0 * x as f32 / 299.0;
+            let ink = y % 40 < 3 && x % 50 < 30;
+            Luma([(if ink { paper * 0.5 } else { paper }) as u8])
+        });
+        let even = normalize_illumination(&shaded);
+        for x in [10, 160, 270] {
+            assert!(even.get_pixel(x, 20)[0] >= 245, "paper at {} is {}", x, even.get_pixel(x, 20)[0]);
+            assert!(even.get_pixel(x, 41)[0] <= 135, "ink at {} is {}", x, even.get_pixel(x, 41)[0]);
+        }
+
+        // Chalk on a dark board turns dark on white
+        let board = GrayImage::from_fn(300, 200, |x, y| Luma([if y % 40 < 3 && x % 50 < 30 { 200 } else { 40 }]));
+        let even = normalize_illumination(&board);
+        assert_eq!(even.get_pixel(100, 20)[0], 255);
+        assert!(even.get_pixel(10, 41)[0] <= 75);
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{orientation, photo};
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Dynamic range used for the Sauvola deviation term
+const SAUVOLA_RANGE: f64 = 128.0;
+
+/// Share of pixels clipped at each end by contrast stretching
+const STRETCH_CLIP: f64 = 0.01;
+
+const INK: u8 = 0;
+const PAPER: u8 = 255;
+
+/// How the image is reduced to black and white
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binarization {
+    /// Keep gray levels
+    None,
+    /// One global threshold, for clean scans and screenshots
+    Otsu,
+    /// A threshold per pixel from the local mean and deviation, for uneven lighting
+    Sauvola,
+}
+
+/// Stages applied to an image before recognition
+///
+/// Stages run in the order of the fields. Despeckling needs a binarized
+/// image and is skipped otherwise. The default only normalizes the image;
+/// stages that drop information are enabled by a profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreprocessingConfig {
+    /// Longest side kept; larger images are scaled down
+    pub max_size: u32,
+    /// Turn light or coloured writing on a dark or coloured background into dark on
+    /// light while loading, so the grayscale stage already shows the result
+    pub polarity: bool,
+    /// Find the page or board in a photo and warp it flat
+    pub perspective: bool,
+    /// Even out shadows and uneven lighting
+    pub illumination: bool,
+    /// Turn the text upright and level it
+    pub deskew: bool,
+    /// Stretch gray levels so that faint ink and paper span the full range
+    pub contrast_stretch: bool,
+    /// 3×3 median filter against sensor and JPEG noise
+    pub median_filter: bool,
+    pub binarization: Binarization,
+    /// Side of the Sauvola window in pixels, about the height of a text line
+    pub sauvola_window: u32,
+    /// Sauvola sensitivity; higher values keep less ink
+    pub sauvola_k: f32,
+    /// Remove specks of ink of at most `speckle_size` pixels
+    pub despeckle: bool,
+    pub speckle_size: u32,
+    /// Close gaps of a pixel in broken strokes
+    pub stroke_repair: bool,
+}
+
+/// Preset stages for a kind of input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreprocessingProfile {
+    /// Clean scans and screenshots, kept in gray levels
+    #[default]
+    Standard,
+    /// Faded scans, photocopies and low-contrast screenshots
+    Scan,
+    /// Photos of paper, whiteboards and blackboards taken at an angle
+    Photo,
+}
+
+impl PreprocessingConfig {
+    pub fn for_profile(profile: PreprocessingProfile) -> Self {
+        match profile {
+            PreprocessingProfile::Standard => Self::default(),
+            PreprocessingProfile::Scan => Self {
+                contrast_stretch: true,
+                binarization: Binarization::Sauvola,
+                despeckle: true,
+                ..Self::default()
+            },
+            // The desk around a page would decide the polarity of the whole
+            // photo; illumination correction turns boards over once it is cut away
+            PreprocessingProfile::Photo => Self {
+                polarity: false,
+                perspective: true,
+                illumination: true,
+                median_filter: true,
+                sauvola_window: 31,
+                speckle_size: 8,
+                ..Self::for_profile(PreprocessingProfile::Scan)
+            },
+        }
+    }
+}
+
+impl Default for PreprocessingConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 2048,
+            polarity: true,
+            perspective: false,
+            illumination: false,
+            deskew: true,
+            contrast_stretch: false,
+            median_filter: false,
+            binarization: Binarization::None,
+            sauvola_window: 25,
+            sauvola_k: 0.2,
+            despeckle: false,
+            speckle_size: 4,
+            stroke_repair: false,
+        }
+    }
+}
+
+/// A preprocessing stage, as reported with its intermediate image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreprocessingStage {
+    Grayscale,
+    Perspective,
+    Illumination,
+    Deskew,
+    ContrastStretch,
+    MedianFilter,
+    Binarization,
+    Despeckle,
+    StrokeRepair,
+}
+
+/// Image after a preprocessing stage, as a data URL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreprocessingStep {
+    pub stage: PreprocessingStage,
+    pub image: String,
+}
+
+/// Run the enabled stages on a grayscale image
+///
+/// `inspect` sees the image after every stage that ran, starting with the
+/// input itself as [`PreprocessingStage::Grayscale`].
+pub fn run(
+    mut image: GrayImage,
+    config: &PreprocessingConfig,
+    mut inspect: impl FnMut(PreprocessingStage, &GrayImage),
+) -> GrayImage {
+    inspect(PreprocessingStage::Grayscale, &image);
+
+    if config.perspective {
+        if let Some(flat) = photo::find_page(&image).and_then(|quad| photo::flatten_page(&image, &quad)) {
+            image = flat;
+        }
+        inspect(PreprocessingStage::Perspective, &image);
+    }
+
+    if config.illumination {
+        image = photo::normalize_illumination(&image);
+        inspect(PreprocessingStage::Illumination, &image);
+    }
+
+    if config.deskew {
+        let orientation = orientation::estimate(&image);
+        if !orientation.is_upright() {
+            image = orientation::apply(&image, &orientation);
+        }
+        inspect(PreprocessingStage::Deskew, &image);
+    }
+
+    if config.contrast_stretch {
+        image = stretch_contrast(&image);
+        inspect(PreprocessingStage::ContrastStretch, &image);
+    }
+
+    if config.median_filter {
+        image = filter_3x3(&image, |window| {
+            window.sort_unstable();
+            window[4]
+        });
+        inspect(PreprocessingStage::MedianFilter, &image);
+    }
+
+    let binarized = match config.binarization {
+        Binarization::None => false,
+        Binarization::Otsu => {
+            image = binarize_otsu(&image);
+            true
+        }
+        Binarization::Sauvola => {
+            image = binarize_sauvola(&image, config.sauvola_window, config.sauvola_k);
+            true
+        }
+    };
+    if binarized {
+        inspect(PreprocessingStage::Binarization, &image);
+    }
+
+    if config.despeckle && binarized {
+        despeckle(&mut image, config.speckle_size);
+        inspect(PreprocessingStage::Despeckle, &image);
+    }
+
+    if config.stroke_repair {
+        // Closing: grow the ink, then shrink it back
+        let grown = filter_3x3(&image, |window| window.iter().copied().min().unwrap_or(PAPER));
+        image = filter_3x3(&grown, |window| window.iter().copied().max().unwrap_or(PAPER));
+        inspect(PreprocessingStage::StrokeRepair, &image);
+    }
+
+    image
+}
+
+pub fn histogram(image: &GrayImage) -> [u64; 256] {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    histogram
+}
+
+/// Otsu's threshold and the gap between the mean levels on either side
+///
+/// Levels up to and including the threshold form the dark class. Returns
+/// `None` for an image of a single gray level.
+pub fn otsu_threshold(histogram: &[u64; 256]) -> Option<(u8, f32)> {
+    let total: u64 = histogram.iter().sum();
+    let weighted_total: f64 = histogram.iter().enumerate().map(|(level, &count)| level as f64 * count as f64).sum();
+    let (mut dark_weight, mut dark_sum) = (0u64, 0f64);
+    let mut best: Option<(u8, f64, f64)> = None;
+
+    for (level, &count) in histogram.iter().enumerate() {
+        dark_weight += count;
+        dark_sum += level as f64 * count as f64;
+        let light_weight = total - dark_weight;
+        if dark_weight == 0 || light_weight == 0 {
+            continue;
+        }
+
+        let dark_mean = dark_sum / dark_weight as f64;
+        let light_mean = (weighted_total - dark_sum) / light_weight as f64;
+        let variance = dark_weight as f64 * light_weight as f64 * (light_mean - dark_mean).powi(2);
+        if best.is_none_or(|(_, best_variance, _)| variance > best_variance) {
+            best = Some((level as u8, variance, light_mean - dark_mean));
+        }
+    }
+
+    best.map(|(threshold, _, contrast)| (threshold, contrast as f32))
+}
+
+/// Bilinear sample, with `outside` beyond the edges
+pub fn sample(image: &GrayImage, x: f32, y: f32, outside: f32) -> f32 {
+    let (left, top) = (x.floor(), y.floor());
+    let (fx, fy) = (x - left, y - top);
+    let level = |px: f32, py: f32| {
+        if px < 0.0 || py < 0.0 || px >= image.width() as f32 || py >= image.height() as f32 {
+            outside
+        } else {
+            image.get_pixel(px as u32, py as u32)[0] as f32
+        }
+    };
+
+    let upper = level(left, top) * (1.0 - fx) + level(left + 1.0, top) * fx;
+    let lower = level(left, top + 1.0) * (1.0 - fx) + level(left + 1.0, top + 1.0) * fx;
+    upper * (1.0 - fy) + lower * fy
+}
+
+fn stretch_contrast(image: &GrayImage) -> GrayImage {
+    let histogram = histogram(image);
+    let clip = (image.len() as f64 * STRETCH_CLIP) as u64;
+
+    let mut seen = 0;
+    let low = histogram.iter().position(|&count| {
+        seen += count;
+        seen > clip
+    });
+    seen = 0;
+    let high = histogram.iter().rposition(|&count| {
+        seen += count;
+        seen > clip
+    });
+
+    match (low, high) {
+        (Some(low), Some(high)) if high > low => {
+            let (low, range) = (low as f32, (high - low) as f32);
+            let mut stretched = image.clone();
+            for pixel in stretched.pixels_mut() {
+                pixel[0] = ((pixel[0] as f32 - low) * 255.0 / range).round().clamp(0.0, 255.0) as u8;
+            }
+            stretched
+        }
+        _ => image.clone(),
+    }
+}
+
+fn binarize_otsu(image: &GrayImage) -> GrayImage {
+    match otsu_threshold(&histogram(image)) {
+        Some((threshold, _)) => {
+            GrayImage::from_fn(image.width(), image.height(), |x, y| {
+                Luma([if image.get_pixel(x, y)[0] <= threshold { INK } else { PAPER }])
+            })
+        }
+        None => image.clone(),
+    }
+}
+
+/// Sauvola's threshold `m·(1 + k·(s/R − 1))` over a window around each pixel
+fn binarize_sauvola(image: &GrayImage, window: u32, k: f32) -> GrayImage {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let radius = (window.max(3) / 2) as usize;
+
+    // Summed-area tables of levels and squared levels, one row and column larger
+    let stride = width + 1;
+    let mut sums = vec![0u64; stride * (height + 1)];
+    let mut squares = vec![0u64; stride * (height + 1)];
+    for y in 0..height {
+        let (mut row_sum, mut row_squares) = (0u64, 0u64);
+        for x in 0..width {
+            let level = image.get_pixel(x as u32, y as u32)[0] as u64;
+            row_sum += level;
+            row_squares += level * level;
+            sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+            squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_squares;
+        }
+    }
+    let area_sum = |table: &[u64], left: usize, top: usize, right: usize, bottom: usize| {
+        table[bottom * stride + right] + table[top * stride + left] - table[top * stride + right] - table[bottom * stride + left]
+    };
+
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (right, bottom) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+        let count = ((right - left) * (bottom - top)) as f64;
+
+        let mean = area_sum(&sums, left, top, right, bottom) as f64 / count;
+        let variance = (area_sum(&squares, left, top, right, bottom) as f64 / count - mean * mean).max(0.0);
+        let threshold = mean * (1.0 + k as f64 * (variance.sqrt() / SAUVOLA_RANGE - 1.0));
+
+        Luma([if (image.get_pixel(x as u32, y as u32)[0] as f64) <= threshold { INK } else { PAPER }])
+    })
+}
+
+/// Paint 8-connected ink components of at most `max_size` pixels as paper
+fn despeckle(image: &mut GrayImage, max_size: u32) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let mut visited = vec![false; image.len()];
+    let mut component = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for start in 0..image.len() {
+        let (x, y) = ((start as i64 % width) as u32, (start as i64 / width) as u32);
+        if visited[start] || image.get_pixel(x, y)[0] != INK {
+            continue;
+        }
+
+        component.clear();
+        visited[start] = true;
+        queue.push_back((x as i64, y as i64));
+        while let Some((x, y)) = queue.pop_front() {
+            component.push((x as u32, y as u32));
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                let index = (ny * width + nx) as usize;
+                if !visited[index] && image.get_pixel(nx as u32, ny as u32)[0] == INK {
+                    visited[index] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+
+        if component.len() <= max_size as usize {
+            for &(x, y) in &component {
+                image.put_pixel(x, y, Luma([PAPER]));
+            }
+        }
+    }
+}
+
+/// Replace each pixel by `pick` of its 3×3 neighbourhood, repeating edge pixels
+fn filter_3x3(image: &GrayImage, pick: impl Fn(&mut [u8; 9]) -> u8) -> GrayImage {
+    let (width, height) = image.dimensions();
+    GrayImage::from_fn(width, height, |x, y| {
+        let mut window = [0u8; 9];
+        for (index, level) in window.iter_mut().enumerate() {
+            let nx = (x as i64 + index as i64 % 3 - 1).clamp(0, width as i64 - 1) as u32;
+            let ny = (y as i64 + index as i64 / 3 - 1).clamp(0, height as i64 - 1) as u32;
+            *level = image.get_pixel(nx, ny)[0];
+        }
+        Luma([pick(&mut window)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every stage off
+    fn none() -> PreprocessingConfig {
+        PreprocessingConfig { deskew: false, ..Default::default() }
+    }
+
+    fn run_quietly(image: &GrayImage, config: PreprocessingConfig) -> GrayImage {
+        run(image.clone(), &config, |_, _| {})
+    }
+
+    #[test]
+    fn test_otsu_threshold() {
+        let mut histogram = [0u64; 256];
+        histogram[40] = 300;
+        histogram[200] = 700;
+        let (threshold, contrast) = otsu_threshold(&histogram).unwrap();
+        assert!((40..200).contains(&threshold));
+        assert_eq!(contrast, 160.0);
+
+        histogram[40] = 0;
+        assert!(otsu_threshold(&histogram).is_none());
+    }
+
+    #[test]
+    fn test_contrast_stretch_and_median() {
+        // Faint ink on gray paper
+        let faint = GrayImage::from_fn(20, 20, |x, _| Luma([if x < 5 { 150 } else { 180 }]));
+        let stretched = run_quietly(&faint, PreprocessingConfig { contrast_stretch: true, ..none() });
+        assert_eq!((stretched.get_pixel(0, 0)[0], stretched.get_pixel(10, 0)[0]), (0, 255));
+
+        // The median drops a lone speck but keeps a stroke three pixels wide
+        let mut noisy = GrayImage::from_fn(20, 20, |x, _| Luma([if (8..11).contains(&x) { 0 } else { 255 }]));
+        noisy.put_pixel(3, 3, Luma([0]));
+        let filtered = run_quietly(&noisy, PreprocessingConfig { median_filter: true, ..none() });
+        assert_eq!(filtered.get_pixel(3, 3)[0], 255);
+        assert!((8..11).all(|x| filtered.get_pixel(x, 10)[0] == 0));
+    }
+
+    #[test]
+    fn test_sauvola_handles_uneven_lighting() {
+        // Dim paper on the left, bright paper on the right, a stroke on each
+        let page = GrayImage::from_fn(80, 40, |x, y| {
+            let paper = if x < 40 { 100 } else { 230 };
+            let ink = (10..14).contains(&y) && (x % 40 >= 10 && x % 40 < 30);
+            Luma([if ink { paper - 60 } else { paper }])
+        });
+
+        let sauvola = run_quietly(&page, PreprocessingConfig { binarization: Binarization::Sauvola, ..none() });
+        for x in [15, 55] {
+            assert_eq!(sauvola.get_pixel(x, 12)[0], INK, "stroke at {}", x);
+            assert_eq!(sauvola.get_pixel(x, 30)[0], PAPER, "paper at {}", x);
+        }
+
+        // A single threshold turns the dim paper into ink
+        let otsu = run_quietly(&page, PreprocessingConfig { binarization: Binarization::Otsu, ..none() });
+        assert_eq!(otsu.get_pixel(15, 30)[0], INK);
+        assert_eq!(otsu.get_pixel(55, 30)[0], PAPER);
+    }
+
+    #[test]
+    fn test_despeckle_and_stroke_repair() {
+        // A stroke broken by a one-pixel gap, and two specks
+        let mut page = GrayImage::from_fn(30, 20, |x, y| {
+            Luma([if (9..11).contains(&y) && (3..25).contains(&x) && x != 14 { 0 } else { 255 }])
+        });
+        page.put_pixel(5, 3, Luma([0]));
+        page.put_pixel(6, 4, Luma([0]));
+        page.put_pixel(20, 16, Luma([0]));
+
+        let config = PreprocessingConfig {
+            binarization: Binarization::Otsu,
+            despeckle: true,
+            speckle_size: 2,
+            stroke_repair: true,
+            ..none()
+        };
+        let repaired = run_quietly(&page, config);
+        assert_eq!(repaired.get_pixel(5, 3)[0], PAPER);
+        assert_eq!(repaired.get_pixel(20, 16)[0], PAPER);
+        assert_eq!(repaired.get_pixel(14, 9)[0], INK);
+        assert_eq!(repaired.get_pixel(3, 9)[0], INK);
+        assert_eq!(repaired.get_pixel(14, 12)[0], PAPER);
+    }
+
+    #[test]
+    fn test_stages_are_reported_in_order() {
+        let page = GrayImage::from_fn(30, 30, |x, y| Luma([((x * 7 + y * 3) % 256) as u8]));
+
+        let mut stages = Vec::new();
+        run(page.clone(), &PreprocessingConfig::default(), |stage, _| stages.push(stage));
+        assert_eq!(stages, vec![PreprocessingStage::Grayscale, PreprocessingStage::Deskew]);
+
+        let mut stages = Vec::new();
+        run(page.clone(), &PreprocessingConfig::for_profile(PreprocessingProfile::Scan), |stage, _| stages.push(stage));
+        assert_eq!(stages, vec![
+            PreprocessingStage::Grayscale,
+            PreprocessingStage::Deskew,
+            PreprocessingStage::ContrastStretch,
+            PreprocessingStage::Binarization,
+            PreprocessingStage::Despeckle,
+        ]);
+
+        // Despeckling has nothing to work on without binarization
+        let mut stages = Vec::new();
+        let config = PreprocessingConfig {
+            binarization: Binarization::None,
+            median_filter: true,
+            ..PreprocessingConfig::for_profile(PreprocessingProfile::Scan)
+        };
+        run(page.clone(), &config, |stage, _| stages.push(stage));
+        assert_eq!(stages, vec![
+            PreprocessingStage::Grayscale,
+            PreprocessingStage::Deskew,
+            PreprocessingStage::ContrastStretch,
+            PreprocessingStage::MedianFilter,
+        ]);
+
+        let mut stages = Vec::new();
+        run(page, &PreprocessingConfig::for_profile(PreprocessingProfile::Photo), |stage, _| stages.push(stage));
+        assert_eq!(stages, vec![
+            PreprocessingStage::Grayscale,
+            PreprocessingStage::Perspective,
+            PreprocessingStage::Illumination,
+            PreprocessingStage::Deskew,
+            PreprocessingStage::ContrastStretch,
+            PreprocessingStage::MedianFilter,
+            PreprocessingStage::Binarization,
+            PreprocessingStage::Despeckle,
+        ]);
+    }
+
+    #[test]
+    fn test_photo_profile_flattens_and_binarizes_a_photo() {
+        let page = run_quietly(&photo::tests::photo(), PreprocessingConfig::for_profile(PreprocessingProfile::Photo));
+
+        // Only the page is left, in black and white, with the square in its
+        // middle; Sauvola keeps the outline of a block wider than its window
+        let (width, height) = page.dimensions();
+        assert!((300..=320).contains(&width) && (205..=220).contains(&height), "{}x{}", width, height);
+        assert!(page.pixels().all(|pixel| pixel[0] == INK || pixel[0] == PAPER));
+        for (x, y) in [(width * 42 / 100, height / 2), (width * 58 / 100, height / 2), (width / 2, height * 42 / 100)] {
+            assert_eq!(page.get_pixel(x, y)[0], INK, "({}, {})", x, y);
+        }
+        for (x, y) in [(5, 5), (width - 5, 5), (width - 5, height - 5), (5, height - 5), (width / 2, height / 5)] {
+            assert_eq!(page.get_pixel(x, y)[0], PAPER, "({}, {})", x, y);
+        }
+    }
+}
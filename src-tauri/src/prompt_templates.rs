@@ -0,0 +1,193 @@
+use crate::{BlockFormat, InlineFormat, InputType, MarkdownFormulaFormat, MathSeekError, MathSeekResult};
+use serde::{Deserialize, Serialize};
+
+/// Variables available in prompt templates as `{{name}}`
+pub const PROMPT_VARIABLES: &[&str] = &["language", "inline_delimiters", "block_delimiters", "macros"];
+
+/// User-editable system prompts for chat model backends
+///
+/// A template holds the instructions only; the reply format the backend
+/// parses is appended when the prompt is rendered. A template line whose
+/// variables all render empty is left out, so optional settings such as
+/// `language` need no conditionals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplates {
+    pub single_formula: String,
+    pub document: String,
+    /// Language of the text in the image, e.g. "German"; empty to leave it to the model
+    pub language: String,
+    /// LaTeX macros the model should prefer, such as `\dfrac` or `\mathbb{R}`
+    pub preferred_macros: Vec<String>,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self {
+            single_formula: [
+                "You are a mathematical OCR engine. Transcribe the formula in the image into LaTeX.",
+                "Prefer these LaTeX macros where they fit: {{macros}}.",
+            ].join("\n"),
+            document: [
+                "You are a mathematical OCR engine. Transcribe the document in the image.",
+                "The text is written in {{language}}; keep it in that language.",
+                "Prefer these LaTeX macros where they fit: {{macros}}.",
+            ].join("\n"),
+            language: String::new(),
+            preferred_macros: Vec::new(),
+        }
+    }
+}
+
+impl PromptTemplates {
+    pub fn template(&self, input_type: &InputType) -> &str {
+        match input_type {
+            InputType::SingleFormula => &self.single_formula,
+            InputType::Document => &self.document,
+        }
+    }
+
+    /// System prompt for recognizing `input_type`, streamed or not
+    pub fn render(&self, input_type: &InputType, format: &MarkdownFormulaFormat, stream: bool) -> MathSeekResult<String> {
+        let instructions = render_template(self.template(input_type), &self.variables(format))?;
+        Ok(format!("{}\n{}", instructions, reply_format(input_type, format, stream)))
+    }
+
+    /// Check both templates for unknown variables and unclosed placeholders
+    pub fn validate(&self) -> MathSeekResult<()> {
+        let variables = self.variables(&MarkdownFormulaFormat::default());
+        render_template(&self.single_formula, &variables)?;
+        render_template(&self.document, &variables)?;
+        Ok(())
+    }
+
+    fn variables(&self, format: &MarkdownFormulaFormat) -> [(&'static str, String); 4] {
+        let (inline, block) = delimiter_examples(format);
+        [
+            ("language", self.language.trim().to_string()),
+            ("inline_delimiters", inline.to_string()),
+            ("block_delimiters", block.to_string()),
+            ("macros", self.preferred_macros.join(", ")),
+        ]
+    }
+}
+
+/// Fill `{{name}}` placeholders, dropping lines whose placeholders are all empty
+fn render_template(template: &str, variables: &[(&str, String)]) -> MathSeekResult<String> {
+    let mut lines = Vec::new();
+
+    for line in template.lines() {
+        let mut rendered = String::new();
+        let mut rest = line;
+        let mut placeholders = 0;
+        let mut filled = 0;
+
+        while let Some(start) = rest.find("{{") {
+            let Some(length) = rest[start + 2..].find("}}") else {
+                return Err(MathSeekError::ConfigError(format!("Unclosed placeholder in prompt template: {}", line)));
+            };
+            let name = rest[start + 2..start + 2 + length].trim();
+            let value = variables.iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| value.as_str())
+                .ok_or_else(|| MathSeekError::ConfigError(format!(
+                    "Unknown prompt variable {{{{{}}}}}, expected one of: {}", name, PROMPT_VARIABLES.join(", ")
+                )))?;
+
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(value);
+            placeholders += 1;
+            if !value.is_empty() {
+                filled += 1;
+            }
+            rest = &rest[start + 2 + length + 2..];
+        }
+        rendered.push_str(rest);
+
+        if placeholders == 0 || filled > 0 {
+            lines.push(rendered);
+        }
+    }
+
+    Ok(lines.join("\n").trim().to_string())
+}
+
+/// `$...$`-style examples of the configured Markdown delimiters
+fn delimiter_examples(format: &MarkdownFormulaFormat) -> (&'static str, &'static str) {
+    let inline = match format.inline {
+        InlineFormat::Dollar => "$...$",
+        InlineFormat::Parentheses => "\\(...\\)",
+    };
+    let block = match format.block {
+        BlockFormat::DoubleDollar => "$$...$$",
+        BlockFormat::Brackets => "\\[...\\]",
+    };
+    (inline, block)
+}
+
+/// Reply format the backends parse, which templates cannot change
+///
+/// Streamed documents are requested as Markdown instead of JSON so that
+/// every partial answer is already readable.
+fn reply_format(input_type: &InputType, format: &MarkdownFormulaFormat, stream: bool) -> String {
+    match (input_type, stream) {
+        (InputType::SingleFormula, _) => {
+            "Reply with the LaTeX source only, without math delimiters, code fences or explanations.".to_string()
+        }
+        (InputType::Document, false) => {
+            "Reply with a single JSON object and nothing else, using this shape: \
+             {\"title\": string or null, \"sections\": [{\"heading\": string or null, \"text\": string, \
+             \"formulas\": [{\"latex\": string, \"position\": number, \"is_inline\": boolean}]}]}. \
+             `position` is the character offset in `text` where the formula belongs; \
+             formulas must not be repeated inside `text`.".to_string()
+        }
+        (InputType::Document, true) => {
+            let (inline, block) = delimiter_examples(format);
+            format!(
+                "Reply in Markdown only. Use `#` headings for the title and section headings, \
+                 `{}` for inline formulas and `{}` on their own lines for display formulas.",
+                inline, block
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_prompts_leave_out_empty_settings() {
+        let templates = PromptTemplates::default();
+        let prompt = templates.render(&InputType::SingleFormula, &MarkdownFormulaFormat::default(), false).unwrap();
+        assert_eq!(prompt, "You are a mathematical OCR engine. Transcribe the formula in the image into LaTeX.\n\
+                            Reply with the LaTeX source only, without math delimiters, code fences or explanations.");
+
+        let templates = PromptTemplates {
+            language: "German".to_string(),
+            preferred_macros: vec!["\\dfrac".to_string(), "\\mathbb{R}".to_string()],
+            ..Default::default()
+        };
+        let format = MarkdownFormulaFormat { inline: InlineFormat::Parentheses, block: BlockFormat::Brackets };
+        let prompt = templates.render(&InputType::Document, &format, true).unwrap();
+        assert!(prompt.contains("written in German;"));
+        assert!(prompt.contains("fit: \\dfrac, \\mathbb{R}."));
+        assert!(prompt.contains("`\\(...\\)` for inline formulas and `\\[...\\]`"));
+
+        let prompt = templates.render(&InputType::Document, &format, false).unwrap();
+        assert!(prompt.ends_with("formulas must not be repeated inside `text`."));
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let templates = PromptTemplates { single_formula: "Use {{ macros }} and {{dialect}}".to_string(), ..Default::default() };
+        let error = templates.validate().unwrap_err();
+        assert!(matches!(&error, MathSeekError::ConfigError(message) if message.contains("{{dialect}}")));
+
+        let templates = PromptTemplates { document: "Unclosed {{language".to_string(), ..Default::default() };
+        assert!(templates.validate().is_err());
+
+        let templates = PromptTemplates { single_formula: "Write {{ inline_delimiters }} math".to_string(), ..Default::default() };
+        assert!(templates.validate().is_ok());
+    }
+}
@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Client-side limits for one provider; `0` disables a limit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub requests_per_minute: u32,
+    pub max_concurrent_requests: u32,
+}
+
+/// Queue state of a provider's limiter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitStatus {
+    pub endpoint: String,
+    pub requests_per_minute: u32,
+    pub max_concurrent_requests: u32,
+    /// Requests waiting for a slot or a token
+    pub queued: usize,
+    /// Requests currently being sent or streamed
+    pub in_flight: usize,
+}
+
+/// Token bucket refilled continuously at a fixed rate
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    per_second: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket holding `capacity` tokens
+    pub fn new(capacity: u32, per_second: f64, now: Instant) -> Self {
+        let capacity = f64::from(capacity.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            per_second,
+            updated_at: now,
+        }
+    }
+
+    /// Bucket for `requests_per_minute`, holding a single token
+    ///
+    /// Without a burst allowance requests are spaced evenly, so no sliding
+    /// one-minute window on the provider side ever sees more than the limit.
+    pub fn per_minute(requests_per_minute: u32, now: Instant) -> Self {
+        Self::new(1, f64::from(requests_per_minute) / 60.0, now)
+    }
+
+    /// Take a token, or return how long until one becomes available
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.per_second))
+        }
+    }
+}
+
+/// Token bucket plus concurrency cap shared by every client of one provider
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: Mutex<RateLimits>,
+    bucket: Mutex<Option<TokenBucket>>,
+    /// Concurrency slots; without a cap it holds `Semaphore::MAX_PERMITS`, so
+    /// that requests in flight still count when a cap is set later
+    semaphore: Arc<Semaphore>,
+    /// Slots to retire as they are released, after the cap was lowered
+    /// below the number of requests in flight
+    retiring: AtomicUsize,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits: Mutex::new(limits),
+            bucket: Mutex::new(Self::bucket(limits)),
+            semaphore: Arc::new(Semaphore::new(Self::capacity(limits))),
+            retiring: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    fn bucket(limits: RateLimits) -> Option<TokenBucket> {
+        (limits.requests_per_minute > 0).then(|| TokenBucket::per_minute(limits.requests_per_minute, Instant::now()))
+    }
+
+    fn capacity(limits: RateLimits) -> usize {
+        match limits.max_concurrent_requests {
+            0 => Semaphore::MAX_PERMITS,
+            cap => cap as usize,
+        }
+    }
+
+    pub fn limits(&self) -> RateLimits {
+        *self.limits.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Switch to new limits without losing track of queued and in-flight requests
+    ///
+    /// A lowered cap takes effect as in-flight requests finish.
+    pub fn update(&self, limits: RateLimits) {
+        let mut current = self.limits.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *current == limits {
+            return;
+        }
+
+        if current.requests_per_minute != limits.requests_per_minute {
+            *self.bucket.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Self::bucket(limits);
+        }
+
+        let (old, new) = (Self::capacity(*current), Self::capacity(limits));
+        if new > old {
+            // Slots still owed from an earlier reduction are cancelled first
+            let extra = new - old;
+            let owed = self.retiring
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| Some(owed.saturating_sub(extra)))
+                .unwrap_or(0);
+            self.semaphore.add_permits(extra - owed.min(extra));
+        } else if new < old {
+            let surplus = old - new;
+            let forgotten = self.semaphore.forget_permits(surplus);
+            self.retiring.fetch_add(surplus - forgotten, Ordering::SeqCst);
+        }
+
+        *current = limits;
+    }
+
+    /// Wait for a concurrency slot and a token; the slot is held until the permit drops
+    ///
+    /// Waiting is cancel-safe: a dropped future leaves the queue immediately.
+    pub async fn acquire(self: &Arc<Self>) -> RatePermit {
+        let queued = QueuedGuard::enter(&self.queued);
+
+        // Take the slot first so tokens are only spent by requests that can go out
+        let slot = self.semaphore.clone().acquire_owned().await.ok();
+
+        loop {
+            let wait = match self.bucket.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).as_mut() {
+                Some(bucket) => bucket.try_take(Instant::now()),
+                None => Ok(()),
+            };
+            match wait {
+                Ok(()) => break,
+                Err(delay) => tokio::time::sleep(delay).await,
+            }
+        }
+
+        drop(queued);
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        RatePermit {
+            limiter: self.clone(),
+            slot,
+        }
+    }
+
+    pub fn status(&self, endpoint: &str) -> RateLimitStatus {
+        let limits = self.limits();
+        RateLimitStatus {
+            endpoint: endpoint.to_string(),
+            requests_per_minute: limits.requests_per_minute,
+            max_concurrent_requests: limits.max_concurrent_requests,
+            queued: self.queued.load(Ordering::SeqCst),
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Counts a waiting request for as long as it is queued
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl<'a> QueuedGuard<'a> {
+    fn enter(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Permission to send one request; frees the concurrency slot when dropped
+#[derive(Debug)]
+pub struct RatePermit {
+    limiter: Arc<RateLimiter>,
+    slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for RatePermit {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let retire = self.limiter.retiring
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| owed.checked_sub(1))
+            .is_ok();
+        if let (Some(slot), true) = (self.slot.take(), retire) {
+            slot.forget();
+        }
+    }
+}
+
+/// Limiters are shared process-wide because Tauri commands create a fresh
+/// `ApiClient` for every call
+fn registry() -> &'static Mutex<HashMap<String, Arc<RateLimiter>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Arc<RateLimiter>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Shared limiter of an endpoint, updated in place when its configured limits change
+pub fn for_endpoint(endpoint: &str, limits: RateLimits) -> Arc<RateLimiter> {
+    let limiter = registry()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry(endpoint.to_string())
+        .or_insert_with(|| Arc::new(RateLimiter::new(limits)))
+        .clone();
+    limiter.update(limits);
+    limiter
+}
+
+/// Drop the shared limiter of `endpoint`, see `circuit_breaker::forget`
+#[cfg(any(test, feature = "mock-server"))]
+pub fn forget(endpoint: &str) {
+    registry().lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(endpoint);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_spacing() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::per_minute(120, start);
+
+        assert!(bucket.try_take(start).is_ok());
+        assert_eq!(bucket.try_take(start), Err(Duration::from_millis(500)));
+        assert!(bucket.try_take(start + Duration::from_millis(250)).is_err());
+        assert!(bucket.try_take(start + Duration::from_millis(500)).is_ok());
+
+        // Idle time never accumulates more than the capacity
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.try_take(later).is_ok());
+        assert!(bucket.try_take(later).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(3, 1.0, start);
+        for _ in 0..3 {
+            assert!(bucket.try_take(start).is_ok());
+        }
+        assert_eq!(bucket.try_take(start), Err(Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_cap_queues() {
+        let limiter = Arc::new(RateLimiter::new(RateLimits { requests_per_minute: 0, max_concurrent_requests: 1 }));
+
+        let first = limiter.acquire().await;
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let status = limiter.status("x");
+        assert_eq!((status.queued, status.in_flight), (1, 1));
+
+        drop(first);
+        let second = waiting.await.unwrap();
+        let status = limiter.status("x");
+        assert_eq!((status.queued, status.in_flight), (0, 1));
+
+        drop(second);
+        assert_eq!(limiter.status("x").in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_wait_leaves_queue() {
+        let limiter = Arc::new(RateLimiter::new(RateLimits { requests_per_minute: 0, max_concurrent_requests: 1 }));
+        let _held = limiter.acquire().await;
+
+        let timed_out = tokio::time::timeout(Duration::from_millis(20), limiter.acquire()).await;
+        assert!(timed_out.is_err());
+        assert_eq!(limiter.status("x").queued, 0);
+    }
+
+    #[test]
+    fn test_registry_updates_changed_limits() {
+        let limits = RateLimits { requests_per_minute: 60, max_concurrent_requests: 2 };
+        let first = for_endpoint("http://rate-limit.test", limits);
+        assert!(Arc::ptr_eq(&first, &for_endpoint("http://rate-limit.test", limits)));
+
+        let changed = RateLimits { max_concurrent_requests: 4, ..limits };
+        assert!(Arc::ptr_eq(&first, &for_endpoint("http://rate-limit.test", changed)));
+        assert_eq!(first.limits(), changed);
+    }
+
+    #[tokio::test]
+    async fn test_changed_cap_keeps_in_flight_requests() {
+        let limiter = Arc::new(RateLimiter::new(RateLimits { requests_per_minute: 0, max_concurrent_requests: 2 }));
+        let first = limiter.acquire().await;
+        let second = limiter.acquire().await;
+        let pending = |limiter: &Arc<RateLimiter>| {
+            let limiter = limiter.clone();
+            async move { tokio::time::timeout(Duration::from_millis(20), limiter.acquire()).await.is_err() }
+        };
+
+        // Both requests stay counted against the lowered cap
+        limiter.update(RateLimits { requests_per_minute: 0, max_concurrent_requests: 1 });
+        assert_eq!(limiter.status("x").in_flight, 2);
+        drop(first);
+        assert!(pending(&limiter).await);
+        drop(second);
+        let third = limiter.acquire().await;
+        assert!(pending(&limiter).await);
+
+        // A raised cap frees slots right away, and lifting it frees all of them
+        limiter.update(RateLimits { requests_per_minute: 0, max_concurrent_requests: 2 });
+        let fourth = limiter.acquire().await;
+        assert!(pending(&limiter).await);
+        limiter.update(RateLimits { requests_per_minute: 0, max_concurrent_requests: 0 });
+        let _more = [limiter.acquire().await, limiter.acquire().await];
+        assert_eq!(limiter.status("x").in_flight, 4);
+        drop((third, fourth));
+    }
+}
@@ -0,0 +1,582 @@
+use crate::{
+    MathSeekError, MathSeekResult, FormulaResult, AnalysisResult, FormulaVariable, InputType, ApiClient,
+    ApiConfig, DocumentContent, DocumentSection, FormulaBlock, PayloadBudget, PayloadFormat
+};
+use crate::api_client::NativeBackend;
+use crate::openai_backend::OpenAiBackend;
+use crate::ollama_backend::OllamaBackend;
+use crate::mathpix_backend::MathpixBackend;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Name of the built-in MathSeek `/recognize` protocol backend
+pub const DEFAULT_BACKEND: &str = "mathseek";
+
+/// Callback receiving the full text accumulated so far by a streamed recognition
+pub type PartialCallback<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+/// Protocol adapter used by `ApiClient` to talk to a recognition provider
+///
+/// Backends only describe the wire format; the HTTP client, credentials and
+/// retry handling are provided by the `ApiClient` passed into each call.
+#[async_trait]
+pub trait RecognitionBackend: Send + Sync {
+    /// Identifier used to select this backend in `AppConfig::backend`
+    fn name(&self) -> &'static str;
+
+    /// Recognize mathematical content from image data encoded as `format`
+    async fn recognize(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        format: PayloadFormat,
+        input_type: InputType,
+    ) -> MathSeekResult<FormulaResult>;
+
+    /// Recognize while reporting the accumulated output through `on_partial`
+    ///
+    /// Backends without a streaming protocol report the complete answer once.
+    async fn recognize_streaming(
+        &self,
+        client: &ApiClient,
+        image_data: &[u8],
+        format: PayloadFormat,
+        input_type: InputType,
+        on_partial: PartialCallback<'_>,
+    ) -> MathSeekResult<FormulaResult> {
+        let result = self.recognize(client, image_data, format, input_type).await?;
+        on_partial(&result.latex);
+        Ok(result)
+    }
+
+    /// Analyze a formula to get its type and description
+    async fn analyze(&self, client: &ApiClient, formula: &str) -> MathSeekResult<AnalysisResult>;
+
+    /// Check that the provider is reachable and accepts our credentials
+    async fn health(&self, client: &ApiClient) -> MathSeekResult<bool>;
+
+    /// List the models installed on or offered by the provider
+    async fn list_models(&self, _client: &ApiClient) -> MathSeekResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Whether the provider needs `ApiConfig::api_key` to be set
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    /// Whether the provider needs `ApiConfig::app_id` to be set
+    fn requires_app_id(&self) -> bool {
+        false
+    }
+
+    /// Whether result confidences are scores from the provider rather than a fixed guess
+    fn reports_confidence(&self) -> bool {
+        true
+    }
+
+    /// Largest image upload and the formats the provider accepts
+    fn payload_budget(&self) -> PayloadBudget {
+        PayloadBudget::default()
+    }
+
+    /// Authentication headers sent with every request
+    fn auth_headers(&self, config: &ApiConfig) -> Vec<(&'static str, String)> {
+        if config.api_key.is_empty() {
+            Vec::new()
+        } else {
+            vec![("Authorization", format!("Bearer {}", config.api_key))]
+        }
+    }
+
+    /// Convert a non-success HTTP response into an error
+    fn error_from_response(&self, status: StatusCode, retry_after_secs: Option<u64>, body: &str) -> MathSeekError {
+        let (code, message) = parse_error_body(status, body);
+        MathSeekError::http(status.as_u16(), code, message, retry_after_secs)
+    }
+}
+
+/// Names of all backends that can be selected in the configuration
+pub fn available_backends() -> Vec<&'static str> {
+    vec![DEFAULT_BACKEND, OpenAiBackend::NAME, OllamaBackend::NAME, MathpixBackend::NAME]
+}
+
+/// Create the backend registered under the given name
+pub fn create_backend(name: &str) -> MathSeekResult<Arc<dyn RecognitionBackend>> {
+    match name {
+        DEFAULT_BACKEND => Ok(Arc::new(NativeBackend)),
+        OpenAiBackend::NAME => Ok(Arc::new(OpenAiBackend)),
+        OllamaBackend::NAME => Ok(Arc::new(OllamaBackend)),
+        MathpixBackend::NAME => Ok(Arc::new(MathpixBackend)),
+        other => Err(MathSeekError::ConfigError(format!("Unknown recognition backend: {}", other))),
+    }
+}
+
+/// Serde default for configuration files written before backends were selectable
+pub(crate) fn default_backend_name() -> String {
+    DEFAULT_BACKEND.to_string()
+}
+
+/// Extract the provider error code and message from an error response body
+///
+/// Understands `{"error": {"code", "message"}}` (OpenAI), `{"error": "..."}`
+/// (Ollama, Mathpix) and `{"code", "message"}`; anything else is kept verbatim.
+pub(crate) fn parse_error_body(status: StatusCode, body: &str) -> (Option<String>, String) {
+    fn as_code(value: &serde_json::Value) -> Option<String> {
+        match value {
+            serde_json::Value::String(code) => Some(code.clone()),
+            serde_json::Value::Number(code) => Some(code.to_string()),
+            _ => None,
+        }
+    }
+
+    let fallback_message = if body.trim().is_empty() {
+        status.canonical_reason().unwrap_or("Unknown error").to_string()
+    } else {
+        body.trim().to_string()
+    };
+
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(body) else {
+        return (None, fallback_message);
+    };
+
+    match &json["error"] {
+        serde_json::Value::Object(error) => {
+            let code = error.get("code").and_then(as_code)
+                .or_else(|| error.get("type").and_then(as_code));
+            let message = error.get("message")
+                .and_then(|message| message.as_str())
+                .map(|message| message.to_string())
+                .unwrap_or(fallback_message);
+            (code, message)
+        }
+        serde_json::Value::String(message) => {
+            let code = as_code(&json["error_info"]["id"]).or_else(|| as_code(&json["code"]));
+            (code, message.clone())
+        }
+        _ => {
+            let message = json["message"].as_str()
+                .map(|message| message.to_string())
+                .unwrap_or(fallback_message);
+            (as_code(&json["code"]), message)
+        }
+    }
+}
+
+// Shared helpers for chat-style backends that answer in free text
+
+/// Confidence reported for chat model answers, which carry no score of their own
+///
+/// Chat backends do not report confidence, so the engine never skips their
+/// answers as below the confidence threshold.
+pub(crate) const CHAT_MODEL_CONFIDENCE: f32 = 0.9;
+
+/// System prompt asking a chat model to analyze a formula as JSON
+pub(crate) const ANALYSIS_PROMPT: &str =
+    "You are a mathematics tutor. Analyze the LaTeX formula given by the user. \
+     Reply with a single JSON object and nothing else, using this shape: \
+     {\"formula_type\": string, \"description\": string, \"usage\": string, \"examples\": [string], \
+     \"variables\": [{\"symbol\": string, \"description\": string, \"unit\": string or null}], \
+     \"domain\": string, \"related_formulas\": [string]}. \
+     Write `symbol` and `related_formulas` in LaTeX.";
+
+/// Analysis fields as returned by a chat model, all optional
+#[derive(Debug, Deserialize)]
+struct ChatAnalysis {
+    formula_type: Option<String>,
+    description: Option<String>,
+    usage: Option<String>,
+    examples: Option<Vec<String>>,
+    variables: Option<Vec<FormulaVariable>>,
+    domain: Option<String>,
+    related_formulas: Option<Vec<String>>,
+}
+
+/// Remove a surrounding Markdown code fence such as ```latex ... ```
+pub(crate) fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    if let Some(rest) = trimmed.strip_prefix("```") {
+        if let Some(body) = rest.strip_suffix("```") {
+            // Skip the language tag on the opening line
+            return match body.find('\n') {
+                Some(newline) => body[newline + 1..].trim(),
+                None => body.trim(),
+            };
+        }
+    }
+    trimmed
+}
+
+/// Remove surrounding `$$`, `$`, `\[ \]` or `\( \)` math delimiters
+pub(crate) fn strip_math_delimiters(latex: &str) -> &str {
+    let latex = latex.trim();
+    for (open, close) in [("$$", "$$"), ("\\[", "\\]"), ("\\(", "\\)"), ("$", "$")] {
+        if latex.len() >= open.len() + close.len() && latex.starts_with(open) && latex.ends_with(close) {
+            return latex[open.len()..latex.len() - close.len()].trim();
+        }
+    }
+    latex
+}
+
+/// Turn the text answer of a chat model into a validated `FormulaResult`
+///
+/// Formula positions in a JSON document are character offsets, as the
+/// prompt asks for, and become byte offsets into the section text.
+pub(crate) fn parse_chat_recognition(text: &str, input_type: InputType) -> MathSeekResult<FormulaResult> {
+    let body = strip_code_fence(text);
+    if body.is_empty() {
+        return Err(MathSeekError::ApiError("No LaTeX content in response".to_string()));
+    }
+
+    let result = match input_type {
+        InputType::SingleFormula => {
+            FormulaResult::new_single_formula(strip_math_delimiters(body).to_string(), CHAT_MODEL_CONFIDENCE)
+        }
+        InputType::Document => {
+            let document = match serde_json::from_str::<DocumentContent>(body) {
+                Ok(mut document) if document.validate().is_ok() => {
+                    positions_to_bytes(&mut document);
+                    document
+                }
+                _ => {
+                    // Fallback: keep the raw answer as a single section
+                    let mut document = DocumentContent::new(None);
+                    document.add_section(DocumentSection::new(None, body.to_string()));
+                    document
+                }
+            };
+
+            FormulaResult::new_document(document_latex(&document, body), CHAT_MODEL_CONFIDENCE, document)
+        }
+    };
+
+    result.validate()?;
+    Ok(result)
+}
+
+/// Turn character offsets of formulas into byte offsets, clamped to the end of the text
+fn positions_to_bytes(document: &mut DocumentContent) {
+    for section in &mut document.sections {
+        for formula in &mut section.formulas {
+            formula.position = section.text.char_indices()
+                .nth(formula.position)
+                .map_or(section.text.len(), |(byte, _)| byte);
+        }
+    }
+}
+
+/// Turn the complete answer of a streamed recognition into a `FormulaResult`
+///
+/// Documents are parsed as Markdown, unless the model answered in JSON anyway.
+pub(crate) fn parse_chat_stream(text: &str, input_type: InputType) -> MathSeekResult<FormulaResult> {
+    let body = strip_code_fence(text);
+    if matches!(input_type, InputType::SingleFormula) || body.starts_with('{') {
+        return parse_chat_recognition(text, input_type);
+    }
+    if body.is_empty() {
+        return Err(MathSeekError::ApiError("No content in streamed response".to_string()));
+    }
+
+    let document = parse_markdown_document(body);
+    let result = FormulaResult::new_document(document_latex(&document, body), CHAT_MODEL_CONFIDENCE, document);
+    result.validate()?;
+    Ok(result)
+}
+
+/// All formulas of a document joined by newlines, or the raw text if there are none
+fn document_latex(document: &DocumentContent, text: &str) -> String {
+    let formulas: Vec<&str> = document.sections.iter()
+        .flat_map(|section| section.formulas.iter().map(|formula| formula.latex.as_str()))
+        .collect();
+
+    if formulas.is_empty() {
+        text.to_string()
+    } else {
+        formulas.join("\n")
+    }
+}
+
+/// Display math delimiters accepted in Markdown answers
+const BLOCK_DELIMITERS: [(&str, &str); 2] = [("$$", "$$"), ("\\[", "\\]")];
+
+/// Inline math delimiters accepted in Markdown answers
+const INLINE_DELIMITERS: [(&str, &str); 2] = [("$", "$"), ("\\(", "\\)")];
+
+/// Build a `DocumentContent` from Markdown with `$`/`$$` or `\(`/`\[` math
+///
+/// `#` headings start new sections (a leading level-one heading becomes the
+/// title), `$$` or `\[` blocks become display formulas and `$...$` or `\(...\)`
+/// spans become inline formulas. An unterminated block, as seen mid-stream,
+/// is kept as well.
+pub(crate) fn parse_markdown_document(markdown: &str) -> DocumentContent {
+    let mut document = DocumentContent::new(None);
+    let mut section = DocumentSection::new(None, String::new());
+    let mut display_block: Option<(String, &str)> = None;
+
+    for line in markdown.lines() {
+        let line = line.trim();
+
+        if let Some((block, close)) = display_block.as_mut() {
+            match line.strip_suffix(*close) {
+                Some(end) => {
+                    block.push_str(end);
+                    push_display_formula(&mut section, block);
+                    display_block = None;
+                }
+                None => {
+                    block.push_str(line);
+                    block.push('\n');
+                }
+            }
+            continue;
+        }
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some((level, heading)) = markdown_heading(line) {
+            if level == 1 && document.title.is_none() && document.sections.is_empty()
+                && section.heading.is_none() && section.text.is_empty() && section.formulas.is_empty()
+            {
+                document.title = Some(heading.to_string());
+            } else {
+                let finished = std::mem::replace(&mut section, DocumentSection::new(Some(heading.to_string()), String::new()));
+                push_section(&mut document, finished);
+            }
+            continue;
+        }
+
+        let opened = BLOCK_DELIMITERS.iter()
+            .find_map(|&(open, close)| line.strip_prefix(open).map(|rest| (rest, close)));
+        if let Some((rest, close)) = opened {
+            match rest.strip_suffix(close) {
+                Some(latex) => push_display_formula(&mut section, latex),
+                None => display_block = Some((format!("{}\n", rest), close)),
+            }
+            continue;
+        }
+
+        if !section.text.is_empty() {
+            section.text.push('\n');
+        }
+        append_text_with_inline_math(&mut section, line, &INLINE_DELIMITERS);
+    }
+
+    if let Some((block, _)) = display_block {
+        push_display_formula(&mut section, &block);
+    }
+    push_section(&mut document, section);
+    document
+}
+
+/// Level and text of a Markdown `#` heading
+fn markdown_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&ch| ch == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    line[level..].strip_prefix(' ').map(|heading| (level, heading.trim()))
+}
+
+fn push_display_formula(section: &mut DocumentSection, latex: &str) {
+    let latex = latex.trim();
+    if latex.is_empty() {
+        return;
+    }
+    if !section.text.is_empty() {
+        section.text.push('\n');
+    }
+    section.add_formula(FormulaBlock::new(latex.to_string(), section.text.len(), false));
+}
+
+/// Append a text line, lifting `open ... close` spans out as inline formulas
+///
+/// The earliest opening delimiter of `delimiters` starts the next span.
+/// Formula positions are byte offsets into the section text.
+pub(crate) fn append_text_with_inline_math(section: &mut DocumentSection, line: &str, delimiters: &[(&str, &str)]) {
+    let mut rest = line;
+    while let Some((start, open, close)) = delimiters.iter()
+        .filter_map(|&(open, close)| rest.find(open).map(|start| (start, open, close)))
+        .min_by_key(|&(start, _, _)| start)
+    {
+        let Some(length) = rest[start + open.len()..].find(close) else {
+            break;
+        };
+        section.text.push_str(&rest[..start]);
+        let latex = rest[start + open.len()..start + open.len() + length].trim().to_string();
+        section.add_formula(FormulaBlock::new(latex, section.text.len(), true));
+        rest = &rest[start + open.len() + length + close.len()..];
+    }
+    section.text.push_str(rest);
+}
+
+fn push_section(document: &mut DocumentContent, section: DocumentSection) {
+    if !section.text.is_empty() || !section.formulas.is_empty() {
+        document.add_section(section);
+    }
+}
+
+/// Turn the JSON answer of a chat model into an `AnalysisResult`
+pub(crate) fn parse_chat_analysis(text: &str) -> MathSeekResult<AnalysisResult> {
+    let analysis: ChatAnalysis = serde_json::from_str(strip_code_fence(text))
+        .map_err(|e| MathSeekError::SerializationError(format!("Failed to parse analysis response: {}", e)))?;
+
+    Ok(AnalysisResult {
+        formula_type: analysis.formula_type.unwrap_or_else(|| "Unknown".to_string()),
+        description: analysis.description.unwrap_or_else(|| "No description available".to_string()),
+        usage: analysis.usage.unwrap_or_else(|| "No usage information available".to_string()),
+        examples: analysis.examples.unwrap_or_default(),
+        variables: analysis.variables.unwrap_or_default(),
+        domain: analysis.domain,
+        related_formulas: analysis.related_formulas.unwrap_or_default(),
+        token_usage: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_default_backend() {
+        let backend = create_backend(DEFAULT_BACKEND).unwrap();
+        assert_eq!(backend.name(), DEFAULT_BACKEND);
+    }
+
+    #[test]
+    fn test_create_unknown_backend() {
+        assert!(create_backend("does-not-exist").is_err());
+    }
+
+    #[test]
+    fn test_available_backends_are_creatable() {
+        for name in available_backends() {
+            assert_eq!(create_backend(name).unwrap().name(), name);
+        }
+    }
+
+    #[test]
+    fn test_default_error_from_response() {
+        let backend = create_backend(DEFAULT_BACKEND).unwrap();
+        let body = r#"{"error": {"message": "Rate limit reached", "type": "requests", "code": "rate_limit_exceeded"}}"#;
+
+        match backend.error_from_response(StatusCode::TOO_MANY_REQUESTS, Some(20), body) {
+            MathSeekError::HttpError { status, code, message, retry_after_secs, retryable } => {
+                assert_eq!(status, 429);
+                assert_eq!(code.as_deref(), Some("rate_limit_exceeded"));
+                assert_eq!(message, "Rate limit reached");
+                assert_eq!(retry_after_secs, Some(20));
+                assert!(retryable);
+            }
+            other => panic!("Expected HttpError, got {:?}", other),
+        }
+
+        // Digits in the body must not influence the classification
+        let error = backend.error_from_response(StatusCode::BAD_GATEWAY, None, "upstream 401 403 400");
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn test_parse_error_body_shapes() {
+        let (code, message) = parse_error_body(StatusCode::BAD_REQUEST, r#"{"error": "Invalid image", "error_info": {"id": "image_decode_error"}}"#);
+        assert_eq!(code.as_deref(), Some("image_decode_error"));
+        assert_eq!(message, "Invalid image");
+
+        let (code, message) = parse_error_body(StatusCode::UNAUTHORIZED, "");
+        assert_eq!(code, None);
+        assert_eq!(message, "Unauthorized");
+    }
+
+    #[test]
+    fn test_strip_code_fence_and_delimiters() {
+        assert_eq!(strip_code_fence("```latex\nx^2\n```"), "x^2");
+        assert_eq!(strip_code_fence("  x^2 "), "x^2");
+        assert_eq!(strip_math_delimiters("$$x^2$$"), "x^2");
+        assert_eq!(strip_math_delimiters("\\[ \\frac{a}{b} \\]"), "\\frac{a}{b}");
+        assert_eq!(strip_math_delimiters("x^2"), "x^2");
+    }
+
+    #[test]
+    fn test_parse_chat_recognition_document() {
+        let answer = r#"```json
+{"title": null, "sections": [{"heading": "Intro", "text": "Energy is ", "formulas": [{"latex": "E = mc^2", "position": 10, "is_inline": true}]}]}
+```"#;
+        let result = parse_chat_recognition(answer, InputType::Document).unwrap();
+        assert_eq!(result.latex, "E = mc^2");
+        match result.content {
+            crate::ResultContent::Document(doc) => assert_eq!(doc.sections[0].heading.as_deref(), Some("Intro")),
+            _ => panic!("Expected Document content"),
+        }
+
+        let fallback = parse_chat_recognition("Some plain text", InputType::Document).unwrap();
+        assert_eq!(fallback.latex, "Some plain text");
+    }
+
+    #[test]
+    fn test_chat_formula_positions_become_byte_offsets() {
+        let answer = r#"{"title": null, "sections": [{"heading": null, "text": "能量是和质量有关", "formulas": [
+            {"latex": "E", "position": 3, "is_inline": true},
+            {"latex": "m", "position": 100, "is_inline": true}]}]}"#;
+        let result = parse_chat_recognition(answer, InputType::Document).unwrap();
+        let crate::ResultContent::Document(doc) = &result.content else {
+            panic!("Expected Document content");
+        };
+        let section = &doc.sections[0];
+        let positions: Vec<usize> = section.formulas.iter().map(|formula| formula.position).collect();
+        assert_eq!(positions, vec!["能量是".len(), section.text.len()]);
+
+        // Exports insert formulas at these offsets
+        let manager = crate::ExportManager::new(crate::AppConfig::default());
+        for format in [crate::ExportFormat::Markdown, crate::ExportFormat::LaTeX, crate::ExportFormat::HTML, crate::ExportFormat::PlainText] {
+            let config = crate::ExportConfig { format, ..Default::default() };
+            assert!(manager.export_formula_result(&result, &config).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_parse_markdown_document() {
+        let markdown = "# Notes\n\n## Energy\nMass $m$ relates to energy:\n$$\nE = mc^2\n$$\n## Motion\nVelocity is $v$.\n$$ p = mv";
+        let doc = parse_markdown_document(markdown);
+
+        assert_eq!(doc.title.as_deref(), Some("Notes"));
+        assert_eq!(doc.sections.len(), 2);
+        assert_eq!(doc.sections[0].heading.as_deref(), Some("Energy"));
+        assert_eq!(doc.sections[0].text, "Mass  relates to energy:\n");
+        assert_eq!(doc.sections[0].formulas[0].latex, "m");
+        assert_eq!(doc.sections[0].formulas[0].position, 5);
+        assert_eq!(doc.sections[0].formulas[1].latex, "E = mc^2");
+        assert!(!doc.sections[0].formulas[1].is_inline);
+        // The unterminated block at the end of a partial answer is kept
+        assert_eq!(doc.sections[1].formulas[1].latex, "p = mv");
+
+        let doc = parse_markdown_document("Let \\(a\\) and $b$ be given.\n\\[\na + b\n\\]");
+        let formulas: Vec<(&str, bool)> = doc.sections[0].formulas.iter()
+            .map(|formula| (formula.latex.as_str(), formula.is_inline))
+            .collect();
+        assert_eq!(formulas, vec![("a", true), ("b", true), ("a + b", false)]);
+        assert_eq!(doc.sections[0].text, "Let  and  be given.\n");
+    }
+
+    #[test]
+    fn test_parse_chat_stream() {
+        let result = parse_chat_stream("## Intro\nEnergy is $E = mc^2$", InputType::Document).unwrap();
+        assert_eq!(result.latex, "E = mc^2");
+
+        let formula = parse_chat_stream("$$x^2$$", InputType::SingleFormula).unwrap();
+        assert_eq!(formula.latex, "x^2");
+
+        assert!(parse_chat_stream("  ", InputType::Document).is_err());
+    }
+
+    #[test]
+    fn test_parse_chat_analysis() {
+        let analysis = parse_chat_analysis(r#"{"formula_type": "Equation", "examples": ["a"]}"#).unwrap();
+        assert_eq!(analysis.formula_type, "Equation");
+        assert_eq!(analysis.usage, "No usage information available");
+        assert_eq!(analysis.examples, vec!["a".to_string()]);
+        assert!(parse_chat_analysis("not json").is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
     MathSeekError, MathSeekResult, AppConfig, FormulaResult, InputType, 
-    ApiClient, ImageProcessor, ResultContent, DocumentContent, DocumentSection
+    ApiClient, ApiConfig, ImageProcessor, ResultContent, DocumentContent, DocumentSection, CancellationToken, RegionKind,
+    PreprocessingConfig,
 };
+use crate::ensemble::{self, Ballot, EnsembleConfig};
 use serde::{Deserialize, Serialize};
 
 /// Configuration for the recognition engine
@@ -9,51 +11,286 @@ use serde::{Deserialize, Serialize};
 pub struct RecognitionConfig {
     pub confidence_threshold: f32,
     pub preprocessing_enabled: bool,
+    /// Stages run when preprocessing is enabled
+    #[serde(default)]
+    pub preprocessing: PreprocessingConfig,
     pub auto_type_detection: bool,
     pub validation_enabled: bool,
 }
 
+impl RecognitionConfig {
+    /// Defaults with the preprocessing stages saved in `app_config`
+    pub fn from_app_config(app_config: &AppConfig) -> Self {
+        Self {
+            preprocessing: app_config.preprocessing.clone(),
+            ..Self::default()
+        }
+    }
+}
+
 impl Default for RecognitionConfig {
     fn default() -> Self {
         Self {
             confidence_threshold: 0.5,
             preprocessing_enabled: true,
+            preprocessing: PreprocessingConfig::default(),
             auto_type_detection: true,
             validation_enabled: true,
         }
     }
 }
 
+/// Progress of a streamed recognition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecognitionPartial {
+    /// Job the output belongs to, filled in by the command running it
+    #[serde(default)]
+    pub job_id: Option<String>,
+    pub input_type: InputType,
+    /// Full LaTeX or Markdown received so far
+    pub text: String,
+}
+
+/// Result of a streamed recognition, with the job it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecognitionFinal {
+    pub job_id: String,
+    #[serde(flatten)]
+    pub result: FormulaResult,
+}
+
+/// Listener notified whenever a streamed recognition receives more output
+pub type PartialListener<'a> = &'a (dyn Fn(RecognitionPartial) + Send + Sync);
+
+/// Which provider produced a result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecognitionProvenance {
+    pub provider: String,
+    pub endpoint: String,
+    /// Providers tried first, in order
+    pub skipped: Vec<SkippedProvider>,
+}
+
+/// A provider the engine fell through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedProvider {
+    pub provider: String,
+    pub endpoint: String,
+    pub reason: SkipReason,
+    pub message: String,
+}
+
+/// Why the engine moved on to the next provider
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Transient failure that outlasted the client's own retries
+    Unavailable,
+    Timeout,
+    CircuitOpen,
+    LowConfidence,
+    /// Any other error of an ensemble member, which does not stop the vote
+    Failed,
+}
+
+impl SkipReason {
+    /// Errors worth trying another provider for; anything else is final
+    fn for_error(error: &MathSeekError) -> Option<Self> {
+        match error {
+            MathSeekError::Timeout(_) => Some(Self::Timeout),
+            MathSeekError::CircuitOpen { .. } => Some(Self::CircuitOpen),
+            error if error.is_retryable() => Some(Self::Unavailable),
+            _ => None,
+        }
+    }
+}
+
 /// Core recognition engine that orchestrates the formula recognition process
+///
+/// API calls go to the first provider and fall through the configured
+/// fallback providers when a provider is down or below the confidence threshold.
+/// Only providers that score their results, such as the native and Mathpix
+/// backends, are checked against the threshold.
 pub struct RecognitionEngine {
-    api_client: ApiClient,
+    api_clients: Vec<ApiClient>,
     config: RecognitionConfig,
 }
 
 impl RecognitionEngine {
     /// Create a new recognition engine with the given configuration
     pub fn new(app_config: &AppConfig) -> MathSeekResult<Self> {
-        let api_client = ApiClient::from_app_config(app_config)?;
-        let config = RecognitionConfig::default();
-        
-        Ok(Self {
-            api_client,
-            config,
-        })
+        Self::with_config(app_config, RecognitionConfig::from_app_config(app_config))
     }
 
     /// Create recognition engine with custom configuration
     pub fn with_config(app_config: &AppConfig, recognition_config: RecognitionConfig) -> MathSeekResult<Self> {
-        let api_client = ApiClient::from_app_config(app_config)?;
+        let mut api_clients = vec![ApiClient::from_app_config(app_config)?];
+        for provider in &app_config.fallback_providers {
+            api_clients.push(ApiClient::new(ApiConfig::for_fallback(app_config, provider))?);
+        }
         
         Ok(Self {
-            api_client,
+            api_clients,
             config: recognition_config,
         })
     }
 
+    /// Create recognition engine around an already configured API client
+    pub fn from_api_client(api_client: ApiClient, recognition_config: RecognitionConfig) -> Self {
+        Self {
+            api_clients: vec![api_client],
+            config: recognition_config,
+        }
+    }
+
+    /// Append a provider to try after the existing ones
+    pub fn with_fallback(mut self, api_client: ApiClient) -> Self {
+        self.api_clients.push(api_client);
+        self
+    }
+
+    /// Preprocess images with `preprocessing`, such as a profile's stages
+    pub fn with_preprocessing(mut self, preprocessing: PreprocessingConfig) -> Self {
+        self.config.preprocessing = preprocessing;
+        self
+    }
+
+    /// Abort API calls of this engine once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.api_clients = self.api_clients
+            .into_iter()
+            .map(|api_client| api_client.with_cancellation(token.clone()))
+            .collect();
+        self
+    }
+
     /// Recognize mathematical content from image data
     pub async fn recognize_content(&self, image_data: Vec<u8>, input_type: Option<InputType>) -> MathSeekResult<FormulaResult> {
+        self.run_recognition(image_data, input_type, None).await
+    }
+
+    /// Recognize content while reporting the output received so far to `on_partial`
+    ///
+    /// The final result goes through the same validation as `recognize_content`.
+    pub async fn recognize_content_streaming(
+        &self,
+        image_data: Vec<u8>,
+        input_type: Option<InputType>,
+        on_partial: PartialListener<'_>,
+    ) -> MathSeekResult<FormulaResult> {
+        self.run_recognition(image_data, input_type, Some(on_partial)).await
+    }
+
+    async fn run_recognition(
+        &self,
+        image_data: Vec<u8>,
+        input_type: Option<InputType>,
+        on_partial: Option<PartialListener<'_>>,
+    ) -> MathSeekResult<FormulaResult> {
+        let (processed_image, detected_type) = self.prepare(image_data, input_type)?;
+
+        // Steps 5-7 run against each provider until one gives a confident result
+        let mut skipped = Vec::new();
+        let mut last_error = None;
+
+        for api_client in &self.api_clients {
+            let (reason, error) = match self.recognize_with(api_client, &processed_image, &detected_type, on_partial).await {
+                // Step 7: Check confidence threshold
+                Ok(result) if api_client.reports_confidence() && result.confidence < self.config.confidence_threshold => {
+                    let error = MathSeekError::ApiError(format!(
+                        "Recognition confidence ({:.2}) below threshold ({:.2})",
+                        result.confidence, self.config.confidence_threshold
+                    ));
+                    (SkipReason::LowConfidence, error)
+                }
+                Ok(mut result) => {
+                    result.provenance = Some(RecognitionProvenance {
+                        provider: api_client.backend_name().to_string(),
+                        endpoint: api_client.api_config().endpoint.clone(),
+                        skipped,
+                    });
+                    return Ok(result);
+                }
+                Err(error) => match SkipReason::for_error(&error) {
+                    Some(reason) => (reason, error),
+                    None => return Err(error),
+                },
+            };
+
+            skipped.push(Self::skipped(api_client, reason, &error));
+            last_error = Some(error);
+        }
+
+        Err(last_error.unwrap_or_else(Self::no_provider))
+    }
+
+    /// Recognize content with every provider and return the answer most of them agree on
+    ///
+    /// Each provider is asked `samples_per_provider` times, all at once. The
+    /// answers are compared after LaTeX normalization; the majority answer gets
+    /// the share of agreeing answers as its confidence, and the other answers
+    /// are kept as alternatives. Failed members are listed in the provenance
+    /// and do not count as votes. The confidence threshold does not apply.
+    pub async fn recognize_ensemble(
+        &self,
+        image_data: Vec<u8>,
+        input_type: Option<InputType>,
+        ensemble: &EnsembleConfig,
+    ) -> MathSeekResult<FormulaResult> {
+        let (processed_image, detected_type) = self.prepare(image_data, input_type)?;
+
+        // Repeated samples of a deterministic model would all be the same
+        let samples = ensemble.samples_per_provider.max(1);
+        let members: Vec<ApiClient> = self.api_clients
+            .iter()
+            .flat_map(|api_client| (0..samples).map(move |_| match samples {
+                1 => api_client.clone(),
+                _ => api_client.clone().with_temperature(ensemble.sample_temperature),
+            }))
+            .collect();
+
+        let outcomes = futures::future::join_all(
+            members.iter().map(|api_client| self.recognize_with(api_client, &processed_image, &detected_type, None))
+        ).await;
+
+        let mut answers = Vec::new();
+        let mut skipped = Vec::new();
+        let mut last_error = None;
+        for (api_client, outcome) in members.iter().zip(outcomes) {
+            match outcome {
+                Ok(result) => answers.push((api_client, Self::provider_label(api_client), result)),
+                Err(MathSeekError::Cancelled) => return Err(MathSeekError::Cancelled),
+                Err(error) => {
+                    let reason = SkipReason::for_error(&error).unwrap_or(SkipReason::Failed);
+                    skipped.push(Self::skipped(api_client, reason, &error));
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        let ballots: Vec<Ballot> = answers
+            .iter()
+            .map(|(_, provider, result)| Ballot { latex: &result.latex, confidence: result.confidence, provider })
+            .collect();
+        let Some(tally) = ensemble::tally(&ballots) else {
+            return Err(last_error.unwrap_or_else(Self::no_provider));
+        };
+
+        let (api_client, _, mut result) = answers.swap_remove(tally.winner);
+        result.confidence = tally.agreement;
+        result.alternatives = tally.alternatives;
+        result.provenance = Some(RecognitionProvenance {
+            provider: api_client.backend_name().to_string(),
+            endpoint: api_client.api_config().endpoint.clone(),
+            skipped,
+        });
+        Ok(result)
+    }
+
+    /// Validate, preprocess and classify the image (steps 1-4)
+    fn prepare(&self, image_data: Vec<u8>, input_type: Option<InputType>) -> MathSeekResult<(Vec<u8>, InputType)> {
         // Step 1: Validate image data
         if !ImageProcessor::validate_image(&image_data) {
             return Err(MathSeekError::ImageError("Invalid image data provided".to_string()));
@@ -66,7 +303,7 @@ impl RecognitionEngine {
 
         // Step 3: Preprocess image if enabled
         let processed_image = if self.config.preprocessing_enabled {
-            ImageProcessor::preprocess_image(&image_data)?
+            ImageProcessor::preprocess_image(&image_data, &self.config.preprocessing)?
         } else {
             image_data
         };
@@ -83,10 +320,42 @@ impl RecognitionEngine {
             }
         };
 
+        Ok((processed_image, detected_type))
+    }
+
+    fn skipped(api_client: &ApiClient, reason: SkipReason, error: &MathSeekError) -> SkippedProvider {
+        SkippedProvider {
+            provider: api_client.backend_name().to_string(),
+            endpoint: api_client.api_config().endpoint.clone(),
+            reason,
+            message: error.to_string(),
+        }
+    }
+
+    fn no_provider() -> MathSeekError {
+        MathSeekError::ConfigError("No recognition provider configured".to_string())
+    }
+
+    /// `backend/model`, or just the backend when it has no model setting
+    fn provider_label(api_client: &ApiClient) -> String {
+        match api_client.api_config().model.as_str() {
+            "" => api_client.backend_name().to_string(),
+            model => format!("{}/{}", api_client.backend_name(), model),
+        }
+    }
+
+    /// Recognize with one provider and validate its result
+    async fn recognize_with(
+        &self,
+        api_client: &ApiClient,
+        image_data: &[u8],
+        input_type: &InputType,
+        on_partial: Option<PartialListener<'_>>,
+    ) -> MathSeekResult<FormulaResult> {
         // Step 5: Call API for recognition based on input type
-        let mut result = match detected_type {
-            InputType::SingleFormula => self.recognize_single_formula(&processed_image).await?,
-            InputType::Document => self.recognize_document(&processed_image).await?,
+        let mut result = match input_type {
+            InputType::SingleFormula => self.recognize_single_formula(api_client, image_data, on_partial).await?,
+            InputType::Document => self.recognize_document(api_client, image_data, on_partial).await?,
         };
 
         // Step 6: Validate result if enabled
@@ -94,21 +363,18 @@ impl RecognitionEngine {
             self.validate_recognition_result(&mut result)?;
         }
 
-        // Step 7: Check confidence threshold
-        if result.confidence < self.config.confidence_threshold {
-            return Err(MathSeekError::ApiError(format!(
-                "Recognition confidence ({:.2}) below threshold ({:.2})",
-                result.confidence, self.config.confidence_threshold
-            )));
-        }
-
         Ok(result)
     }
 
     /// Recognize a single mathematical formula
-    async fn recognize_single_formula(&self, image_data: &[u8]) -> MathSeekResult<FormulaResult> {
+    async fn recognize_single_formula(
+        &self,
+        api_client: &ApiClient,
+        image_data: &[u8],
+        on_partial: Option<PartialListener<'_>>,
+    ) -> MathSeekResult<FormulaResult> {
         // Use API client to recognize the formula
-        let result = self.api_client.recognize_image(image_data, InputType::SingleFormula).await?;
+        let result = Self::call_api(api_client, image_data, InputType::SingleFormula, on_partial).await?;
         
         // Ensure the result is properly formatted for single formula
         match result.content {
@@ -116,18 +382,27 @@ impl RecognitionEngine {
             ResultContent::Document(doc) => {
                 // Convert document to single formula if it contains only one formula
                 let latex = self.extract_single_formula_from_document(&doc)?;
-                Ok(FormulaResult::new_single_formula(latex, result.confidence))
+                Ok(FormulaResult {
+                    token_usage: result.token_usage,
+                    payload: result.payload,
+                    ..FormulaResult::new_single_formula(latex, result.confidence)
+                })
             }
         }
     }
 
     /// Recognize a document containing multiple formulas and text
-    async fn recognize_document(&self, image_data: &[u8]) -> MathSeekResult<FormulaResult> {
+    async fn recognize_document(
+        &self,
+        api_client: &ApiClient,
+        image_data: &[u8],
+        on_partial: Option<PartialListener<'_>>,
+    ) -> MathSeekResult<FormulaResult> {
         // First, analyze the image layout to understand structure
         let layout = ImageProcessor::analyze_image_layout(image_data)?;
         
         // Use API client to recognize the document
-        let mut result = self.api_client.recognize_image(image_data, InputType::Document).await?;
+        let mut result = Self::call_api(api_client, image_data, InputType::Document, on_partial).await?;
         
         // Enhance the result with layout information
         if let ResultContent::Document(ref mut doc) = result.content {
@@ -137,6 +412,27 @@ impl RecognitionEngine {
         Ok(result)
     }
 
+    /// Call the API, streaming the response when a listener is given
+    async fn call_api(
+        api_client: &ApiClient,
+        image_data: &[u8],
+        input_type: InputType,
+        on_partial: Option<PartialListener<'_>>,
+    ) -> MathSeekResult<FormulaResult> {
+        match on_partial {
+            Some(listener) => {
+                let partial_type = input_type.clone();
+                let report = move |text: &str| listener(RecognitionPartial {
+                    job_id: None,
+                    input_type: partial_type.clone(),
+                    text: text.to_string(),
+                });
+                api_client.recognize_image_streaming(image_data, input_type, &report).await
+            }
+            None => api_client.recognize_image(image_data, input_type).await,
+        }
+    }
+
     /// Extract a single formula from a document structure
     fn extract_single_formula_from_document(&self, doc: &DocumentContent) -> MathSeekResult<String> {
         // Look for the first formula in the document
@@ -175,6 +471,17 @@ impl RecognitionEngine {
                 first_section.text = region_info;
             }
         }
+
+        // When the counts agree, the formulas are the math regions in reading
+        // order, and the layout knows which of them stand on their own line
+        let formula_count: usize = doc.sections.iter().map(|section| section.formulas.len()).sum();
+        if formula_count == layout.formula_regions.len() {
+            let kinds = layout.regions.iter().filter(|region| region.kind.is_math());
+            let formulas = doc.sections.iter_mut().flat_map(|section| section.formulas.iter_mut());
+            for (formula, region) in formulas.zip(kinds) {
+                formula.is_inline = region.kind == RegionKind::InlineMath;
+            }
+        }
         
         Ok(())
     }
@@ -322,6 +629,196 @@ mod tests {
         assert!(!engine.is_valid_latex_syntax("$x + y"));            // Unclosed math mode
     }
 
+    #[tokio::test]
+    async fn test_recognize_content_from_cassette() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes");
+        let api_config = crate::ApiConfig {
+            endpoint: "http://recognize-cassette.invalid".to_string(),
+            api_key: "test-key".to_string(),
+            retry_delay_ms: 1,
+            cassette: Some(crate::cassette::CassetteConfig::replay(format!("{}/recognize_single_formula.json", fixtures))),
+            ..Default::default()
+        };
+        // Without preprocessing the request carries the fixture image unchanged
+        let engine = RecognitionEngine::from_api_client(
+            ApiClient::new(api_config).unwrap(),
+            RecognitionConfig { preprocessing_enabled: false, ..Default::default() },
+        );
+        let image = std::fs::read(format!("{}/plus_sign.png", fixtures)).unwrap();
+
+        // The cassette starts with a 503, which is retried
+        let result = engine.recognize_content(image, Some(InputType::SingleFormula)).await.unwrap();
+        assert_eq!(result.latex, "+");
+        assert_eq!(result.confidence, 0.97);
+    }
+
+    #[tokio::test]
+    async fn test_failover_through_providers() {
+        use crate::mock_server::{MockResponse, MockScript, MockServer};
+
+        let formula = |latex: &str, confidence: f32| MockScript::default().route("/recognize", vec![
+            MockResponse::ok(serde_json::json!({"success": true, "latex": latex, "confidence": confidence})),
+        ]);
+        let down = MockServer::start(MockScript::default().failure_rate(1.0)).await.unwrap();
+        let unsure = MockServer::start(formula("t", 0.3)).await.unwrap();
+        let healthy = MockServer::start(formula("a + b", 0.9)).await.unwrap();
+        let client = |server: &MockServer| ApiClient::new(crate::ApiConfig {
+            endpoint: server.url(),
+            api_key: "test-key".to_string(),
+            max_retries: 1,
+            retry_delay_ms: 1,
+            ..Default::default()
+        }).unwrap();
+
+        let engine = RecognitionEngine::from_api_client(
+            client(&down),
+            RecognitionConfig { preprocessing_enabled: false, ..Default::default() },
+        )
+        .with_fallback(client(&unsure))
+        .with_fallback(client(&healthy));
+        let image = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes/plus_sign.png")).unwrap();
+
+        let result = engine.recognize_content(image.clone(), Some(InputType::SingleFormula)).await.unwrap();
+        assert_eq!(result.latex, "a + b");
+        let provenance = result.provenance.unwrap();
+        assert_eq!(provenance.endpoint, healthy.url());
+        let reasons: Vec<SkipReason> = provenance.skipped.iter().map(|skipped| skipped.reason).collect();
+        assert_eq!(reasons, vec![SkipReason::Unavailable, SkipReason::LowConfidence]);
+        assert_eq!(provenance.skipped[0].endpoint, down.url());
+        assert_eq!(down.request_count("/recognize"), 2);
+
+        // Errors that another provider would not fix are returned immediately
+        let rejecting = MockServer::start(MockScript::default().api_key("other-key")).await.unwrap();
+        let engine = RecognitionEngine::from_api_client(
+            client(&rejecting),
+            RecognitionConfig { preprocessing_enabled: false, ..Default::default() },
+        )
+        .with_fallback(client(&healthy));
+        let error = engine.recognize_content(image, Some(InputType::SingleFormula)).await.unwrap_err();
+        assert!(matches!(error, MathSeekError::HttpError { status: 401, .. }));
+        assert_eq!(healthy.request_count("/recognize"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_chat_answers_are_not_skipped_for_confidence() {
+        use crate::mock_server::{MockResponse, MockScript, MockServer};
+
+        let chat = MockServer::start(MockScript::default().route("/chat/completions", vec![
+            MockResponse::ok(serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": "a + b"}}],
+            })),
+        ])).await.unwrap();
+        let native = MockServer::start(MockScript::default()).await.unwrap();
+        let client = |backend: &str, server: &MockServer| ApiClient::new(crate::ApiConfig {
+            backend: backend.to_string(),
+            endpoint: server.url(),
+            api_key: "test-key".to_string(),
+            model: "vision-model".to_string(),
+            retry_delay_ms: 1,
+            ..Default::default()
+        }).unwrap();
+
+        // The fixed chat confidence is below this threshold, but it is not a score
+        let engine = RecognitionEngine::from_api_client(
+            client("openai", &chat),
+            RecognitionConfig { preprocessing_enabled: false, confidence_threshold: 0.95, ..Default::default() },
+        )
+        .with_fallback(client(crate::recognition_backend::DEFAULT_BACKEND, &native));
+        let image = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes/plus_sign.png")).unwrap();
+
+        let result = engine.recognize_content(image, Some(InputType::SingleFormula)).await.unwrap();
+        assert_eq!(result.latex, "a + b");
+        assert!(result.provenance.unwrap().skipped.is_empty());
+        assert_eq!(native.request_count("/recognize"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ensemble_majority_vote() {
+        use crate::mock_server::{MockResponse, MockScript, MockServer};
+
+        let formula = |latex: &str, confidence: f32| MockScript::default().route("/recognize", vec![
+            MockResponse::ok(serde_json::json!({"success": true, "latex": latex, "confidence": confidence})),
+        ]);
+        let first = MockServer::start(formula("\\frac{1}{2}", 0.8)).await.unwrap();
+        let second = MockServer::start(formula("\\dfrac{1}{2}", 0.7)).await.unwrap();
+        let dissenting = MockServer::start(formula("\\frac{1}{z}", 0.99)).await.unwrap();
+        let down = MockServer::start(MockScript::default().failure_rate(1.0)).await.unwrap();
+        let client = |server: &MockServer| ApiClient::new(crate::ApiConfig {
+            endpoint: server.url(),
+            api_key: "test-key".to_string(),
+            max_retries: 0,
+            retry_delay_ms: 1,
+            ..Default::default()
+        }).unwrap();
+
+        let engine = RecognitionEngine::from_api_client(
+            client(&first),
+            RecognitionConfig { preprocessing_enabled: false, ..Default::default() },
+        )
+        .with_fallback(client(&dissenting))
+        .with_fallback(client(&down))
+        .with_fallback(client(&second));
+        let image = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes/plus_sign.png")).unwrap();
+
+        let result = engine
+            .recognize_ensemble(image, Some(InputType::SingleFormula), &EnsembleConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.latex, "\\frac{1}{2}");
+        assert!((result.confidence - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(result.alternatives.len(), 1);
+        assert_eq!(result.alternatives[0].latex, "\\frac{1}{z}");
+        assert_eq!(result.alternatives[0].providers, vec!["mathseek".to_string()]);
+
+        let provenance = result.provenance.unwrap();
+        assert_eq!(provenance.endpoint, first.url());
+        assert_eq!(provenance.skipped.len(), 1);
+        assert_eq!(provenance.skipped[0].endpoint, down.url());
+        assert_eq!(provenance.skipped[0].reason, SkipReason::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_ensemble_queries_members_concurrently() {
+        use crate::mock_server::{MockResponse, MockScript, MockServer};
+
+        // Every member holds its answer until long after the others have been asked
+        let slow = || MockScript::default().route("/recognize", vec![
+            MockResponse::ok(serde_json::json!({"success": true, "latex": "x", "confidence": 0.9})).with_delay(30_000),
+        ]);
+        let mut servers = Vec::new();
+        for _ in 0..3 {
+            servers.push(MockServer::start(slow()).await.unwrap());
+        }
+        let client = |server: &MockServer| ApiClient::new(crate::ApiConfig {
+            endpoint: server.url(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        let mut engine = RecognitionEngine::from_api_client(
+            client(&servers[0]),
+            RecognitionConfig { preprocessing_enabled: false, ..Default::default() },
+        );
+        for server in &servers[1..] {
+            engine = engine.with_fallback(client(server));
+        }
+        let image = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cassettes/plus_sign.png")).unwrap();
+        let recognition = tokio::spawn(async move {
+            engine.recognize_ensemble(image, Some(InputType::SingleFormula), &EnsembleConfig::default()).await
+        });
+
+        // Sequential requests would leave the later servers without one until the first answers
+        let all_asked = async {
+            while servers.iter().any(|server| server.request_count("/recognize") == 0) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), all_asked).await
+            .expect("ensemble members were not queried concurrently");
+        assert!(!recognition.is_finished());
+        recognition.abort();
+    }
+
     #[test]
     fn test_recognition_stats() {
         let app_config = AppConfig::default();
@@ -331,4 +828,69 @@ mod tests {
         assert_eq!(stats.confidence_threshold, 0.5);
         assert!(stats.preprocessing_enabled);
     }
+
+    #[test]
+    fn test_stream_events_carry_job_id() {
+        let partial = RecognitionPartial { job_id: Some("job-1".to_string()), input_type: InputType::SingleFormula, text: "x".to_string() };
+        assert_eq!(serde_json::to_value(&partial).unwrap()["jobId"], "job-1");
+
+        let result = FormulaResult::new_single_formula("x^2".to_string(), 0.9);
+        let event = serde_json::to_value(RecognitionFinal { job_id: "job-1".to_string(), result }).unwrap();
+        assert_eq!((event["jobId"].as_str(), event["latex"].as_str()), (Some("job-1"), Some("x^2")));
+        let parsed: RecognitionFinal = serde_json::from_value(event).unwrap();
+        assert_eq!(parsed.result.latex, "x^2");
+    }
+
+    #[test]
+    fn test_preprocessing_comes_from_app_config() {
+        use crate::{Binarization, PreprocessingProfile};
+
+        let app_config = AppConfig {
+            preprocessing: PreprocessingConfig::for_profile(PreprocessingProfile::Photo),
+            ..Default::default()
+        };
+        let engine = RecognitionEngine::new(&app_config).unwrap();
+        assert!(engine.config.preprocessing.perspective);
+        assert_eq!(engine.config.preprocessing.binarization, Binarization::Sauvola);
+
+        // A profile given to a command replaces the saved stages
+        let engine = engine.with_preprocessing(PreprocessingConfig::default());
+        assert!(!engine.config.preprocessing.perspective);
+    }
+
+    #[test]
+    fn test_layout_marks_display_formulas() {
+        use crate::{FormulaBlock, ImageLayout, LayoutRegion, Region};
+
+        let engine = RecognitionEngine::new(&AppConfig::default()).unwrap();
+        let region = |kind, line| LayoutRegion { kind, line, bounds: Region { x: 0, y: 0, width: 1, height: 1 } };
+        let regions = vec![
+            region(RegionKind::Text, 0),
+            region(RegionKind::InlineMath, 0),
+            region(RegionKind::DisplayMath, 1),
+        ];
+        let layout = ImageLayout {
+            has_multiple_formulas: true,
+            has_text_content: true,
+            formula_regions: regions[1..].iter().map(|region| region.bounds.clone()).collect(),
+            text_regions: vec![regions[0].bounds.clone()],
+            regions,
+        };
+
+        let formula = |latex: &str| FormulaBlock { latex: latex.to_string(), position: 0, is_inline: false };
+        let mut section = DocumentSection::new(None, "Let x be".to_string());
+        section.add_formula(formula("x"));
+        section.add_formula(formula("x^2 = 1"));
+        let mut doc = DocumentContent { title: None, sections: vec![section] };
+
+        engine.enhance_document_with_layout(&mut doc, &layout).unwrap();
+        let inline: Vec<bool> = doc.sections[0].formulas.iter().map(|formula| formula.is_inline).collect();
+        assert_eq!(inline, vec![true, false]);
+
+        // Counts that disagree leave the formulas alone
+        doc.sections[0].add_formula(formula("y"));
+        engine.enhance_document_with_layout(&mut doc, &layout).unwrap();
+        assert!(!doc.sections[0].formulas[2].is_inline);
+        assert!(doc.sections[0].formulas[0].is_inline);
+    }
 }
\ No newline at end of file
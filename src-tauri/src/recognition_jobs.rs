@@ -0,0 +1,155 @@
+use crate::{MathSeekError, MathSeekResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Signal that lets a running recognition be aborted from another command
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    receiver: watch::Receiver<bool>,
+}
+
+impl CancellationToken {
+    /// Whether the job has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolve once the job is cancelled; never resolves after it finished normally
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        if receiver.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Announces the ID of a job that has started, generated or not
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecognitionStarted {
+    pub job_id: String,
+}
+
+/// Registry of in-flight recognition jobs, managed as Tauri state
+#[derive(Debug, Default)]
+pub struct RecognitionJobs {
+    jobs: Mutex<HashMap<String, watch::Sender<bool>>>,
+    next_id: AtomicU64,
+}
+
+impl RecognitionJobs {
+    /// Register a job, generating an ID when the caller did not supply one
+    ///
+    /// The job is unregistered when the returned guard is dropped.
+    pub fn start(&self, job_id: Option<String>) -> MathSeekResult<RecognitionJob<'_>> {
+        let id = job_id.unwrap_or_else(|| format!("job-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1));
+        let mut jobs = self.lock();
+
+        if jobs.contains_key(&id) {
+            return Err(MathSeekError::DuplicateJob(id));
+        }
+
+        let (sender, receiver) = watch::channel(false);
+        jobs.insert(id.clone(), sender);
+
+        Ok(RecognitionJob {
+            jobs: self,
+            id,
+            token: CancellationToken { receiver },
+        })
+    }
+
+    /// Cancel a running job; returns false if no job with that ID is running
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.lock().get(job_id) {
+            Some(sender) => {
+                sender.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// IDs of all running jobs
+    pub fn active_jobs(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.lock().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, watch::Sender<bool>>> {
+        self.jobs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A registered job; unregisters itself when dropped
+pub struct RecognitionJob<'a> {
+    jobs: &'a RecognitionJobs,
+    id: String,
+    token: CancellationToken,
+}
+
+impl RecognitionJob<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Token to hand to the `ApiClient` running this job
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for RecognitionJob<'_> {
+    fn drop(&mut self) {
+        self.jobs.lock().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let jobs = RecognitionJobs::default();
+        let first = jobs.start(None).unwrap();
+        let named = jobs.start(Some("paste-1".to_string())).unwrap();
+        assert_eq!(first.id(), "job-1");
+        assert_eq!(jobs.active_jobs(), vec!["job-1".to_string(), "paste-1".to_string()]);
+
+        // IDs must be unique while a job runs
+        assert!(matches!(
+            jobs.start(Some("paste-1".to_string())),
+            Err(MathSeekError::DuplicateJob(id)) if id == "paste-1"
+        ));
+
+        assert!(jobs.cancel("paste-1"));
+        assert!(named.token().is_cancelled());
+        assert!(!first.token().is_cancelled());
+
+        drop(named);
+        assert!(!jobs.cancel("paste-1"));
+        assert_eq!(jobs.active_jobs(), vec!["job-1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_future() {
+        let jobs = RecognitionJobs::default();
+        let job = jobs.start(None).unwrap();
+        let token = job.token();
+
+        // A finished job never reports cancellation
+        drop(job);
+        assert!(tokio::time::timeout(Duration::from_millis(20), token.cancelled()).await.is_err());
+
+        let job = jobs.start(None).unwrap();
+        let token = job.token();
+        jobs.cancel(job.id());
+        assert!(tokio::time::timeout(Duration::from_millis(20), token.cancelled()).await.is_ok());
+    }
+}
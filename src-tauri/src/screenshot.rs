@@ -0,0 +1,757 @@
+use crate::{MathSeekError, MathSeekResult, Region};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// What `capture` takes a picture of
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotOptions {
+    /// Monitor name from `list_monitors`; the primary monitor when unset
+    pub monitor: Option<String>,
+    /// Part of the monitor to keep, relative to its top-left corner
+    pub region: Option<Region>,
+    /// Let the user drag a rectangle instead of using `monitor` and `region`
+    pub interactive: bool,
+}
+
+/// A screen picture taken by `capture`
+#[derive(Debug, Clone)]
+pub struct Screenshot {
+    pub png: Vec<u8>,
+    /// Copy saved by the desktop portal that could not be removed
+    pub leftover_file: Option<PathBuf>,
+}
+
+/// A screen picture as handed to the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapturedScreenshot {
+    /// PNG as a data URL
+    pub image: String,
+    pub leftover_file: Option<PathBuf>,
+}
+
+/// A monitor in desktop coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+/// A rectangle of the desktop to capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Area {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl From<&MonitorInfo> for Area {
+    fn from(monitor: &MonitorInfo) -> Self {
+        Self { x: monitor.x, y: monitor.y, width: monitor.width, height: monitor.height }
+    }
+}
+
+/// Whether this session has a screen `capture` can reach
+pub fn is_available() -> bool {
+    cfg!(target_os = "linux") && (std::env::var_os("DISPLAY").is_some() || is_wayland_session())
+}
+
+/// Capture the screen as PNG
+///
+/// X11 is used when `$DISPLAY` is set outside a Wayland session. On Wayland
+/// the desktop portal takes the picture, since X11 clients only see other
+/// X11 windows there; the portal has no notion of monitors, so `monitor`
+/// is ignored and `region` applies to the whole desktop. Interactive
+/// selection that the user cancels fails with `Cancelled`, and one that
+/// nobody finishes within `SELECTION_TIMEOUT` fails with `Timeout`.
+/// Dropping the returned future ends an X11 selection and its grabs.
+/// The portal also saves the picture to a file, which is removed again;
+/// one that cannot be removed is named in the result or the error.
+pub async fn capture(options: &ScreenshotOptions) -> MathSeekResult<Screenshot> {
+    #[cfg(target_os = "linux")]
+    {
+        if uses_portal() {
+            return portal::capture(options).await;
+        }
+
+        let options = options.clone();
+        let abandoned = AbandonOnDrop::default();
+        let flag = abandoned.0.clone();
+        let png = tokio::task::spawn_blocking(move || x11::capture(&options, &flag))
+            .await
+            .map_err(|e| MathSeekError::ImageError(format!("Screenshot capture was interrupted: {}", e)))??;
+        Ok(Screenshot { png, leftover_file: None })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = options;
+        Err(unsupported())
+    }
+}
+
+/// Monitors `capture` can select, primary first
+///
+/// Empty on Wayland, where the portal lets the user choose instead.
+pub async fn list_monitors() -> MathSeekResult<Vec<MonitorInfo>> {
+    #[cfg(target_os = "linux")]
+    {
+        if uses_portal() {
+            return Ok(Vec::new());
+        }
+
+        tokio::task::spawn_blocking(|| x11::XServer::connect()?.monitors())
+            .await
+            .map_err(|e| MathSeekError::ImageError(format!("Monitor query was interrupted: {}", e)))?
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    Err(unsupported())
+}
+
+/// Longest time an interactive selection waits for the user
+pub const SELECTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// Raises its flag when dropped, telling a blocking capture that nobody waits for it
+#[derive(Default)]
+struct AbandonOnDrop(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl Drop for AbandonOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
+
+#[cfg(target_os = "linux")]
+fn uses_portal() -> bool {
+    is_wayland_session() || std::env::var_os("DISPLAY").is_none()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> MathSeekError {
+    MathSeekError::ImageError("Screenshot capture is only supported on Linux".to_string())
+}
+
+/// The monitor named `name`, or the primary one
+fn select_monitor<'a>(monitors: &'a [MonitorInfo], name: Option<&str>) -> MathSeekResult<&'a MonitorInfo> {
+    let monitor = match name {
+        Some(name) => monitors.iter().find(|monitor| monitor.name == name),
+        None => monitors.iter().find(|monitor| monitor.primary).or(monitors.first()),
+    };
+
+    monitor.ok_or_else(|| {
+        let names: Vec<&str> = monitors.iter().map(|monitor| monitor.name.as_str()).collect();
+        MathSeekError::ImageError(format!(
+            "Monitor {} not found, available: {}", name.unwrap_or("(primary)"), names.join(", ")
+        ))
+    })
+}
+
+/// The part of `monitor` that `region` covers, clipped to the monitor
+fn capture_area(monitor: &MonitorInfo, region: Option<&Region>) -> MathSeekResult<Area> {
+    let Some(region) = region else {
+        return Ok(Area::from(monitor));
+    };
+
+    let width = region.width.min(monitor.width.saturating_sub(region.x));
+    let height = region.height.min(monitor.height.saturating_sub(region.y));
+    if width == 0 || height == 0 {
+        return Err(MathSeekError::ImageError(format!(
+            "Region {}x{} at ({}, {}) is empty or outside monitor {} ({}x{})",
+            region.width, region.height, region.x, region.y, monitor.name, monitor.width, monitor.height
+        )));
+    }
+
+    Ok(Area {
+        x: monitor.x + region.x as i32,
+        y: monitor.y + region.y as i32,
+        width,
+        height,
+    })
+}
+
+/// The rectangle dragged from `start` to `end`, or the monitor under a click
+fn selected_area(monitors: &[MonitorInfo], start: (i32, i32), end: (i32, i32)) -> Option<Area> {
+    let (width, height) = (start.0.abs_diff(end.0), start.1.abs_diff(end.1));
+    if width > 0 && height > 0 {
+        return Some(Area { x: start.0.min(end.0), y: start.1.min(end.1), width, height });
+    }
+
+    monitors.iter()
+        .find(|monitor| {
+            (monitor.x..monitor.x + monitor.width as i32).contains(&end.0)
+                && (monitor.y..monitor.y + monitor.height as i32).contains(&end.1)
+        })
+        .map(Area::from)
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use super::{capture_area, select_monitor, selected_area, Area, MonitorInfo, ScreenshotOptions};
+    use crate::{MathSeekError, MathSeekResult};
+    use x11rb::connection::{Connection, RequestConnection};
+    use x11rb::protocol::randr::ConnectionExt as _;
+    use x11rb::protocol::xproto::{
+        ConnectionExt as _, CreateGCAux, EventMask, GrabMode, GrabStatus, ImageFormat, ImageOrder,
+        Rectangle, Screen, SubwindowMode, GX,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Press and release positions of a drag
+    pub(super) type Drag = ((i32, i32), (i32, i32));
+
+    /// Crosshair in the X cursor font; its mask is the next glyph
+    const CROSSHAIR_GLYPH: u16 = 34;
+
+    /// How often a selection without input checks whether it should stop
+    const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// How pixels of a Z-format image are laid out
+    #[derive(Debug, Clone, Copy)]
+    pub(super) struct PixelLayout {
+        pub bits_per_pixel: u8,
+        pub scanline_pad: u8,
+        pub big_endian: bool,
+        pub red_mask: u32,
+        pub green_mask: u32,
+        pub blue_mask: u32,
+    }
+
+    pub(super) struct XServer {
+        connection: RustConnection,
+        screen: usize,
+    }
+
+    pub(super) fn capture(options: &ScreenshotOptions, abandoned: &AtomicBool) -> MathSeekResult<Vec<u8>> {
+        let display = XServer::connect()?;
+        let monitors = display.monitors()?;
+
+        let area = if options.interactive {
+            let (start, end) = display.select_area(abandoned, || {})?.ok_or(MathSeekError::Cancelled)?;
+            selected_area(&monitors, start, end).ok_or(MathSeekError::Cancelled)?
+        } else {
+            capture_area(select_monitor(&monitors, options.monitor.as_deref())?, options.region.as_ref())?
+        };
+
+        let pixels = display.grab(area)?;
+        crate::clipboard::png_from_rgba(area.width, area.height, pixels)
+    }
+
+    fn x11_error(error: impl std::fmt::Display) -> MathSeekError {
+        MathSeekError::ImageError(format!("X11 screenshot failed: {}", error))
+    }
+
+    /// `area` in the 16-bit coordinates of the X11 protocol
+    fn rectangle(area: Area) -> MathSeekResult<Rectangle> {
+        let out_of_range = |_| x11_error(format!(
+            "area {}x{} at ({}, {}) is outside the X11 coordinate range", area.width, area.height, area.x, area.y
+        ));
+        Ok(Rectangle {
+            x: i16::try_from(area.x).map_err(out_of_range)?,
+            y: i16::try_from(area.y).map_err(out_of_range)?,
+            width: u16::try_from(area.width).map_err(out_of_range)?,
+            height: u16::try_from(area.height).map_err(out_of_range)?,
+        })
+    }
+
+    impl XServer {
+        pub fn connect() -> MathSeekResult<Self> {
+            let (connection, screen) = x11rb::connect(None)
+                .map_err(|e| MathSeekError::ImageError(format!("Cannot connect to the X server: {}", e)))?;
+            Ok(Self { connection, screen })
+        }
+
+        fn screen(&self) -> &Screen {
+            &self.connection.setup().roots[self.screen]
+        }
+
+        /// RandR monitors, or the whole screen when RandR 1.5 is missing
+        pub fn monitors(&self) -> MathSeekResult<Vec<MonitorInfo>> {
+            let screen = self.screen();
+            let whole_screen = vec![MonitorInfo {
+                name: "screen".to_string(),
+                x: 0,
+                y: 0,
+                width: screen.width_in_pixels as u32,
+                height: screen.height_in_pixels as u32,
+                primary: true,
+            }];
+
+            let has_randr = self.connection
+                .extension_information(x11rb::protocol::randr::X11_EXTENSION_NAME)
+                .map_err(x11_error)?
+                .is_some();
+            if !has_randr {
+                return Ok(whole_screen);
+            }
+            let version = self.connection.randr_query_version(1, 5).map_err(x11_error)?.reply().map_err(x11_error)?;
+            if (version.major_version, version.minor_version) < (1, 5) {
+                return Ok(whole_screen);
+            }
+
+            let reply = self.connection
+                .randr_get_monitors(screen.root, true)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?;
+
+            let mut monitors = Vec::new();
+            for monitor in reply.monitors {
+                let name = self.connection.get_atom_name(monitor.name).map_err(x11_error)?.reply().map_err(x11_error)?.name;
+                monitors.push(MonitorInfo {
+                    name: String::from_utf8_lossy(&name).into_owned(),
+                    x: monitor.x as i32,
+                    y: monitor.y as i32,
+                    width: monitor.width as u32,
+                    height: monitor.height as u32,
+                    primary: monitor.primary,
+                });
+            }
+            monitors.sort_by_key(|monitor| !monitor.primary);
+
+            Ok(if monitors.is_empty() { whole_screen } else { monitors })
+        }
+
+        /// RGBA pixels of `area` of the root window
+        pub fn grab(&self, area: Area) -> MathSeekResult<Vec<u8>> {
+            let screen = self.screen();
+            let rectangle = rectangle(area)?;
+            let reply = self.connection
+                .get_image(
+                    ImageFormat::Z_PIXMAP,
+                    screen.root,
+                    rectangle.x,
+                    rectangle.y,
+                    rectangle.width,
+                    rectangle.height,
+                    u32::MAX,
+                )
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?;
+
+            let setup = self.connection.setup();
+            let format = setup.pixmap_formats.iter()
+                .find(|format| format.depth == reply.depth)
+                .ok_or_else(|| x11_error(format!("no pixmap format for depth {}", reply.depth)))?;
+            let visual = screen.allowed_depths.iter()
+                .flat_map(|depth| &depth.visuals)
+                .find(|visual| visual.visual_id == reply.visual)
+                .ok_or_else(|| x11_error(format!("unknown visual {}", reply.visual)))?;
+
+            let layout = PixelLayout {
+                bits_per_pixel: format.bits_per_pixel,
+                scanline_pad: format.scanline_pad,
+                big_endian: setup.image_byte_order == ImageOrder::MSB_FIRST,
+                red_mask: visual.red_mask,
+                green_mask: visual.green_mask,
+                blue_mask: visual.blue_mask,
+            };
+            decode_zpixmap(&reply.data, area.width, area.height, layout)
+        }
+
+        /// Let the user drag a rectangle over the screen
+        ///
+        /// Returns the press and release positions, or `None` when a key or
+        /// another mouse button cancels or `abandoned` is raised. `grabbed`
+        /// is called once the pointer and keyboard belong to the selection.
+        /// The rectangle is drawn on the root window, which compositing
+        /// window managers may not show.
+        pub fn select_area(&self, abandoned: &AtomicBool, grabbed: impl FnOnce()) -> MathSeekResult<Option<Drag>> {
+            let connection = &self.connection;
+            let screen = self.screen();
+
+            let font = connection.generate_id().map_err(x11_error)?;
+            connection.open_font(font, b"cursor").map_err(x11_error)?;
+            let cursor = connection.generate_id().map_err(x11_error)?;
+            connection
+                .create_glyph_cursor(cursor, font, font, CROSSHAIR_GLYPH, CROSSHAIR_GLYPH + 1, 0, 0, 0, 0xffff, 0xffff, 0xffff)
+                .map_err(x11_error)?;
+            let gc = connection.generate_id().map_err(x11_error)?;
+            connection
+                .create_gc(gc, screen.root, &CreateGCAux::new()
+                    .function(GX::XOR)
+                    .foreground(screen.white_pixel ^ screen.black_pixel)
+                    .subwindow_mode(SubwindowMode::INCLUDE_INFERIORS)
+                    .line_width(1))
+                .map_err(x11_error)?;
+
+            let selection = self.track_drag(cursor, gc, abandoned, grabbed);
+
+            // Release everything even when tracking failed
+            let _ = connection.ungrab_pointer(x11rb::CURRENT_TIME);
+            let _ = connection.ungrab_keyboard(x11rb::CURRENT_TIME);
+            let _ = connection.free_gc(gc);
+            let _ = connection.free_cursor(cursor);
+            let _ = connection.close_font(font);
+            // Wait until the rectangle is gone before the screen is captured
+            connection.sync().map_err(x11_error)?;
+
+            selection
+        }
+
+        fn track_drag(&self, cursor: u32, gc: u32, abandoned: &AtomicBool, grabbed: impl FnOnce()) -> MathSeekResult<Option<Drag>> {
+            let connection = &self.connection;
+            let root = self.screen().root;
+
+            let mask = EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION;
+            let pointer = connection
+                .grab_pointer(false, root, mask, GrabMode::ASYNC, GrabMode::ASYNC, x11rb::NONE, cursor, x11rb::CURRENT_TIME)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?;
+            if pointer.status != GrabStatus::SUCCESS {
+                return Err(x11_error("another application has grabbed the pointer"));
+            }
+            // Without the keyboard, Escape would not reach the selection
+            let keyboard = connection
+                .grab_keyboard(false, root, x11rb::CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?;
+            if keyboard.status != GrabStatus::SUCCESS {
+                return Err(x11_error("another application has grabbed the keyboard"));
+            }
+            connection.flush().map_err(x11_error)?;
+            grabbed();
+
+            let draw = |start: (i32, i32), end: (i32, i32)| -> MathSeekResult<()> {
+                let area = Area {
+                    x: start.0.min(end.0),
+                    y: start.1.min(end.1),
+                    width: start.0.abs_diff(end.0),
+                    height: start.1.abs_diff(end.1),
+                };
+                connection.poly_rectangle(root, gc, &[rectangle(area)?]).map_err(x11_error)?;
+                connection.flush().map_err(x11_error)
+            };
+
+            let deadline = Instant::now() + super::SELECTION_TIMEOUT;
+            let mut drag: Option<Drag> = None;
+            loop {
+                let Some(event) = connection.poll_for_event().map_err(x11_error)? else {
+                    let timed_out = Instant::now() >= deadline;
+                    if timed_out || abandoned.load(Ordering::Relaxed) {
+                        if let Some((start, current)) = drag {
+                            draw(start, current)?;
+                        }
+                        return match timed_out {
+                            true => Err(MathSeekError::Timeout(format!(
+                                "No area selected within {}s", super::SELECTION_TIMEOUT.as_secs()
+                            ))),
+                            false => Ok(None),
+                        };
+                    }
+                    std::thread::sleep(EVENT_POLL_INTERVAL);
+                    continue;
+                };
+
+                match event {
+                    Event::ButtonPress(event) if event.detail == 1 && drag.is_none() => {
+                        let start = (event.root_x as i32, event.root_y as i32);
+                        draw(start, start)?;
+                        drag = Some((start, start));
+                    }
+                    Event::MotionNotify(event) => {
+                        if let Some((start, current)) = drag {
+                            // Drawing with XOR a second time erases the old rectangle
+                            let end = (event.root_x as i32, event.root_y as i32);
+                            draw(start, current)?;
+                            draw(start, end)?;
+                            drag = Some((start, end));
+                        }
+                    }
+                    Event::ButtonRelease(event) if event.detail == 1 => {
+                        if let Some((start, current)) = drag {
+                            draw(start, current)?;
+                            return Ok(Some((start, (event.root_x as i32, event.root_y as i32))));
+                        }
+                    }
+                    Event::ButtonPress(_) | Event::KeyPress(_) => {
+                        if let Some((start, current)) = drag {
+                            draw(start, current)?;
+                        }
+                        return Ok(None);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Convert a Z-format image to RGBA using the visual's channel masks
+    pub(super) fn decode_zpixmap(data: &[u8], width: u32, height: u32, layout: PixelLayout) -> MathSeekResult<Vec<u8>> {
+        let bytes_per_pixel = layout.bits_per_pixel as usize / 8;
+        if !(1..=4).contains(&bytes_per_pixel) || !layout.bits_per_pixel.is_multiple_of(8) {
+            return Err(x11_error(format!("unsupported pixel size of {} bits", layout.bits_per_pixel)));
+        }
+        let pad = layout.scanline_pad.max(8) as usize;
+        let stride = (width as usize * layout.bits_per_pixel as usize).div_ceil(pad) * pad / 8;
+        if data.len() < stride * height as usize {
+            return Err(x11_error(format!("image data is {} bytes, expected {}", data.len(), stride * height as usize)));
+        }
+
+        let channel = |pixel: u32, mask: u32| -> u8 {
+            if mask == 0 {
+                return 0;
+            }
+            let value = (pixel & mask) >> mask.trailing_zeros();
+            let max = mask >> mask.trailing_zeros();
+            (value * 255 / max) as u8
+        };
+
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in data.chunks(stride).take(height as usize) {
+            for bytes in row.chunks(bytes_per_pixel).take(width as usize) {
+                let pixel = if layout.big_endian {
+                    bytes.iter().fold(0u32, |pixel, &byte| (pixel << 8) | byte as u32)
+                } else {
+                    bytes.iter().rev().fold(0u32, |pixel, &byte| (pixel << 8) | byte as u32)
+                };
+                rgba.extend_from_slice(&[
+                    channel(pixel, layout.red_mask),
+                    channel(pixel, layout.green_mask),
+                    channel(pixel, layout.blue_mask),
+                    255,
+                ]);
+            }
+        }
+        Ok(rgba)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod portal {
+    use super::{capture_area, MonitorInfo, Screenshot, ScreenshotOptions};
+    use crate::{MathSeekError, MathSeekResult};
+    use ashpd::desktop::ResponseError;
+    use std::path::Path;
+
+    pub(super) async fn capture(options: &ScreenshotOptions) -> MathSeekResult<Screenshot> {
+        let screenshot = ashpd::desktop::screenshot::Screenshot::request()
+            .interactive(options.interactive)
+            .modal(true)
+            .send()
+            .await
+            .and_then(|request| request.response())
+            .map_err(portal_error)?;
+
+        let uri = screenshot.uri();
+        let path = uri.to_file_path()
+            .map_err(|_| MathSeekError::ImageError(format!("Desktop portal returned a non-local screenshot: {}", uri)))?;
+        let data = tokio::fs::read(&path).await
+            .map_err(|e| MathSeekError::ImageError(format!("Cannot read screenshot {}: {}", path.display(), e)));
+        // The portal saves every screenshot, usually among the user's pictures
+        let leftover_file = tokio::fs::remove_file(&path).await.err().map(|_| path.clone());
+        let png = data.and_then(|data| crop(&data, &path, options)).map_err(|error| match (&leftover_file, error) {
+            (Some(leftover), MathSeekError::ImageError(message)) => MathSeekError::ImageError(format!(
+                "{}; the screenshot could not be removed from {}", message, leftover.display()
+            )),
+            (_, error) => error,
+        })?;
+        Ok(Screenshot { png, leftover_file })
+    }
+
+    /// The part of the desktop picture `options` asks for, as PNG
+    fn crop(data: &[u8], path: &Path, options: &ScreenshotOptions) -> MathSeekResult<Vec<u8>> {
+        let image = image::load_from_memory(data)
+            .map_err(|e| MathSeekError::ImageError(format!("Failed to decode screenshot {}: {}", path.display(), e)))?
+            .to_rgba8();
+
+        let desktop = MonitorInfo {
+            name: "desktop".to_string(),
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+            primary: true,
+        };
+        let region = if options.interactive { None } else { options.region.as_ref() };
+        let area = capture_area(&desktop, region)?;
+
+        let cropped = image::imageops::crop_imm(&image, area.x as u32, area.y as u32, area.width, area.height).to_image();
+        crate::clipboard::png_from_rgba(area.width, area.height, cropped.into_raw())
+    }
+
+    fn portal_error(error: ashpd::Error) -> MathSeekError {
+        match error {
+            ashpd::Error::Response(ResponseError::Cancelled) => MathSeekError::Cancelled,
+            error => MathSeekError::ImageError(format!("Desktop portal screenshot failed: {}", error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitors() -> Vec<MonitorInfo> {
+        vec![
+            MonitorInfo { name: "DP-1".to_string(), x: 0, y: 0, width: 1920, height: 1080, primary: false },
+            MonitorInfo { name: "HDMI-1".to_string(), x: 1920, y: 0, width: 1280, height: 1024, primary: true },
+        ]
+    }
+
+    #[test]
+    fn test_select_monitor() {
+        let monitors = monitors();
+        assert_eq!(select_monitor(&monitors, None).unwrap().name, "HDMI-1");
+        assert_eq!(select_monitor(&monitors, Some("DP-1")).unwrap().name, "DP-1");
+
+        let error = select_monitor(&monitors, Some("eDP-1")).unwrap_err();
+        assert!(matches!(error, MathSeekError::ImageError(message) if message.contains("available: DP-1, HDMI-1")));
+    }
+
+    #[test]
+    fn test_capture_area_is_relative_and_clipped() {
+        let monitor = &monitors()[1];
+        assert_eq!(capture_area(monitor, None).unwrap(), Area { x: 1920, y: 0, width: 1280, height: 1024 });
+
+        let region = Region { x: 100, y: 50, width: 400, height: 2000 };
+        assert_eq!(capture_area(monitor, Some(&region)).unwrap(), Area { x: 2020, y: 50, width: 400, height: 974 });
+
+        let outside = Region { x: 1280, y: 0, width: 10, height: 10 };
+        assert!(capture_area(monitor, Some(&outside)).is_err());
+    }
+
+    #[test]
+    fn test_selected_area() {
+        let monitors = monitors();
+        // Dragging up and to the left works as well
+        assert_eq!(
+            selected_area(&monitors, (300, 200), (100, 150)),
+            Some(Area { x: 100, y: 150, width: 200, height: 50 })
+        );
+        // A click selects the monitor under the pointer
+        assert_eq!(
+            selected_area(&monitors, (2000, 10), (2000, 10)),
+            Some(Area { x: 1920, y: 0, width: 1280, height: 1024 })
+        );
+        assert_eq!(selected_area(&monitors, (2000, 1050), (2000, 1050)), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_decode_zpixmap() {
+        use x11::{decode_zpixmap, PixelLayout};
+
+        // Two BGRX pixels per row padded to 32 bits, as on a depth 24 TrueColor visual
+        let layout = PixelLayout {
+            bits_per_pixel: 32,
+            scanline_pad: 32,
+            big_endian: false,
+            red_mask: 0xff0000,
+            green_mask: 0x00ff00,
+            blue_mask: 0x0000ff,
+        };
+        let data = [0x30, 0x20, 0x10, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00, 0x01, 0x02, 0x03, 0x00];
+        assert_eq!(decode_zpixmap(&data, 2, 2, layout).unwrap(), vec![
+            0x10, 0x20, 0x30, 255, 0x00, 0x00, 0xff, 255,
+            0xff, 0x00, 0x00, 255, 0x03, 0x02, 0x01, 255,
+        ]);
+
+        // RGB565 with rows padded from 6 to 8 bytes
+        let layout = PixelLayout {
+            bits_per_pixel: 16,
+            scanline_pad: 32,
+            big_endian: false,
+            red_mask: 0xf800,
+            green_mask: 0x07e0,
+            blue_mask: 0x001f,
+        };
+        let data = [0x00, 0xf8, 0xe0, 0x07, 0x1f, 0x00, 0xaa, 0xaa];
+        assert_eq!(decode_zpixmap(&data, 3, 1, layout).unwrap(), vec![
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255,
+        ]);
+
+        assert!(decode_zpixmap(&data, 3, 2, layout).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[ignore = "needs an X server, run with `xvfb-run cargo test screenshot -- --ignored`"]
+    async fn test_x11_capture() {
+        let monitors = list_monitors().await.unwrap();
+        let primary = &monitors[0];
+
+        let screenshot = capture(&ScreenshotOptions::default()).await.unwrap();
+        assert!(screenshot.leftover_file.is_none());
+        assert_eq!(image::load_from_memory(&screenshot.png).unwrap().into_rgba8().dimensions(), (primary.width, primary.height));
+
+        let options = ScreenshotOptions {
+            monitor: Some(primary.name.clone()),
+            region: Some(Region { x: 10, y: 20, width: 64, height: 32 }),
+            interactive: false,
+        };
+        let png = capture(&options).await.unwrap().png;
+        assert_eq!(image::load_from_memory(&png).unwrap().into_rgba8().dimensions(), (64, 32));
+    }
+
+    /// Start a selection on another thread and wait until it owns the pointer
+    #[cfg(target_os = "linux")]
+    fn start_selection(
+        abandoned: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    ) -> std::thread::JoinHandle<MathSeekResult<Option<x11::Drag>>> {
+        let (grabbed, ready) = std::sync::mpsc::channel();
+        let selection = std::thread::spawn(move || {
+            x11::XServer::connect()?.select_area(&abandoned, move || grabbed.send(()).unwrap())
+        });
+        ready.recv_timeout(std::time::Duration::from_secs(10)).expect("the selection did not grab the pointer");
+        selection
+    }
+
+    /// Drags a rectangle with the XTEST extension while the selection runs
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs an X server with XTEST, run with `xvfb-run cargo test screenshot -- --ignored`"]
+    fn test_x11_interactive_selection() {
+        use x11rb::connection::Connection;
+        use x11rb::protocol::xproto::{BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, MOTION_NOTIFY_EVENT};
+        use x11rb::protocol::xtest::ConnectionExt as _;
+        use x11rb::wrapper::ConnectionExt as _;
+
+        let selection = start_selection(Default::default());
+
+        let (connection, screen) = x11rb::connect(None).unwrap();
+        let root = connection.setup().roots[screen].root;
+        for (event, button, x, y) in [
+            (MOTION_NOTIFY_EVENT, 0, 40, 30),
+            (BUTTON_PRESS_EVENT, 1, 0, 0),
+            (MOTION_NOTIFY_EVENT, 0, 90, 55),
+            (MOTION_NOTIFY_EVENT, 0, 140, 80),
+            (BUTTON_RELEASE_EVENT, 1, 0, 0),
+        ] {
+            connection.xtest_fake_input(event, button, x11rb::CURRENT_TIME, root, x, y, 0).unwrap();
+            connection.sync().unwrap();
+        }
+
+        let (start, end) = selection.join().unwrap().unwrap().unwrap();
+        assert_eq!(selected_area(&[], start, end), Some(Area { x: 40, y: 30, width: 100, height: 50 }));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs an X server, run with `xvfb-run cargo test screenshot -- --ignored`"]
+    fn test_x11_selection_stops_when_abandoned() {
+        let abandoned = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let selection = start_selection(abandoned.clone());
+
+        abandoned.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(selection.join().unwrap().unwrap(), None);
+    }
+}
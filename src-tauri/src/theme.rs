@@ -0,0 +1,160 @@
+use image::{GrayImage, Luma, RgbImage};
+use serde::{Deserialize, Serialize};
+
+/// Pixels sampled to find the background colour
+const MAX_SAMPLES: usize = 200_000;
+
+/// Backgrounds darker than this are a dark theme
+const DARK_LUMA: f32 = 128.0;
+
+/// Backgrounds whose channels spread this far apart are coloured
+const COLOURED_CHROMA: u8 = 48;
+
+/// Share of pixels at least as far from the background as full ink
+const INK_PERCENTILE: f64 = 0.005;
+
+/// Smallest colour distance mapped to full ink, so that noise on a blank image stays faint
+const MIN_INK_DISTANCE: u32 = 64;
+
+/// Background of an image, as found by [`detect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    /// Dominant colour
+    pub background: [u8; 3],
+    /// Light writing on a dark background, as in dark editor themes
+    pub dark: bool,
+    /// A background with a distinct hue
+    pub coloured: bool,
+}
+
+impl Theme {
+    /// Dark writing on white or gray paper, which plain grayscale already handles
+    pub fn is_plain(&self) -> bool {
+        !self.dark && !self.coloured
+    }
+}
+
+/// Find the dominant background colour of `rgb`
+///
+/// Colours are counted in buckets of 16 levels per channel and the most
+/// common bucket's mean colour is the background.
+pub fn detect(rgb: &RgbImage) -> Theme {
+    let mut buckets = vec![(0u32, [0u64; 3]); 16 * 16 * 16];
+    let step = (rgb.len() / 3).div_ceil(MAX_SAMPLES).max(1);
+    for pixel in rgb.pixels().step_by(step) {
+        let [r, g, b] = pixel.0;
+        let bucket = &mut buckets[(r as usize >> 4) << 8 | (g as usize >> 4) << 4 | b as usize >> 4];
+        bucket.0 += 1;
+        for (sum, level) in bucket.1.iter_mut().zip(pixel.0) {
+            *sum += level as u64;
+        }
+    }
+
+    let background = buckets.iter()
+        .max_by_key(|(count, _)| *count)
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, sums)| sums.map(|sum| (sum / *count as u64) as u8))
+        .unwrap_or([255; 3]);
+
+    let chroma = background.iter().max().unwrap_or(&0) - background.iter().min().unwrap_or(&0);
+    Theme {
+        background,
+        dark: luma(background) < DARK_LUMA,
+        coloured: chroma >= COLOURED_CHROMA,
+    }
+}
+
+/// Grayscale of `rgb` with dark writing on a light background
+///
+/// A plain page is converted as usual. For a dark or coloured theme the
+/// gray level is the colour distance from the background, so that light,
+/// dark and coloured writing alike turn dark on white, anti-aliasing
+/// included.
+pub fn to_grayscale(rgb: &RgbImage) -> GrayImage {
+    let theme = detect(rgb);
+    if theme.is_plain() {
+        return image::DynamicImage::ImageRgb8(rgb.clone()).to_luma8();
+    }
+
+    let distance = |pixel: [u8; 3]| {
+        let squared: u32 = pixel.iter()
+            .zip(theme.background)
+            .map(|(&level, background)| (level as i32 - background as i32).pow(2) as u32)
+            .sum();
+        (squared as f32).sqrt() as u32
+    };
+
+    // Scale so that the most distant writing becomes black
+    let mut histogram = vec![0u64; 443];
+    for pixel in rgb.pixels() {
+        histogram[distance(pixel.0) as usize] += 1;
+    }
+    let ink_pixels = ((rgb.len() / 3) as f64 * INK_PERCENTILE) as u64;
+    let mut seen = 0;
+    let full_ink = histogram.iter()
+        .rposition(|&count| {
+            seen += count;
+            seen > ink_pixels
+        })
+        .unwrap_or(0) as u32;
+    let full_ink = full_ink.max(MIN_INK_DISTANCE) as f32;
+
+    GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+        let ink = (distance(rgb.get_pixel(x, y).0) as f32 / full_ink).min(1.0);
+        Luma([(255.0 * (1.0 - ink)).round() as u8])
+    })
+}
+
+fn luma([r, g, b]: [u8; 3]) -> f32 {
+    0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// Strokes in `ink` colours on `background`, one colour per row of strokes
+    fn screenshot(background: [u8; 3], inks: &[[u8; 3]]) -> RgbImage {
+        RgbImage::from_fn(120, 40 * inks.len() as u32, |x, y| {
+            let stroke = y % 40 >= 10 && y % 40 < 14 && (10..110).contains(&x);
+            Rgb(if stroke { inks[(y / 40) as usize] } else { background })
+        })
+    }
+
+    #[test]
+    fn test_detect_theme() {
+        let light = detect(&screenshot([250, 250, 248], &[[20, 20, 20]]));
+        assert_eq!(light, Theme { background: [250, 250, 248], dark: false, coloured: false });
+        assert!(light.is_plain());
+
+        let dark = detect(&screenshot([40, 42, 54], &[[248, 248, 242]]));
+        assert!(dark.dark && !dark.coloured);
+
+        let blue = detect(&screenshot([30, 90, 200], &[[250, 220, 40]]));
+        assert!(blue.coloured && !blue.is_plain());
+        assert_eq!(blue.background, [30, 90, 200]);
+    }
+
+    #[test]
+    fn test_plain_pages_convert_as_usual() {
+        let page = screenshot([250, 250, 248], &[[20, 20, 20], [200, 30, 30]]);
+        assert_eq!(to_grayscale(&page), image::DynamicImage::ImageRgb8(page.clone()).to_luma8());
+    }
+
+    #[test]
+    fn test_themes_become_dark_on_light() {
+        // Light text in a dark editor
+        let dark = to_grayscale(&screenshot([40, 42, 54], &[[248, 248, 242], [139, 233, 253]]));
+        assert_eq!(dark.get_pixel(5, 5)[0], 255);
+        assert_eq!(dark.get_pixel(50, 11)[0], 0);
+        assert!(dark.get_pixel(50, 51)[0] < 80, "{}", dark.get_pixel(50, 51)[0]);
+
+        // Red text on blue has almost the luma of its background, but not its colour
+        let page = screenshot([30, 90, 200], &[[220, 40, 40]]);
+        let plain = image::DynamicImage::ImageRgb8(page.clone()).to_luma8();
+        assert!((plain.get_pixel(50, 11)[0] as i32 - plain.get_pixel(5, 5)[0] as i32).abs() < 20);
+        let coloured = to_grayscale(&page);
+        assert_eq!((coloured.get_pixel(5, 5)[0], coloured.get_pixel(50, 11)[0]), (255, 0));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{recognition_backend, ConfigManager, MathSeekError, MathSeekResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Price of one model in USD, looked up by `provider/model`, `model` or `provider`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// Flat fee per request, for providers such as Mathpix that bill per image
+    pub per_request: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        self.per_request
+            + input_tokens as f64 * self.input_per_million / 1_000_000.0
+            + output_tokens as f64 * self.output_per_million / 1_000_000.0
+    }
+}
+
+/// Prices keyed by `provider/model`, `model` or `provider`
+pub type PriceTable = HashMap<String, ModelPrice>;
+
+/// List prices at the time of writing; users override them in `AppConfig::model_prices`
+pub fn default_price_table() -> PriceTable {
+    let per_token = |input_per_million, output_per_million| ModelPrice {
+        input_per_million,
+        output_per_million,
+        per_request: 0.0,
+    };
+
+    HashMap::from([
+        ("gpt-4o".to_string(), per_token(2.5, 10.0)),
+        ("gpt-4o-mini".to_string(), per_token(0.15, 0.6)),
+        ("gpt-4.1".to_string(), per_token(2.0, 8.0)),
+        ("gpt-4.1-mini".to_string(), per_token(0.4, 1.6)),
+        ("mathpix".to_string(), ModelPrice { per_request: 0.004, ..Default::default() }),
+        // Local models cost nothing per request
+        ("ollama".to_string(), ModelPrice::default()),
+        // The MathSeek service has no list price; override it to track what it costs you
+        (recognition_backend::DEFAULT_BACKEND.to_string(), ModelPrice::default()),
+    ])
+}
+
+/// Most specific price for a provider and model
+pub fn lookup_price<'a>(prices: &'a PriceTable, provider: &str, model: &str) -> Option<&'a ModelPrice> {
+    prices.get(&format!("{}/{}", provider, model))
+        .or_else(|| prices.get(model))
+        .or_else(|| prices.get(provider))
+}
+
+/// Tokens used by one provider call and what they are estimated to cost
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Estimated cost in USD; `None` when the model is missing from the price table
+    pub estimated_cost: Option<f64>,
+    /// Why the call could not be added to the usage ledger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger_error: Option<String>,
+}
+
+impl TokenUsage {
+    pub fn estimate(prices: &PriceTable, provider: &str, model: &str, input_tokens: u64, output_tokens: u64) -> Self {
+        Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            estimated_cost: lookup_price(prices, provider, model).map(|price| price.cost(input_tokens, output_tokens)),
+            ledger_error: None,
+        }
+    }
+}
+
+/// Spend of one provider within a month
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ProviderSpend {
+    pub provider: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Sum of the estimated costs in USD
+    pub estimated_cost: f64,
+    /// Requests whose model had no price and are missing from `estimated_cost`
+    pub unpriced_requests: u64,
+}
+
+impl ProviderSpend {
+    fn add(&mut self, usage: &TokenUsage) {
+        self.requests += 1;
+        self.input_tokens += usage.input_tokens;
+        self.output_tokens += usage.output_tokens;
+        match usage.estimated_cost {
+            Some(cost) => self.estimated_cost += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+}
+
+/// On-disk ledger format, totals per `YYYY-MM` month and provider
+#[derive(Debug, Serialize, Deserialize)]
+struct LedgerFile {
+    version: u32,
+    months: BTreeMap<String, Vec<ProviderSpend>>,
+}
+
+impl Default for LedgerFile {
+    fn default() -> Self {
+        Self { version: 1, months: BTreeMap::new() }
+    }
+}
+
+/// Serializes read-modify-write cycles of ledger files within the process
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Persistent monthly totals of provider usage
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    path: PathBuf,
+}
+
+impl UsageLedger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `usage.json` next to the configuration file
+    pub fn default_path() -> MathSeekResult<PathBuf> {
+        Ok(ConfigManager::config_directory()?.join("usage.json"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add one provider call to the month containing `timestamp`
+    pub fn record(&self, usage: &TokenUsage, timestamp: u64) -> MathSeekResult<()> {
+        let _guard = LEDGER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut ledger = self.load()?;
+
+        let providers = ledger.months.entry(month_key(timestamp)).or_default();
+        match providers.iter_mut().find(|spend| spend.provider == usage.provider) {
+            Some(spend) => spend.add(usage),
+            None => {
+                let mut spend = ProviderSpend { provider: usage.provider.clone(), ..Default::default() };
+                spend.add(usage);
+                providers.push(spend);
+                providers.sort_by(|a, b| a.provider.cmp(&b.provider));
+            }
+        }
+
+        self.save(&ledger)
+    }
+
+    /// Spend per provider in a `YYYY-MM` month
+    pub fn monthly_spend(&self, month: &str) -> MathSeekResult<Vec<ProviderSpend>> {
+        let _guard = LEDGER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(self.load()?.months.remove(month).unwrap_or_default())
+    }
+
+    /// Months with recorded usage, oldest first
+    pub fn months(&self) -> MathSeekResult<Vec<String>> {
+        let _guard = LEDGER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(self.load()?.months.into_keys().collect())
+    }
+
+    fn load(&self) -> MathSeekResult<LedgerFile> {
+        if !self.path.exists() {
+            return Ok(LedgerFile::default());
+        }
+
+        let content = std::fs::read_to_string(&self.path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| MathSeekError::SerializationError(format!("Invalid usage ledger {}: {}", self.path.display(), e)))
+    }
+
+    /// Replace the ledger through a temporary file, so that an interrupted
+    /// write leaves the previous ledger intact
+    fn save(&self, ledger: &LedgerFile) -> MathSeekResult<()> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(ledger)?)?;
+        std::fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+/// `YYYY-MM` of a Unix timestamp, in UTC
+pub fn month_key(timestamp: u64) -> String {
+    // Civil-from-days conversion for the proleptic Gregorian calendar
+    let days = timestamp / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!("{:04}-{:02}", year, month)
+}
+
+/// `YYYY-MM` of the current month
+pub fn current_month() -> String {
+    month_key(unix_now())
+}
+
+pub(crate) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_key() {
+        assert_eq!(month_key(0), "1970-01");
+        assert_eq!(month_key(951_782_400), "2000-02");
+        assert_eq!(month_key(1_709_251_199), "2024-02");
+        assert_eq!(month_key(1_709_251_200), "2024-03");
+        assert_eq!(month_key(1_735_689_599), "2024-12");
+        assert_eq!(month_key(1_735_689_600), "2025-01");
+    }
+
+    #[test]
+    fn test_price_lookup() {
+        let mut prices = default_price_table();
+        prices.insert("openai/gpt-4o".to_string(), ModelPrice { input_per_million: 5.0, ..Default::default() });
+
+        let usage = TokenUsage::estimate(&prices, "openai", "gpt-4o", 1_000_000, 1_000_000);
+        assert_eq!(usage.estimated_cost, Some(5.0));
+
+        let usage = TokenUsage::estimate(&prices, "vllm", "gpt-4o-mini", 1_000_000, 500_000);
+        assert_eq!(usage.estimated_cost, Some(0.15 + 0.3));
+
+        let usage = TokenUsage::estimate(&prices, "mathpix", "", 0, 0);
+        assert_eq!(usage.estimated_cost, Some(0.004));
+
+        assert_eq!(TokenUsage::estimate(&prices, "openai", "unknown-model", 10, 10).estimated_cost, None);
+    }
+
+    #[test]
+    fn test_ledger_totals_per_month_and_provider() {
+        let path = std::env::temp_dir().join(format!("mathseek-usage-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ledger = UsageLedger::new(&path);
+        let prices = default_price_table();
+
+        let october = 1_727_740_800; // 2024-10-01
+        ledger.record(&TokenUsage::estimate(&prices, "openai", "gpt-4o", 1_000, 200), october).unwrap();
+        ledger.record(&TokenUsage::estimate(&prices, "openai", "gpt-4o", 3_000, 800), october + 60).unwrap();
+        ledger.record(&TokenUsage::estimate(&prices, "openai", "custom", 500, 100), october + 120).unwrap();
+        ledger.record(&TokenUsage::estimate(&prices, "mathpix", "", 0, 0), october + 180).unwrap();
+        ledger.record(&TokenUsage::estimate(&prices, "openai", "gpt-4o", 1, 1), october - 1).unwrap();
+
+        let spend = ledger.monthly_spend("2024-10").unwrap();
+        assert_eq!(spend.len(), 2);
+        assert_eq!(spend[0].provider, "mathpix");
+        assert_eq!(spend[1].provider, "openai");
+        assert_eq!((spend[1].requests, spend[1].input_tokens, spend[1].output_tokens), (3, 4_500, 1_100));
+        assert_eq!(spend[1].unpriced_requests, 1);
+        assert!((spend[1].estimated_cost - (4_000.0 * 2.5 + 1_000.0 * 10.0) / 1_000_000.0).abs() < 1e-12);
+
+        assert_eq!(ledger.months().unwrap(), vec!["2024-09".to_string(), "2024-10".to_string()]);
+        assert!(ledger.monthly_spend("2023-01").unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_is_replaced_whole() {
+        let path = std::env::temp_dir().join(format!("mathseek-usage-replace-{}.json", std::process::id()));
+        let temporary = path.with_extension("json.tmp");
+        let ledger = UsageLedger::new(&path);
+        let usage = TokenUsage::estimate(&default_price_table(), "openai", "gpt-4o", 10, 10);
+        ledger.record(&usage, 1_727_740_800).unwrap();
+
+        // A write cut short by a crash leaves only the temporary file broken
+        std::fs::write(&temporary, "{\"months\": {\"2024-10\"").unwrap();
+        assert_eq!(ledger.monthly_spend("2024-10").unwrap()[0].requests, 1);
+
+        ledger.record(&usage, 1_727_740_800).unwrap();
+        assert_eq!(ledger.monthly_spend("2024-10").unwrap()[0].requests, 2);
+        assert!(!temporary.exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
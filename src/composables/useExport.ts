@@ -2,7 +2,7 @@ import { ref, computed } from 'vue'
 import { invoke } from '@tauri-apps/api/core'
 import { save } from '@tauri-apps/plugin-dialog'
 import type { FormulaResult, AppConfig } from '@/types'
-import { ExportFormat } from '@/types'
+import { ExportFormat, describeError } from '@/types'
 
 export interface ExportConfig {
   format: ExportFormat
@@ -82,7 +82,7 @@ export function useExport(formulaResult: FormulaResult, appConfig: AppConfig) {
         state.value.selectedFormat = defaultFormat as ExportFormat
       }
     } catch (error) {
-      exportError.value = error instanceof Error ? error.message : '加载导出格式失败'
+      exportError.value = describeError(error, '加载导出格式失败')
       throw error
     }
   }
@@ -112,7 +112,7 @@ export function useExport(formulaResult: FormulaResult, appConfig: AppConfig) {
 
       return result
     } catch (error) {
-      const errorMessage = error instanceof Error ? error.message : '导出失败'
+      const errorMessage = describeError(error, '导出失败')
       exportError.value = errorMessage
       throw new Error(errorMessage)
     } finally {
@@ -163,7 +163,7 @@ export function useExport(formulaResult: FormulaResult, appConfig: AppConfig) {
       
       return targetPath
     } catch (error) {
-      const errorMessage = error instanceof Error ? error.message : '导出到文件失败'
+      const errorMessage = describeError(error, '导出到文件失败')
       exportError.value = errorMessage
       throw new Error(errorMessage)
     } finally {
@@ -174,9 +174,14 @@ export function useExport(formulaResult: FormulaResult, appConfig: AppConfig) {
   async function exportToClipboard(config: ExportConfig): Promise<void> {
     try {
       const result = await exportToString(config)
-      await navigator.clipboard.writeText(result.content)
+      // Plain text plus an HTML flavor with MathML, so Word and browsers render the math
+      await invoke('copy_export_result', {
+        result: formulaResult,
+        exportResult: result,
+        appConfig
+      })
     } catch (error) {
-      const errorMessage = error instanceof Error ? error.message : '复制到剪贴板失败'
+      const errorMessage = describeError(error, '复制到剪贴板失败')
       exportError.value = errorMessage
       throw new Error(errorMessage)
     }
@@ -1,13 +1,32 @@
 import { invoke } from '@tauri-apps/api/core'
-import type { AppConfig, SystemStatus, ImageLayout } from '@/types'
+import type {
+  AppConfig,
+  SystemStatus,
+  ImageLayout,
+  ScreenshotOptions,
+  CapturedScreenshot,
+  MonitorInfo,
+  PreprocessingConfig,
+  PreprocessingProfile,
+  PreprocessingStep,
+  MathSeekError
+} from '@/types'
+import { toMathSeekError } from '@/types'
 
 export function useTauri() {
+  // Log a failed command and hand back its error in structured form
+  const fail = (action: string, error: unknown): MathSeekError => {
+    const err = toMathSeekError(error)
+    console.error(`Failed to ${action}:`, err.type, err.message)
+    return err
+  }
+
   // Get application version
   const getAppVersion = async (): Promise<string> => {
     try {
       return await invoke('get_app_version')
     } catch (error) {
-      console.error('Failed to get app version:', error)
+      fail('get app version', error)
       return '0.1.0'
     }
   }
@@ -17,7 +36,7 @@ export function useTauri() {
     try {
       return await invoke('check_system_status')
     } catch (error) {
-      console.error('Failed to check system status:', error)
+      fail('check system status', error)
       return {
         clipboard_available: false,
         screenshot_available: false,
@@ -32,8 +51,7 @@ export function useTauri() {
     try {
       await invoke('save_config', { config })
     } catch (error) {
-      console.error('Failed to save config:', error)
-      throw error
+      throw fail('save config', error)
     }
   }
 
@@ -42,18 +60,25 @@ export function useTauri() {
     try {
       return await invoke('load_config')
     } catch (error) {
-      console.error('Failed to load config:', error)
+      fail('load config', error)
       return null
     }
   }
 
   // Image processing functions
-  const captureScreenshot = async (): Promise<string> => {
+  const captureScreenshot = async (options?: ScreenshotOptions): Promise<CapturedScreenshot> => {
+    try {
+      return await invoke('capture_screenshot', { options })
+    } catch (error) {
+      throw fail('capture screenshot', error)
+    }
+  }
+
+  const listMonitors = async (): Promise<MonitorInfo[]> => {
     try {
-      return await invoke('capture_screenshot')
+      return await invoke('list_monitors')
     } catch (error) {
-      console.error('Failed to capture screenshot:', error)
-      throw error
+      throw fail('list monitors', error)
     }
   }
 
@@ -61,8 +86,7 @@ export function useTauri() {
     try {
       return await invoke('get_clipboard_image')
     } catch (error) {
-      console.error('Failed to get clipboard image:', error)
-      throw error
+      throw fail('get clipboard image', error)
     }
   }
 
@@ -70,26 +94,45 @@ export function useTauri() {
     try {
       return await invoke('validate_image_data', { base64Data })
     } catch (error) {
-      console.error('Failed to validate image data:', error)
-      throw error
+      throw fail('validate image data', error)
+    }
+  }
+
+  const preprocessImage = async (
+    base64Data: string,
+    config?: Partial<PreprocessingConfig>,
+    profile?: PreprocessingProfile
+  ): Promise<string> => {
+    try {
+      return await invoke('preprocess_image', { base64Data, config, profile })
+    } catch (error) {
+      throw fail('preprocess image', error)
     }
   }
 
-  const preprocessImage = async (base64Data: string): Promise<string> => {
+  // Image after every preprocessing stage, for debugging the pipeline
+  const preprocessImageStages = async (
+    base64Data: string,
+    config?: Partial<PreprocessingConfig>,
+    profile?: PreprocessingProfile
+  ): Promise<PreprocessingStep[]> => {
     try {
-      return await invoke('preprocess_image', { base64Data })
+      return await invoke('preprocess_image_stages', { base64Data, config, profile })
     } catch (error) {
-      console.error('Failed to preprocess image:', error)
-      throw error
+      throw fail('preprocess image stages', error)
     }
   }
 
-  const getImageInfo = async (base64Data: string): Promise<any> => {
+  // Rotation in the info is what preprocessing with these stages applies
+  const getImageInfo = async (
+    base64Data: string,
+    config?: Partial<PreprocessingConfig>,
+    profile?: PreprocessingProfile
+  ): Promise<any> => {
     try {
-      return await invoke('get_image_info', { base64Data })
+      return await invoke('get_image_info', { base64Data, config, profile })
     } catch (error) {
-      console.error('Failed to get image info:', error)
-      throw error
+      throw fail('get image info', error)
     }
   }
 
@@ -97,8 +140,7 @@ export function useTauri() {
     try {
       return await invoke('detect_input_type', { base64Data })
     } catch (error) {
-      console.error('Failed to detect input type:', error)
-      throw error
+      throw fail('detect input type', error)
     }
   }
 
@@ -106,8 +148,7 @@ export function useTauri() {
     try {
       return await invoke('analyze_image_layout', { base64Data })
     } catch (error) {
-      console.error('Failed to analyze image layout:', error)
-      throw error
+      throw fail('analyze image layout', error)
     }
   }
 
@@ -115,8 +156,7 @@ export function useTauri() {
     try {
       return await invoke('get_detection_confidence', { base64Data })
     } catch (error) {
-      console.error('Failed to get detection confidence:', error)
-      throw error
+      throw fail('get detection confidence', error)
     }
   }
 
@@ -126,9 +166,11 @@ export function useTauri() {
     saveConfig,
     loadConfig,
     captureScreenshot,
+    listMonitors,
     getClipboardImage,
     validateImageData,
     preprocessImage,
+    preprocessImageStages,
     getImageInfo,
     detectInputType,
     analyzeImageLayout,
@@ -19,6 +19,10 @@ import {
   validateDocumentSection,
   formatInlineFormula,
   formatBlockFormula,
+  MathSeekErrorType,
+  isMathSeekError,
+  toMathSeekError,
+  describeError,
 
   type ResultContent
 } from './index'
@@ -209,4 +213,33 @@ describe('Data Types', () => {
       expect(formatBlockFormula(latex, BlockFormat.Brackets)).toBe('\\[E = mc^2\\]')
     })
   })
+
+  describe('Command Errors', () => {
+    it('should recognize serialized command errors', () => {
+      const error = { type: 'ConfigError', message: '配置错误: API key cannot be empty', retryable: false }
+
+      expect(isMathSeekError(error)).toBe(true)
+      expect(isMathSeekError('plain string')).toBe(false)
+      expect(toMathSeekError(error)).toBe(error)
+      expect(toMathSeekError(new Error('boom'))).toEqual({
+        type: MathSeekErrorType.Unknown,
+        message: 'boom',
+        retryable: false
+      })
+    })
+
+    it('should describe errors by kind', () => {
+      expect(describeError({ type: 'ConfigError', message: '配置错误', retryable: false }, '失败'))
+        .toBe('配置错误，请检查设置')
+      expect(describeError({ type: 'HttpError', message: 'HTTP错误 401', retryable: false, status: 401 }, '失败'))
+        .toBe('HTTP错误 401，请检查 API 密钥')
+      expect(describeError({ type: 'HttpError', message: 'HTTP错误 429', retryable: true, status: 429, retry_after_secs: 5 }, '失败'))
+        .toBe('HTTP错误 429，请在 5 秒后重试')
+      expect(describeError({ type: 'Timeout', message: '请求超时', retryable: true }, '失败'))
+        .toBe('请求超时，请稍后重试')
+      expect(describeError({ type: 'Cancelled', message: '识别已取消', retryable: false }, '失败'))
+        .toBe('识别已取消')
+      expect(describeError(undefined, '失败')).toBe('失败')
+    })
+  })
 })
\ No newline at end of file
@@ -38,11 +38,47 @@ export interface MarkdownFormulaFormat {
 }
 
 export interface AppConfig {
+  backend: string
   apiEndpoint: string
   apiKey: string
+  appId: string
+  model: string
+  // Client-side limits for the provider; 0 means unlimited
+  requestsPerMinute: number
+  maxConcurrentRequests: number
+  // Prices keyed by 'provider/model', 'model' or 'provider'; the built-in table is used when unset
+  modelPrices?: Record<string, ModelPrice>
+  // Tried in order when the primary provider is down or below the confidence threshold
+  fallbackProviders: ProviderConfig[]
+  // Upload limit for images in bytes of base64; 0 uses the provider's own limit
+  maxPayloadBytes: number
+  // Proxy and TLS settings shared by all providers
+  network: NetworkConfig
   defaultExportFormat: Record<InputType, ExportFormat>
   renderEngine: RenderEngine
   markdownFormulaFormat: MarkdownFormulaFormat
+  // System prompts for chat model backends; the built-in templates are used when unset
+  promptTemplates?: PromptTemplates
+  // Stages run before recognition; a profile passed to a command replaces them
+  preprocessing?: PreprocessingConfig
+}
+
+// Certificate and key settings are paths to PEM files; the key must be PKCS#8.
+// noProxy only applies to proxyUrl; environment proxies follow NO_PROXY.
+export interface NetworkConfig {
+  proxyUrl: string
+  noProxy: string[]
+  caCertificates: string[]
+  clientCertificate: string
+  clientKey: string
+}
+
+// Templates may use {{language}}, {{inline_delimiters}}, {{block_delimiters}} and {{macros}}
+export interface PromptTemplates {
+  singleFormula: string
+  document: string
+  language: string
+  preferredMacros: string[]
 }
 
 export interface FormulaResult {
@@ -51,8 +87,120 @@ export interface FormulaResult {
   timestamp: number
   inputType: InputType
   content: ResultContent
+  tokenUsage?: TokenUsage
+  provenance?: RecognitionProvenance
+  alternatives?: RecognitionAlternative[]
+  payload?: ImagePayload
+}
+
+export type PayloadFormat = 'png' | 'jpeg' | 'webp'
+
+// The image sent to the provider after fitting it to the upload limit
+export interface ImagePayload {
+  format: PayloadFormat
+  quality?: number
+  width: number
+  height: number
+  originalBytes: number
+  bytes: number
+  encodedBytes: number
+}
+
+export interface ProviderConfig {
+  backend: string
+  apiEndpoint: string
+  apiKey: string
+  appId: string
+  model: string
+  maxPayloadBytes: number
+}
+
+export type SkipReason = 'unavailable' | 'timeout' | 'circuit_open' | 'low_confidence' | 'failed'
+
+export interface SkippedProvider {
+  provider: string
+  endpoint: string
+  reason: SkipReason
+  message: string
+}
+
+export interface RecognitionProvenance {
+  provider: string
+  endpoint: string
+  skipped: SkippedProvider[]
+}
+
+export interface EnsembleConfig {
+  samplesPerProvider: number
+  sampleTemperature: number
+}
+
+export interface RecognitionAlternative {
+  latex: string
+  votes: number
+  agreement: number
+  providers: string[]
+}
+
+// Prices in USD
+export interface ModelPrice {
+  inputPerMillion: number
+  outputPerMillion: number
+  perRequest: number
+}
+
+export interface TokenUsage {
+  provider: string
+  model: string
+  inputTokens: number
+  outputTokens: number
+  // Null when the model has no price
+  estimatedCost: number | null
+  // Set when the call could not be added to the usage ledger
+  ledgerError?: string
+}
+
+// Returned by the get_monthly_spend command
+export interface ProviderSpend {
+  provider: string
+  requests: number
+  inputTokens: number
+  outputTokens: number
+  estimatedCost: number
+  unpricedRequests: number
+}
+
+// Returned by the get_rate_limit_status command
+export interface RateLimitStatus {
+  endpoint: string
+  requests_per_minute: number
+  max_concurrent_requests: number
+  queued: number
+  in_flight: number
+}
+
+// Events emitted by the recognize_content_streaming command
+export const RECOGNITION_PARTIAL_EVENT = 'recognition://partial'
+export const RECOGNITION_FINAL_EVENT = 'recognition://final'
+
+// Emitted by every recognition command with the ID to pass to cancel_recognition.
+// Pass your own jobId to a command to tell its events from those of other jobs.
+export const RECOGNITION_STARTED_EVENT = 'recognition://started'
+
+export interface RecognitionStarted {
+  jobId: string
 }
 
+export interface RecognitionPartial {
+  jobId: string
+  inputType: InputType
+  // Full LaTeX or Markdown received so far
+  text: string
+}
+
+// Payload of RECOGNITION_FINAL_EVENT
+export type RecognitionFinal = FormulaResult & { jobId: string }
+
 export type ResultContent = 
   | { SingleFormula: string }
   | { Document: DocumentContent }
@@ -79,6 +227,16 @@ export interface AnalysisResult {
   description: string
   usage: string
   examples: string[]
+  variables: FormulaVariable[]
+  domain?: string
+  relatedFormulas: string[]
+  tokenUsage?: TokenUsage
+}
+
+export interface FormulaVariable {
+  symbol: string
+  description: string
+  unit?: string
 }
 
 export interface ImageLayout {
@@ -86,6 +244,8 @@ export interface ImageLayout {
   has_text_content: boolean
   formula_regions: Region[]
   text_regions: Region[]
+  // Text, inline math and display math in reading order
+  regions: LayoutRegion[]
 }
 
 export interface Region {
@@ -95,6 +255,82 @@ export interface Region {
   height: number
 }
 
+export type RegionKind = 'text' | 'inline_math' | 'display_math'
+
+export interface LayoutRegion extends Region {
+  kind: RegionKind
+  line: number
+}
+
+// Stages run before recognition; despeckle needs binarization
+export interface PreprocessingConfig {
+  max_size: number
+  polarity: boolean
+  perspective: boolean
+  illumination: boolean
+  deskew: boolean
+  contrast_stretch: boolean
+  median_filter: boolean
+  binarization: 'none' | 'otsu' | 'sauvola'
+  sauvola_window: number
+  sauvola_k: number
+  despeckle: boolean
+  speckle_size: number
+  stroke_repair: boolean
+}
+
+// Dominant background; dark and coloured themes are normalized to dark on light
+export interface Theme {
+  background: [number, number, number]
+  dark: boolean
+  coloured: boolean
+}
+
+// Preset stages; standard keeps gray levels, scan binarizes faint input,
+// photo also flattens and evens out photos of pages and boards
+export type PreprocessingProfile = 'standard' | 'scan' | 'photo'
+
+export type PreprocessingStage =
+  | 'grayscale'
+  | 'perspective'
+  | 'illumination'
+  | 'deskew'
+  | 'contrast_stretch'
+  | 'median_filter'
+  | 'binarization'
+  | 'despeckle'
+  | 'stroke_repair'
+
+// Image after a stage, as a data URL
+export interface PreprocessingStep {
+  stage: PreprocessingStage
+  image: string
+}
+
+// Regions are relative to the monitor; interactive lets the user drag one out
+export interface ScreenshotOptions {
+  monitor?: string
+  region?: Region
+  interactive?: boolean
+}
+
+// Returned by the capture_screenshot command; leftoverFile names a copy the
+// desktop portal saved that could not be removed
+export interface CapturedScreenshot {
+  image: string
+  leftoverFile: string | null
+}
+
+// Returned by the list_monitors command, primary first; empty on Wayland
+export interface MonitorInfo {
+  name: string
+  x: number
+  y: number
+  width: number
+  height: number
+  primary: boolean
+}
+
 export interface SystemStatus {
   clipboard_available: boolean
   screenshot_available: boolean
@@ -123,8 +359,22 @@ export function isResultContentDocument(content: ResultContent): content is { Do
 
 export function createDefaultAppConfig(): AppConfig {
   return {
+    backend: 'mathseek',
     apiEndpoint: '',
     apiKey: '',
+    appId: '',
+    model: '',
+    requestsPerMinute: 0,
+    maxConcurrentRequests: 0,
+    fallbackProviders: [],
+    maxPayloadBytes: 0,
+    network: {
+      proxyUrl: '',
+      noProxy: [],
+      caCertificates: [],
+      clientCertificate: '',
+      clientKey: ''
+    },
     defaultExportFormat: {
       [InputType.SingleFormula]: ExportFormat.LaTeX,
       [InputType.Document]: ExportFormat.Markdown
@@ -206,10 +456,15 @@ export interface InputProps {
 // Error handling types
 export enum MathSeekErrorType {
   ApiError = 'ApiError',
+  HttpError = 'HttpError',
   ImageError = 'ImageError',
   ConfigError = 'ConfigError',
   ExportError = 'ExportError',
   NetworkError = 'NetworkError',
+  Timeout = 'Timeout',
+  CircuitOpen = 'CircuitOpen',
+  Cancelled = 'Cancelled',
+  DuplicateJob = 'DuplicateJob',
   IoError = 'IoError',
   SerializationError = 'SerializationError',
   Unknown = 'Unknown'
@@ -218,6 +473,55 @@ export enum MathSeekErrorType {
 export interface MathSeekError {
   type: MathSeekErrorType
   message: string
+  retryable: boolean
+  // Only present for HttpError
+  status?: number
+  code?: string | null
+  // Present for HttpError and CircuitOpen
+  retry_after_secs?: number | null
+  // Only present for CircuitOpen
+  endpoint?: string
+}
+
+export function isMathSeekError(error: unknown): error is MathSeekError {
+  return typeof error === 'object' && error !== null
+    && typeof (error as MathSeekError).type === 'string'
+    && typeof (error as MathSeekError).message === 'string'
+}
+
+// Commands reject with a serialized MathSeekError; anything else becomes Unknown
+export function toMathSeekError(error: unknown): MathSeekError {
+  if (isMathSeekError(error)) {
+    return error
+  }
+  const message = error instanceof Error ? error.message : String(error)
+  return { type: MathSeekErrorType.Unknown, message, retryable: false }
+}
+
+// Message to show for a failed command, with a hint for errors the user can act on
+export function describeError(error: unknown, fallback: string): string {
+  if (!isMathSeekError(error)) {
+    return error instanceof Error ? error.message : fallback
+  }
+  switch (error.type) {
+    case MathSeekErrorType.Cancelled:
+      return error.message
+    case MathSeekErrorType.ConfigError:
+      return `${error.message}，请检查设置`
+    case MathSeekErrorType.HttpError:
+      if (error.status === 401 || error.status === 403) {
+        return `${error.message}，请检查 API 密钥`
+      }
+      break
+    case MathSeekErrorType.CircuitOpen:
+      return error.message
+  }
+  if (error.retryable) {
+    return error.retry_after_secs
+      ? `${error.message}，请在 ${error.retry_after_secs} 秒后重试`
+      : `${error.message}，请稍后重试`
+  }
+  return error.message || fallback
 }
 
 // Validation functions